@@ -3,7 +3,7 @@
 set -e
 
 # Build and push image with newest code
-docker build -t gcr.io/gamesite-369621/backend:latest .
+docker build --build-arg GIT_COMMIT="$(git rev-parse --short HEAD)" -t gcr.io/gamesite-369621/backend:latest .
 docker push gcr.io/gamesite-369621/backend:latest
 
 # Restart the backend to pull the new latest image
@@ -0,0 +1,126 @@
+use axum::{
+    async_trait,
+    extract::{Extension, FromRequest, Path, RequestParts},
+    headers::{authorization::Bearer, Authorization},
+    http::{header::WWW_AUTHENTICATE, StatusCode},
+    middleware::from_extractor,
+    response::{IntoResponse, Response},
+    routing::{delete, get, post},
+    Json, Router, TypedHeader,
+};
+use serde_json::json;
+use std::sync::Arc;
+use tracing::info;
+
+use crate::{
+    config::Config,
+    lobby_manager::{LobbyError, LobbyManager},
+    models::admin::Announcement,
+};
+
+/// Operator endpoints, nested under `/admin`. Every route needs the
+/// configured bearer token.
+pub fn routes() -> Router {
+    Router::new()
+        .route("/lobbies", get(list_lobbies))
+        .route("/lobbies/:id", get(dump_lobby).delete(close_lobby))
+        .route("/lobbies/:id/users/:user_id", delete(kick_user))
+        .route("/announcements", post(announce))
+        .route_layer(from_extractor::<AdminAuth>())
+}
+
+/// Proof the request carried the admin token
+struct AdminAuth;
+
+#[async_trait]
+impl<B: Send> FromRequest<B> for AdminAuth {
+    type Rejection = Response;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        let token = req
+            .extensions()
+            .get::<Arc<Config>>()
+            .and_then(|c| c.admin.token.clone());
+        // Hide the admin API entirely when it isn't configured
+        let Some(token) = token else {
+            return Err(StatusCode::NOT_FOUND.into_response());
+        };
+
+        match TypedHeader::<Authorization<Bearer>>::from_request(req).await {
+            Ok(TypedHeader(auth)) if constant_time_eq(auth.token(), &token) => Ok(AdminAuth),
+            _ => Err((
+                StatusCode::UNAUTHORIZED,
+                [(WWW_AUTHENTICATE, "Bearer")],
+                "Invalid admin token",
+            )
+                .into_response()),
+        }
+    }
+}
+
+/// Compare tokens without leaking how much of a guess was right
+fn constant_time_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0, |acc, (x, y)| acc | (x ^ y))
+            == 0
+}
+
+impl IntoResponse for LobbyError {
+    fn into_response(self) -> Response {
+        let code = match self {
+            LobbyError::LobbyDoesNotExist | LobbyError::UserDoesNotExist => StatusCode::NOT_FOUND,
+            LobbyError::UserAlreadyConnected => StatusCode::CONFLICT,
+            LobbyError::LobbyUnresponsive => StatusCode::GATEWAY_TIMEOUT,
+        };
+
+        (code, Json(json!({ "error": format!("{:?}", self) }))).into_response()
+    }
+}
+
+async fn list_lobbies(Extension(lm): Extension<Arc<LobbyManager>>) -> impl IntoResponse {
+    Json(lm.summaries().await)
+}
+
+async fn dump_lobby(
+    Path(id): Path<String>,
+    Extension(lm): Extension<Arc<LobbyManager>>,
+) -> Result<impl IntoResponse, LobbyError> {
+    Ok(Json(lm.dump(&id).await?))
+}
+
+async fn close_lobby(
+    Path(id): Path<String>,
+    Extension(lm): Extension<Arc<LobbyManager>>,
+) -> Result<StatusCode, LobbyError> {
+    lm.close(&id)?;
+    info!(lobby_id = %id, "Admin closed lobby");
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn kick_user(
+    Path((id, user_id)): Path<(String, String)>,
+    Extension(lm): Extension<Arc<LobbyManager>>,
+) -> Result<StatusCode, LobbyError> {
+    lm.kick(&id, &user_id)?;
+    info!(lobby_id = %id, %user_id, "Admin kicked user");
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn announce(
+    Extension(lm): Extension<Arc<LobbyManager>>,
+    Json(announcement): Json<Announcement>,
+) -> Response {
+    let msg = announcement.msg.trim();
+    if msg.is_empty() {
+        return (StatusCode::BAD_REQUEST, "Announcement is empty").into_response();
+    }
+
+    let lobbies = lm.announce(msg);
+    info!(lobbies, "Admin sent announcement");
+
+    Json(json!({ "lobbies": lobbies })).into_response()
+}
@@ -0,0 +1,467 @@
+use axum::http::Method;
+use clap::{Parser, ValueEnum};
+use eyre::{eyre, WrapErr};
+use serde::{Deserialize, Deserializer};
+use std::{net::SocketAddr, path::PathBuf, time::Duration};
+use tower_http::cors::{AllowOrigin, Any, CorsLayer};
+
+use crate::games::just_one::DuplicateRules;
+
+/// Config file read when `--config` isn't given, if it exists
+const DEFAULT_CONFIG_PATH: &str = "gamesite.toml";
+
+/// Command line flags. Every flag can also be set through the listed
+/// environment variable, and both take precedence over the config file.
+#[derive(Parser, Debug)]
+#[command(version, about = "Game site backend")]
+pub struct Args {
+    /// Path to a TOML config file
+    #[arg(long, env = "GAMESITE_CONFIG")]
+    pub config: Option<PathBuf>,
+    /// Address to listen on
+    #[arg(long, env = "GAMESITE_BIND")]
+    pub bind: Option<SocketAddr>,
+    /// Origins allowed by CORS, `*` allows any origin
+    #[arg(long, env = "GAMESITE_ALLOWED_ORIGINS", value_delimiter = ',')]
+    pub allowed_origins: Option<Vec<String>>,
+    /// HTTP methods allowed by CORS
+    #[arg(long, env = "GAMESITE_ALLOWED_METHODS", value_delimiter = ',')]
+    pub allowed_methods: Option<Vec<String>>,
+    /// Capacity of each user's outbound message queue
+    #[arg(long, env = "GAMESITE_USER_CHANNEL_CAPACITY")]
+    pub user_channel_capacity: Option<usize>,
+    /// Consecutive messages a slow user may miss before being disconnected
+    #[arg(long, env = "GAMESITE_MAX_LAG_STRIKES")]
+    pub max_lag_strikes: Option<u32>,
+    /// Messages per second each socket may send on average
+    #[arg(long, env = "GAMESITE_MESSAGES_PER_SEC")]
+    pub messages_per_sec: Option<f64>,
+    /// Largest message a client may send, in bytes
+    #[arg(long, env = "GAMESITE_MAX_MESSAGE_BYTES")]
+    pub max_message_bytes: Option<usize>,
+    /// Seconds an empty lobby is kept around for users to reconnect
+    #[arg(long, env = "GAMESITE_GRACE_PERIOD_SECS")]
+    pub grace_period_secs: Option<u64>,
+    /// Seconds without a message before a lobby is reaped
+    #[arg(long, env = "GAMESITE_IDLE_TTL_SECS")]
+    pub idle_ttl_secs: Option<u64>,
+    /// Seconds after creation before a lobby is reaped
+    #[arg(long, env = "GAMESITE_MAX_LIFETIME_SECS")]
+    pub max_lifetime_secs: Option<u64>,
+    /// Path to the Just One word list
+    #[arg(long, env = "GAMESITE_NOUNS_PATH")]
+    pub nouns_path: Option<PathBuf>,
+    /// Seconds to wait for sockets to close when shutting down
+    #[arg(long, env = "GAMESITE_SHUTDOWN_DEADLINE_SECS")]
+    pub shutdown_deadline_secs: Option<u64>,
+    /// Directory game states are saved to when shutting down
+    #[arg(long, env = "GAMESITE_STATE_DIR")]
+    pub state_dir: Option<PathBuf>,
+    /// Log filter directives, e.g. `info` or `game_site_be=debug`
+    #[arg(long, env = "GAMESITE_LOG_LEVEL")]
+    pub log_level: Option<String>,
+    /// Log output format
+    #[arg(long, env = "GAMESITE_LOG_FORMAT")]
+    pub log_format: Option<LogFormat>,
+    /// Bearer token for the admin API, which is disabled when unset
+    #[arg(long, env = "GAMESITE_ADMIN_TOKEN", hide_env_values = true)]
+    pub admin_token: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub lobby: LobbyConfig,
+    pub limits: LimitsConfig,
+    pub games: GamesConfig,
+    pub shutdown: ShutdownConfig,
+    pub log: LogConfig,
+    pub admin: AdminConfig,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub bind: SocketAddr,
+    pub allowed_origins: Vec<String>,
+    pub allowed_methods: Vec<String>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            bind: SocketAddr::from(([0, 0, 0, 0], 9000)),
+            allowed_origins: vec!["*".to_owned()],
+            allowed_methods: vec!["GET".to_owned()],
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct LobbyConfig {
+    /// Capacity of each lobby's inbound message queue
+    pub channel_capacity: usize,
+    /// Capacity of each user's outbound message queue
+    pub user_channel_capacity: usize,
+    /// Consecutive messages a slow user may miss before being disconnected
+    pub max_lag_strikes: u32,
+    /// How long a lobby with nobody connected is kept alive so that users
+    /// with a flaky connection can come back to their game
+    #[serde(rename = "grace_period_secs", deserialize_with = "secs")]
+    pub grace_period: Duration,
+    pub reaper: ReaperConfig,
+}
+
+impl Default for LobbyConfig {
+    fn default() -> Self {
+        LobbyConfig {
+            channel_capacity: 100,
+            user_channel_capacity: 100,
+            max_lag_strikes: 20,
+            grace_period: Duration::from_secs(60),
+            reaper: ReaperConfig::default(),
+        }
+    }
+}
+
+/// Controls when idle or long running lobbies are reclaimed
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct ReaperConfig {
+    /// How often lobbies are checked
+    #[serde(rename = "interval_secs", deserialize_with = "secs")]
+    pub interval: Duration,
+    /// Close lobbies that haven't received a message for this long
+    #[serde(rename = "idle_ttl_secs", deserialize_with = "secs")]
+    pub idle_ttl: Duration,
+    /// Close lobbies this long after creation, no matter how active they are
+    #[serde(rename = "max_lifetime_secs", deserialize_with = "secs")]
+    pub max_lifetime: Duration,
+    /// How long before closing a lobby its members are warned
+    #[serde(rename = "warning_secs", deserialize_with = "secs")]
+    pub warning: Duration,
+}
+
+impl Default for ReaperConfig {
+    fn default() -> Self {
+        ReaperConfig {
+            interval: Duration::from_secs(30),
+            idle_ttl: Duration::from_secs(60 * 60),
+            max_lifetime: Duration::from_secs(24 * 60 * 60),
+            warning: Duration::from_secs(5 * 60),
+        }
+    }
+}
+
+/// Per socket limits on what clients can send
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct LimitsConfig {
+    /// Rate the token bucket refills at
+    pub messages_per_sec: f64,
+    /// Size of the token bucket, the most messages that can be sent at once
+    pub burst: u32,
+    /// Refill rate of the separate bucket for binary frames, e.g. drawing
+    pub binary_per_sec: f64,
+    pub binary_burst: u32,
+    /// Largest text message accepted, in bytes
+    pub max_message_bytes: usize,
+    /// Largest binary frame accepted, e.g. a finished drawing, in bytes
+    pub max_binary_bytes: usize,
+    /// Longest user id, hint, guess or other string field, in characters
+    pub max_field_chars: usize,
+    /// Violations within the window before the client is disconnected
+    pub max_violations: u32,
+    #[serde(rename = "violation_window_secs", deserialize_with = "secs")]
+    pub violation_window: Duration,
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        LimitsConfig {
+            messages_per_sec: 5.0,
+            burst: 20,
+            binary_per_sec: 30.0,
+            binary_burst: 60,
+            max_message_bytes: 4096,
+            max_binary_bytes: 512 * 1024,
+            max_field_chars: 64,
+            max_violations: 10,
+            violation_window: Duration::from_secs(60),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct GamesConfig {
+    /// Word list used by Just One
+    pub nouns_path: PathBuf,
+    /// Category list used by Categories
+    pub categories_path: PathBuf,
+    /// Location pack used by Spyfall
+    pub locations_path: PathBuf,
+    /// Spectrum cards used by Wavelength
+    pub spectrums_path: PathBuf,
+    /// Question pack used by Trivia
+    pub questions_path: PathBuf,
+    pub just_one: JustOneConfig,
+}
+
+impl Default for GamesConfig {
+    fn default() -> Self {
+        GamesConfig {
+            nouns_path: PathBuf::from("assets/nouns.json"),
+            categories_path: PathBuf::from("assets/categories.json"),
+            locations_path: PathBuf::from("assets/locations.json"),
+            spectrums_path: PathBuf::from("assets/spectrums.json"),
+            questions_path: PathBuf::from("assets/questions.json"),
+            just_one: JustOneConfig::default(),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum HintCheckKind {
+    SameStem,
+    WordForm,
+    EditDistance,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct JustOneConfig {
+    /// Checks that flag hints too close to the word for the table to review
+    pub hint_checks: Vec<HintCheckKind>,
+    /// Most edits between a hint and the word for it to be flagged
+    pub max_edit_distance: usize,
+    /// Default duplicate rules for new lobbies, each lobby can change them
+    pub duplicates: DuplicateRules,
+}
+
+impl Default for JustOneConfig {
+    fn default() -> Self {
+        JustOneConfig {
+            hint_checks: vec![
+                HintCheckKind::SameStem,
+                HintCheckKind::WordForm,
+                HintCheckKind::EditDistance,
+            ],
+            max_edit_distance: 2,
+            duplicates: DuplicateRules::default(),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct ShutdownConfig {
+    /// How long readiness reports failure before lobbies are told to close,
+    /// giving load balancers time to stop routing new players here
+    #[serde(rename = "readiness_delay_secs", deserialize_with = "secs")]
+    pub readiness_delay: Duration,
+    /// How long to wait for sockets to close before exiting anyway
+    #[serde(rename = "deadline_secs", deserialize_with = "secs")]
+    pub deadline: Duration,
+    /// Hint sent to clients for when to try reconnecting
+    #[serde(rename = "reconnect_after_secs", deserialize_with = "secs")]
+    pub reconnect_after: Duration,
+    /// Directory running games are saved to, nothing is saved when unset
+    pub state_dir: Option<PathBuf>,
+}
+
+impl Default for ShutdownConfig {
+    fn default() -> Self {
+        ShutdownConfig {
+            readiness_delay: Duration::from_secs(5),
+            deadline: Duration::from_secs(20),
+            reconnect_after: Duration::from_secs(15),
+            state_dir: None,
+        }
+    }
+}
+
+#[derive(Deserialize, ValueEnum, Debug, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum LogFormat {
+    #[default]
+    Pretty,
+    Json,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogConfig {
+    /// Filter directives in `RUST_LOG` syntax
+    pub level: String,
+    pub format: LogFormat,
+    /// Keep words, hints and guesses out of the logs
+    pub redact_secrets: bool,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        LogConfig {
+            level: "info".to_owned(),
+            format: LogFormat::Pretty,
+            redact_secrets: true,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct AdminConfig {
+    /// Bearer token required by `/admin` routes. They respond 404 when unset.
+    pub token: Option<String>,
+}
+
+fn secs<'de, D: Deserializer<'de>>(d: D) -> Result<Duration, D::Error> {
+    u64::deserialize(d).map(Duration::from_secs)
+}
+
+impl Config {
+    /// Build the config from the file named in `args` (or the default file
+    /// if present) and then apply any flag or environment overrides.
+    pub fn load(args: Args) -> eyre::Result<Config> {
+        let path = args.config.clone().or_else(|| {
+            let default = PathBuf::from(DEFAULT_CONFIG_PATH);
+            default.exists().then_some(default)
+        });
+
+        let mut config = match path {
+            Some(path) => {
+                let txt = std::fs::read_to_string(&path)
+                    .wrap_err(format!("Unable to read config file {}", path.display()))?;
+                toml::from_str(&txt)
+                    .wrap_err(format!("Unable to parse config file {}", path.display()))?
+            }
+            None => Config::default(),
+        };
+
+        config.apply(args);
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    fn apply(&mut self, args: Args) {
+        if let Some(bind) = args.bind {
+            self.server.bind = bind;
+        }
+        if let Some(origins) = args.allowed_origins {
+            self.server.allowed_origins = origins;
+        }
+        if let Some(methods) = args.allowed_methods {
+            self.server.allowed_methods = methods;
+        }
+        if let Some(cap) = args.user_channel_capacity {
+            self.lobby.user_channel_capacity = cap;
+        }
+        if let Some(strikes) = args.max_lag_strikes {
+            self.lobby.max_lag_strikes = strikes;
+        }
+        if let Some(rate) = args.messages_per_sec {
+            self.limits.messages_per_sec = rate;
+        }
+        if let Some(bytes) = args.max_message_bytes {
+            self.limits.max_message_bytes = bytes;
+        }
+        if let Some(secs) = args.grace_period_secs {
+            self.lobby.grace_period = Duration::from_secs(secs);
+        }
+        if let Some(secs) = args.idle_ttl_secs {
+            self.lobby.reaper.idle_ttl = Duration::from_secs(secs);
+        }
+        if let Some(secs) = args.max_lifetime_secs {
+            self.lobby.reaper.max_lifetime = Duration::from_secs(secs);
+        }
+        if let Some(path) = args.nouns_path {
+            self.games.nouns_path = path;
+        }
+        if let Some(secs) = args.shutdown_deadline_secs {
+            self.shutdown.deadline = Duration::from_secs(secs);
+        }
+        if let Some(dir) = args.state_dir {
+            self.shutdown.state_dir = Some(dir);
+        }
+        if let Some(level) = args.log_level {
+            self.log.level = level;
+        }
+        if let Some(format) = args.log_format {
+            self.log.format = format;
+        }
+        if let Some(token) = args.admin_token {
+            self.admin.token = Some(token);
+        }
+    }
+
+    fn validate(&self) -> eyre::Result<()> {
+        if self.lobby.channel_capacity == 0 || self.lobby.user_channel_capacity == 0 {
+            return Err(eyre!("Channel capacities must be greater than 0"));
+        }
+        if self.lobby.reaper.interval.is_zero() {
+            return Err(eyre!("Reaper interval must be greater than 0"));
+        }
+        let limits = &self.limits;
+        if limits.messages_per_sec <= 0.0
+            || !limits.messages_per_sec.is_finite()
+            || limits.burst == 0
+        {
+            return Err(eyre!("Message rate and burst must be greater than 0"));
+        }
+        if limits.binary_per_sec <= 0.0
+            || !limits.binary_per_sec.is_finite()
+            || limits.binary_burst == 0
+        {
+            return Err(eyre!("Binary rate and burst must be greater than 0"));
+        }
+        if limits.max_message_bytes == 0
+            || limits.max_binary_bytes == 0
+            || limits.max_field_chars == 0
+        {
+            return Err(eyre!(
+                "Message and field size limits must be greater than 0"
+            ));
+        }
+        if limits.max_violations == 0 {
+            return Err(eyre!("Max violations must be greater than 0"));
+        }
+        if self.admin.token.as_ref().is_some_and(|t| t.is_empty()) {
+            return Err(eyre!("Admin token must not be empty"));
+        }
+        let _ = self.server.cors()?;
+
+        Ok(())
+    }
+}
+
+impl ServerConfig {
+    pub fn cors(&self) -> eyre::Result<CorsLayer> {
+        let methods = self
+            .allowed_methods
+            .iter()
+            .map(|m| {
+                Method::from_bytes(m.to_uppercase().as_bytes())
+                    .map_err(|_| eyre!("Invalid HTTP method {}", m))
+            })
+            .collect::<eyre::Result<Vec<Method>>>()?;
+
+        let cors = CorsLayer::new().allow_methods(methods);
+
+        if self.allowed_origins.iter().any(|o| o == "*") {
+            return Ok(cors.allow_origin(Any));
+        }
+
+        let origins = self
+            .allowed_origins
+            .iter()
+            .map(|o| o.parse().map_err(|_| eyre!("Invalid origin {}", o)))
+            .collect::<eyre::Result<Vec<_>>>()?;
+
+        Ok(cors.allow_origin(AllowOrigin::list(origins)))
+    }
+}
@@ -1,8 +1,486 @@
+pub mod categories;
+pub mod codenames;
+pub mod connect_four;
+pub mod decrypto;
+pub mod grid;
 pub mod just_one;
+pub mod location_list;
+pub mod question_pack;
+pub mod sketch;
+pub mod spectrum_list;
+pub mod spyfall;
+pub mod telephone;
+pub mod tic_tac_toe;
+pub mod trivia;
+pub mod wavelength;
+pub mod werewolf;
+pub mod word_list;
 
-use serde::{Serialize, Deserialize};
-#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default)]
+use eyre::{eyre, WrapErr};
+use location_list::LocationList;
+use question_pack::QuestionPack;
+use serde::{de::DeserializeOwned, Deserialize, Serialize, Serializer};
+use serde_json::Value;
+use spectrum_list::SpectrumList;
+use std::{path::Path, sync::Arc, time::Instant};
+use tokio::fs::read_to_string;
+use tracing::info;
+use word_list::WordList;
+
+use crate::config::GamesConfig;
+use crate::text::TextError;
+use just_one::{DuplicateRules, HintChecker};
+
+/// Game content loaded once at startup and shared by every lobby
+pub struct GameAssets {
+    pub nouns: WordList,
+    pub categories: WordList,
+    pub locations: LocationList,
+    pub spectrums: SpectrumList,
+    pub questions: QuestionPack,
+}
+
+impl GameAssets {
+    pub async fn load(config: &GamesConfig) -> eyre::Result<GameAssets> {
+        Ok(GameAssets {
+            nouns: WordList::load(&config.nouns_path).await?,
+            categories: WordList::load(&config.categories_path).await?,
+            locations: LocationList::load(&config.locations_path).await?,
+            spectrums: SpectrumList::load(&config.spectrums_path).await?,
+            questions: QuestionPack::load(&config.questions_path).await?,
+        })
+    }
+
+    /// Whether every word list has words to draw from
+    pub fn is_loaded(&self) -> bool {
+        !self.nouns.words.is_empty()
+            && !self.categories.words.is_empty()
+            && !self.locations.locations.is_empty()
+            && !self.spectrums.cards.is_empty()
+            && !self.questions.questions.is_empty()
+    }
+}
+
+/// Content loaded from one of the json files in `assets/`
+pub(crate) trait Asset {
+    fn description(&self) -> &str;
+
+    /// Number of words, cards or the like in the file
+    fn entries(&self) -> usize;
+}
+
+/// Read and parse an asset file, refusing one with no entries. `what` names
+/// the kind of file in errors and logs, e.g. "word list".
+pub(crate) async fn load_json<T: DeserializeOwned + Asset>(
+    path: &Path,
+    what: &str,
+) -> eyre::Result<T> {
+    let txt = read_to_string(path)
+        .await
+        .wrap_err_with(|| format!("Unable to read {} {}", what, path.display()))?;
+
+    let asset: T = serde_json::from_str(&txt)
+        .wrap_err_with(|| format!("Unable to parse {} {}", what, path.display()))?;
+    if asset.entries() == 0 {
+        return Err(eyre!("The {} {} is empty", what, path.display()));
+    }
+
+    info!(
+        path = %path.display(),
+        description = %asset.description(),
+        entries = asset.entries(),
+        "Loaded {}",
+        what
+    );
+    Ok(asset)
+}
+
+/// Everything a lobby hands a game when it starts
+pub struct GameContext<'a> {
+    pub assets: &'a GameAssets,
+    pub settings: &'a GameSettings,
+    pub hint_checker: Arc<HintChecker>,
+    /// The user who started the game
+    pub host: &'a str,
+}
+
+/// A game being played in a lobby. The lobby forwards moves from users and
+/// broadcasts each user their view of the game after every change.
+pub trait Game: Send + Sync {
+    /// Apply a move from `user`, leaving the game untouched if it's refused
+    fn make_move(&mut self, user: &str, action: Value) -> Result<(), InvalidMove>;
+
+    /// The game as `user` is allowed to see it
+    fn filter(&self, user: &str) -> Value;
+
+    /// Name of the group of users who all see the same view as `user`, e.g.
+    /// their team, so the view is only built once for the group. `None`
+    /// means the view is just for `user`.
+    fn audience(&self, _user: &str) -> Option<String> {
+        None
+    }
+
+    /// The full game, secrets included, for saving and the admin API
+    fn state(&self) -> Value;
+
+    /// Name of the stage the game is in, e.g. "Guessing"
+    fn phase(&self) -> String;
+
+    /// When the game next moves on by itself, e.g. when a round timer runs out
+    fn deadline(&self) -> Option<Instant> {
+        None
+    }
+
+    /// Called once the deadline has passed. Returns whether the game changed.
+    fn tick(&mut self, _now: Instant) -> bool {
+        false
+    }
+
+    /// Whether the game has finished, after which the lobby goes back to
+    /// choosing the next game
+    fn is_over(&self) -> bool {
+        false
+    }
+
+    /// Accept a binary frame from `user`, e.g. drawing strokes. Returns the
+    /// frame to relay to everyone else in the lobby, otherwise everyone is
+    /// sent the new game state.
+    fn binary(
+        &mut self,
+        _user: &str,
+        _data: &Arc<Vec<u8>>,
+    ) -> Result<Option<Arc<Vec<u8>>>, InvalidMove> {
+        Err(InvalidMove::NotAllowed {
+            msg: "This game doesn't take binary data".to_owned(),
+        })
+    }
+
+    /// Every binary frame in play that `user` can see, e.g. for users
+    /// joining part way through
+    fn binary_history(&self, _user: &str) -> Vec<Arc<Vec<u8>>> {
+        Vec::new()
+    }
+}
+
+/// Serialize game state that is known to be representable as json
+pub(crate) fn to_json(state: &impl Serialize) -> Value {
+    serde_json::to_value(state).expect("Game state serializes to json")
+}
+
+#[derive(Debug)]
+pub enum InvalidMove {
+    CouldNotParse { msg: String },
+    NotYourTurn { msg: String },
+    WrongState { msg: String },
+    InvalidUser { msg: String },
+    EmptyInput { msg: String },
+    TooLong { msg: String },
+    InvalidCharacters { msg: String },
+    NotOneWord { msg: String },
+    HintIsWord { msg: String },
+    HintTooSimilar { msg: String },
+    NotAllowed { msg: String },
+}
+
+impl InvalidMove {
+    /// Short name of the variant, used as a metric label
+    pub fn kind(&self) -> &'static str {
+        match self {
+            InvalidMove::CouldNotParse { .. } => "could_not_parse",
+            InvalidMove::NotYourTurn { .. } => "not_your_turn",
+            InvalidMove::WrongState { .. } => "wrong_state",
+            InvalidMove::InvalidUser { .. } => "invalid_user",
+            InvalidMove::EmptyInput { .. } => "empty_input",
+            InvalidMove::TooLong { .. } => "too_long",
+            InvalidMove::InvalidCharacters { .. } => "invalid_characters",
+            InvalidMove::NotOneWord { .. } => "not_one_word",
+            InvalidMove::HintIsWord { .. } => "hint_is_word",
+            InvalidMove::HintTooSimilar { .. } => "hint_too_similar",
+            InvalidMove::NotAllowed { .. } => "not_allowed",
+        }
+    }
+
+    /// Describe why the text typed into `field` was refused
+    pub fn from_text(field: &str, e: TextError) -> InvalidMove {
+        let msg = format!("{} {}", field, e);
+        match e {
+            TextError::Empty => InvalidMove::EmptyInput { msg },
+            TextError::TooLong { .. } => InvalidMove::TooLong { msg },
+            TextError::InvalidCharacters => InvalidMove::InvalidCharacters { msg },
+        }
+    }
+}
+
+/// Longest a game timer can be set to. Settings come from clients, so this
+/// keeps deadlines well within what an `Instant` can hold.
+pub(crate) const MAX_TIMER_SECS: u64 = 60 * 60;
+
+/// Serialize a deadline as the whole seconds left until it, for clients to
+/// count down from
+pub(crate) fn secs_left<S: Serializer>(
+    deadline: &Option<Instant>,
+    s: S,
+) -> Result<S::Ok, S::Error> {
+    deadline
+        .map(|d| d.saturating_duration_since(Instant::now()).as_secs())
+        .serialize(s)
+}
+
+/// Parse a move into the game's move type
+pub(crate) fn parse_move<T: serde::de::DeserializeOwned>(action: Value) -> Result<T, InvalidMove> {
+    serde_json::from_value(action).map_err(|e| InvalidMove::CouldNotParse { msg: e.to_string() })
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
 pub enum GameType {
     #[default]
-    JustOne
+    JustOne,
+    Codenames,
+    Spyfall,
+    Werewolf,
+    Wavelength,
+    Sketch,
+    Decrypto,
+    Trivia,
+    Categories,
+    Telephone,
+    ConnectFour,
+    TicTacToe,
+}
+
+impl GameType {
+    pub fn name(&self) -> &'static str {
+        match self {
+            GameType::JustOne => "just_one",
+            GameType::Codenames => "codenames",
+            GameType::Spyfall => "spyfall",
+            GameType::Werewolf => "werewolf",
+            GameType::Wavelength => "wavelength",
+            GameType::Sketch => "sketch",
+            GameType::Decrypto => "decrypto",
+            GameType::Trivia => "trivia",
+            GameType::Categories => "categories",
+            GameType::Telephone => "telephone",
+            GameType::ConnectFour => "connect_four",
+            GameType::TicTacToe => "tic_tac_toe",
+        }
+    }
+
+    /// Set up a game of this type for `players`, or explain why it can't start
+    pub fn start<'a>(
+        &self,
+        players: &'a Vec<String>,
+        ctx: GameContext<'_>,
+    ) -> Result<Box<dyn Game + 'a>, String> {
+        match self {
+            GameType::JustOne => Ok(Box::new(just_one::GameData::new(
+                players,
+                &ctx.assets.nouns,
+                ctx.hint_checker,
+                ctx.settings.just_one.duplicates,
+            ))),
+            GameType::Codenames => {
+                codenames::Codenames::new(players, &ctx.assets.nouns).map(|g| Box::new(g) as _)
+            }
+            GameType::Spyfall => {
+                spyfall::Spyfall::new(players, &ctx.assets.locations, ctx.settings.spyfall)
+                    .map(|g| Box::new(g) as _)
+            }
+            GameType::Werewolf => {
+                werewolf::Werewolf::new(players, ctx.settings.werewolf).map(|g| Box::new(g) as _)
+            }
+            GameType::Wavelength => {
+                wavelength::Wavelength::new(players, &ctx.assets.spectrums, ctx.settings.wavelength)
+                    .map(|g| Box::new(g) as _)
+            }
+            GameType::Sketch => {
+                sketch::Sketch::new(players, &ctx.assets.nouns, ctx.settings.sketch)
+                    .map(|g| Box::new(g) as _)
+            }
+            GameType::Decrypto => {
+                decrypto::Decrypto::new(players, &ctx.assets.nouns).map(|g| Box::new(g) as _)
+            }
+            GameType::Trivia => {
+                trivia::Trivia::new(players, &ctx.assets.questions, ctx.settings.trivia.clone())
+                    .map(|g| Box::new(g) as _)
+            }
+            GameType::Categories => categories::Categories::new(
+                players,
+                &ctx.assets.categories,
+                ctx.settings.categories,
+            )
+            .map(|g| Box::new(g) as _),
+            GameType::Telephone => telephone::Telephone::new(
+                players,
+                ctx.host,
+                &ctx.assets.nouns,
+                ctx.settings.telephone,
+            )
+            .map(|g| Box::new(g) as _),
+            GameType::ConnectFour => {
+                connect_four::ConnectFour::new(players).map(|g| Box::new(g) as _)
+            }
+            GameType::TicTacToe => tic_tac_toe::TicTacToe::new(players).map(|g| Box::new(g) as _),
+        }
+    }
+}
+
+/// Options a lobby can change between games
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default, rename_all = "camelCase")]
+pub struct GameSettings {
+    pub just_one: JustOneSettings,
+    pub spyfall: SpyfallSettings,
+    pub werewolf: WerewolfSettings,
+    pub wavelength: WavelengthSettings,
+    pub sketch: SketchSettings,
+    pub trivia: TriviaSettings,
+    pub categories: CategoriesSettings,
+    pub telephone: TelephoneSettings,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default)]
+#[serde(default, rename_all = "camelCase")]
+pub struct JustOneSettings {
+    pub duplicates: DuplicateRules,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[serde(default, rename_all = "camelCase")]
+pub struct SpyfallSettings {
+    /// Length of the question round
+    pub round_secs: u64,
+}
+
+impl Default for SpyfallSettings {
+    fn default() -> Self {
+        SpyfallSettings { round_secs: 480 }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[serde(default, rename_all = "camelCase")]
+pub struct WerewolfSettings {
+    pub werewolves: usize,
+    pub seers: usize,
+    pub doctors: usize,
+    pub night_secs: u64,
+    pub day_secs: u64,
+}
+
+impl Default for WerewolfSettings {
+    fn default() -> Self {
+        WerewolfSettings {
+            werewolves: 1,
+            seers: 1,
+            doctors: 1,
+            night_secs: 60,
+            day_secs: 180,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[serde(default, rename_all = "camelCase")]
+pub struct WavelengthSettings {
+    /// Points a team needs to win
+    pub target_score: u32,
+}
+
+impl Default for WavelengthSettings {
+    fn default() -> Self {
+        WavelengthSettings { target_score: 10 }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[serde(default, rename_all = "camelCase")]
+pub struct SketchSettings {
+    /// Times each player draws
+    pub rounds: usize,
+    pub draw_secs: u64,
+}
+
+impl Default for SketchSettings {
+    fn default() -> Self {
+        SketchSettings {
+            rounds: 3,
+            draw_secs: 80,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default, rename_all = "camelCase")]
+pub struct TriviaSettings {
+    /// Categories to ask questions from, every category when empty
+    pub categories: Vec<String>,
+    pub questions: usize,
+    pub answer_secs: u64,
+}
+
+impl Default for TriviaSettings {
+    fn default() -> Self {
+        TriviaSettings {
+            categories: Vec::new(),
+            questions: 10,
+            answer_secs: 20,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[serde(default, rename_all = "camelCase")]
+pub struct CategoriesSettings {
+    pub rounds: usize,
+    /// Categories drawn each round
+    pub categories: usize,
+    pub answer_secs: u64,
+    /// How answers are compared when looking for duplicates
+    pub duplicates: DuplicateRules,
+}
+
+impl Default for CategoriesSettings {
+    fn default() -> Self {
+        CategoriesSettings {
+            rounds: 3,
+            categories: 10,
+            answer_secs: 120,
+            duplicates: DuplicateRules::default(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[serde(default, rename_all = "camelCase")]
+pub struct TelephoneSettings {
+    pub draw_secs: u64,
+    pub describe_secs: u64,
+}
+
+impl Default for TelephoneSettings {
+    fn default() -> Self {
+        TelephoneSettings {
+            draw_secs: 90,
+            describe_secs: 45,
+        }
+    }
+}
+
+impl GameSettings {
+    /// The settings new lobbies start with
+    pub fn from_config(config: &GamesConfig) -> GameSettings {
+        GameSettings {
+            just_one: JustOneSettings {
+                duplicates: config.just_one.duplicates,
+            },
+            spyfall: SpyfallSettings::default(),
+            werewolf: WerewolfSettings::default(),
+            wavelength: WavelengthSettings::default(),
+            sketch: SketchSettings::default(),
+            trivia: TriviaSettings::default(),
+            categories: CategoriesSettings::default(),
+            telephone: TelephoneSettings::default(),
+        }
+    }
 }
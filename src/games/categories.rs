@@ -0,0 +1,596 @@
+//! Categories: each round draws a letter and a list of categories, and
+//! everyone secretly writes an answer for each category starting with that
+//! letter. Answers are then revealed and players vote out bad ones. Only
+//! accepted answers nobody else gave score.
+
+use rand::{seq::SliceRandom, thread_rng};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use super::{
+    just_one::validation, parse_move, secs_left, to_json, word_list::WordList, CategoriesSettings,
+    Game, InvalidMove, MAX_TIMER_SECS,
+};
+use crate::text;
+
+const MIN_PLAYERS: usize = 2;
+const MAX_ANSWER_CHARS: usize = 48;
+/// Letters few categories have answers for are left out
+const LETTERS: &[char] = &[
+    'A', 'B', 'C', 'D', 'E', 'F', 'G', 'H', 'I', 'J', 'K', 'L', 'M', 'N', 'O', 'P', 'R', 'S', 'T',
+    'W',
+];
+/// Time players get to vote out answers before the round is scored anyway
+const VOTE_SECS: u64 = 90;
+/// How long the scores are shown between rounds
+const SCORES_SECS: u64 = 10;
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Copy)]
+pub enum Phase {
+    Answering,
+    Voting,
+    Scores,
+    GameOver,
+}
+
+#[derive(Serialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct Answer {
+    /// Empty when the player left the category blank
+    text: String,
+    /// Doesn't start with the round's letter
+    wrong_letter: bool,
+    /// Matches another player's answer in the same category
+    duplicate: bool,
+    /// Players voting to throw the answer out
+    rejected_by: Vec<String>,
+    points: u32,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Categories {
+    players: Vec<String>,
+    scores: HashMap<String, u32>,
+    round: usize,
+    rounds: usize,
+    letter: char,
+    categories: Vec<String>,
+    /// Each player's answers, in category order. Players only see their own
+    /// until everyone's are revealed.
+    answers: HashMap<String, Vec<Answer>>,
+    /// Players who have handed in their answers
+    submitted: Vec<String>,
+    /// Players who have finished voting
+    done_voting: Vec<String>,
+    phase: Phase,
+    #[serde(rename = "secsLeft", serialize_with = "secs_left")]
+    deadline: Option<Instant>,
+
+    #[serde(skip)]
+    deck: Vec<String>,
+    #[serde(skip)]
+    settings: CategoriesSettings,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "actionType", content = "data")]
+#[serde(rename_all = "camelCase")]
+pub enum CategoriesMove {
+    /// One answer per category, in order, empty for blanks
+    Answers(Vec<String>),
+    /// Vote to throw out `player`'s answer to the category at `category`
+    Reject {
+        player: String,
+        category: usize,
+    },
+    /// Take back a vote to throw out an answer. Also clears a duplicate
+    /// mark, for answers the table decides are different enough.
+    Allow {
+        player: String,
+        category: usize,
+    },
+    DoneVoting,
+}
+
+impl Categories {
+    pub fn new(
+        players: &[String],
+        categories: &WordList,
+        settings: CategoriesSettings,
+    ) -> Result<Categories, String> {
+        if players.len() < MIN_PLAYERS {
+            return Err(format!("Categories needs at least {} players", MIN_PLAYERS));
+        }
+        if settings.rounds == 0 || settings.categories == 0 || settings.answer_secs == 0 {
+            return Err("Categories needs at least one round, category and second".to_owned());
+        }
+        if categories.words.len() < settings.categories {
+            return Err(format!(
+                "There are only {} categories to pick from",
+                categories.words.len()
+            ));
+        }
+        let settings = CategoriesSettings {
+            answer_secs: settings.answer_secs.min(MAX_TIMER_SECS),
+            ..settings
+        };
+
+        let mut game = Categories {
+            players: players.to_vec(),
+            scores: players.iter().map(|p| (p.clone(), 0)).collect(),
+            round: 0,
+            rounds: settings.rounds,
+            letter: LETTERS[0],
+            categories: Vec::new(),
+            answers: HashMap::new(),
+            submitted: Vec::new(),
+            done_voting: Vec::new(),
+            phase: Phase::Answering,
+            deadline: None,
+            deck: categories.shuffled().words,
+            settings,
+        };
+        game.start_round(Instant::now());
+
+        Ok(game)
+    }
+
+    fn start_round(&mut self, now: Instant) {
+        // Categories go to the bottom of the deck once played
+        let n = self.settings.categories;
+        self.categories = self.deck.drain(..n).collect();
+        self.deck.extend(self.categories.iter().cloned());
+
+        self.round += 1;
+        self.letter = *LETTERS
+            .choose(&mut thread_rng())
+            .expect("There are letters");
+        self.answers.clear();
+        self.submitted.clear();
+        self.done_voting.clear();
+        self.phase = Phase::Answering;
+        self.deadline = Some(now + Duration::from_secs(self.settings.answer_secs));
+    }
+
+    fn check_phase(&self, phase: Phase) -> Result<(), InvalidMove> {
+        if self.phase != phase {
+            return Err(InvalidMove::WrongState {
+                msg: format!("Can't do that during {:?}", self.phase),
+            });
+        }
+        Ok(())
+    }
+
+    fn check_player(&self, user: &str) -> Result<(), InvalidMove> {
+        if !self.players.iter().any(|p| p == user) {
+            return Err(InvalidMove::InvalidUser {
+                msg: format!("{} is not playing", user),
+            });
+        }
+        Ok(())
+    }
+
+    fn submit(
+        &mut self,
+        user: &str,
+        answers: Vec<String>,
+        now: Instant,
+    ) -> Result<(), InvalidMove> {
+        self.check_phase(Phase::Answering)?;
+        self.check_player(user)?;
+        if self.answers.contains_key(user) {
+            return Err(InvalidMove::WrongState {
+                msg: "You've already handed in your answers".to_owned(),
+            });
+        }
+        if answers.len() != self.categories.len() {
+            return Err(InvalidMove::NotAllowed {
+                msg: format!(
+                    "Give one answer for each of the {} categories",
+                    self.categories.len()
+                ),
+            });
+        }
+
+        let letter = text::fold(&self.letter.to_string());
+        let answers = answers
+            .iter()
+            .map(|a| {
+                if a.trim().is_empty() {
+                    return Ok(Answer::default());
+                }
+                let text = text::clean(a, MAX_ANSWER_CHARS)
+                    .map_err(|e| InvalidMove::from_text("Answer", e))?;
+                Ok(Answer {
+                    wrong_letter: !validation::letters(&text).starts_with(&letter),
+                    text,
+                    ..Answer::default()
+                })
+            })
+            .collect::<Result<Vec<_>, InvalidMove>>()?;
+
+        self.answers.insert(user.to_owned(), answers);
+        self.submitted.push(user.to_owned());
+        if self.submitted.len() == self.players.len() {
+            self.start_voting(now);
+        }
+        Ok(())
+    }
+
+    /// Reveal the answers with duplicates already marked
+    fn start_voting(&mut self, now: Instant) {
+        for c in 0..self.categories.len() {
+            let given: Vec<(&String, &String)> = self
+                .answers
+                .iter()
+                .filter(|(_, a)| !a[c].text.is_empty())
+                .map(|(p, a)| (p, &a[c].text))
+                .collect();
+            let texts: Vec<&String> = given.iter().map(|(_, t)| *t).collect();
+            let marks = self.settings.duplicates.mark(&texts);
+
+            let dupes: Vec<String> = given
+                .iter()
+                .zip(marks)
+                .filter(|(_, dupe)| *dupe)
+                .map(|((p, _), _)| (*p).clone())
+                .collect();
+            for p in dupes {
+                if let Some(a) = self.answers.get_mut(&p) {
+                    a[c].duplicate = true;
+                }
+            }
+        }
+
+        self.phase = Phase::Voting;
+        self.deadline = Some(now + Duration::from_secs(VOTE_SECS));
+    }
+
+    fn vote(
+        &mut self,
+        user: &str,
+        player: &str,
+        category: usize,
+        reject: bool,
+    ) -> Result<(), InvalidMove> {
+        self.check_phase(Phase::Voting)?;
+        self.check_player(user)?;
+        if user == player {
+            return Err(InvalidMove::NotAllowed {
+                msg: "You can't vote on your own answers".to_owned(),
+            });
+        }
+        let Some(answer) = self
+            .answers
+            .get_mut(player)
+            .and_then(|a| a.get_mut(category))
+            .filter(|a| !a.text.is_empty())
+        else {
+            return Err(InvalidMove::NotAllowed {
+                msg: "There's no answer there to vote on".to_owned(),
+            });
+        };
+
+        answer.rejected_by.retain(|p| p != user);
+        if reject {
+            answer.rejected_by.push(user.to_owned());
+        } else {
+            answer.duplicate = false;
+        }
+        Ok(())
+    }
+
+    fn done_voting(&mut self, user: &str, now: Instant) -> Result<(), InvalidMove> {
+        self.check_phase(Phase::Voting)?;
+        self.check_player(user)?;
+        if !self.done_voting.iter().any(|p| p == user) {
+            self.done_voting.push(user.to_owned());
+        }
+        if self.done_voting.len() == self.players.len() {
+            self.score(now);
+        }
+        Ok(())
+    }
+
+    /// An answer scores a point unless it's blank, starts with the wrong
+    /// letter, duplicates another, or most of the other players voted it out
+    fn score(&mut self, now: Instant) {
+        let voters = self.players.len() - 1;
+        for (player, answers) in self.answers.iter_mut() {
+            for a in answers.iter_mut() {
+                let accepted = !a.text.is_empty()
+                    && !a.wrong_letter
+                    && !a.duplicate
+                    && a.rejected_by.len() * 2 <= voters;
+                a.points = u32::from(accepted);
+                *self.scores.entry(player.clone()).or_default() += a.points;
+            }
+        }
+
+        if self.round >= self.rounds {
+            self.phase = Phase::GameOver;
+            self.deadline = None;
+        } else {
+            self.phase = Phase::Scores;
+            self.deadline = Some(now + Duration::from_secs(SCORES_SECS));
+        }
+    }
+}
+
+impl Game for Categories {
+    fn make_move(&mut self, user: &str, action: Value) -> Result<(), InvalidMove> {
+        let now = Instant::now();
+        match parse_move(action)? {
+            CategoriesMove::Answers(answers) => self.submit(user, answers, now),
+            CategoriesMove::Reject { player, category } => self.vote(user, &player, category, true),
+            CategoriesMove::Allow { player, category } => self.vote(user, &player, category, false),
+            CategoriesMove::DoneVoting => self.done_voting(user, now),
+        }
+    }
+
+    /// Answers stay private until everyone has handed theirs in
+    fn filter(&self, user: &str) -> Value {
+        if self.phase != Phase::Answering {
+            return to_json(self);
+        }
+
+        let mut view = self.clone();
+        view.answers.retain(|p, _| p == user);
+        to_json(&view)
+    }
+
+    fn state(&self) -> Value {
+        to_json(self)
+    }
+
+    fn phase(&self) -> String {
+        format!("{:?}", self.phase)
+    }
+
+    fn is_over(&self) -> bool {
+        self.phase == Phase::GameOver
+    }
+
+    fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    /// Answers still out when time runs out count as blank
+    fn tick(&mut self, now: Instant) -> bool {
+        match (self.phase, self.deadline) {
+            (Phase::Answering, Some(d)) if now >= d => {
+                let blank = vec![Answer::default(); self.categories.len()];
+                for p in &self.players {
+                    self.answers
+                        .entry(p.clone())
+                        .or_insert_with(|| blank.clone());
+                }
+                self.start_voting(now);
+            }
+            (Phase::Voting, Some(d)) if now >= d => self.score(now),
+            (Phase::Scores, Some(d)) if now >= d => self.start_round(now),
+            _ => return false,
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn players() -> Vec<String> {
+        ["alice", "bob", "carol"].map(String::from).to_vec()
+    }
+
+    fn game() -> Categories {
+        let categories = WordList {
+            description: "Test".to_owned(),
+            words: ["Fruits", "Animals", "Countries"]
+                .map(String::from)
+                .to_vec(),
+        };
+        let settings = CategoriesSettings {
+            categories: 2,
+            ..CategoriesSettings::default()
+        };
+        let mut g = Categories::new(&players(), &categories, settings).unwrap();
+        g.letter = 'B';
+        g
+    }
+
+    fn submit(g: &mut Categories, user: &str, answers: [&str; 2]) -> Result<(), InvalidMove> {
+        let now = g.deadline.unwrap();
+        g.submit(user, answers.map(String::from).to_vec(), now)
+    }
+
+    fn points(g: &Categories, user: &str) -> Vec<u32> {
+        g.answers[user].iter().map(|a| a.points).collect()
+    }
+
+    fn everyone_done(g: &mut Categories) {
+        let now = g.deadline.unwrap();
+        for p in players() {
+            g.done_voting(&p, now).unwrap();
+        }
+    }
+
+    #[test]
+    fn rounds_draw_a_letter_and_categories() {
+        let g = game();
+        assert_eq!(g.categories.len(), 2);
+        assert_ne!(g.categories[0], g.categories[1]);
+        assert_eq!(g.deck.len(), 3);
+        assert_eq!(g.phase, Phase::Answering);
+        assert!(g.deadline.is_some());
+    }
+
+    #[test]
+    fn answers_are_private_until_everyone_is_in() {
+        let mut g = game();
+        submit(&mut g, "alice", ["Banana", "Bear"]).unwrap();
+
+        let view = g.filter("bob");
+        assert_eq!(view["answers"], json!({}));
+        assert_eq!(view["submitted"], json!(["alice"]));
+        assert_eq!(
+            g.filter("alice")["answers"]["alice"][0]["text"],
+            json!("Banana")
+        );
+
+        submit(&mut g, "bob", ["Blueberry", ""]).unwrap();
+        submit(&mut g, "carol", ["Apple", "Bison"]).unwrap();
+        assert_eq!(g.phase, Phase::Voting);
+        assert_eq!(
+            g.filter("bob")["answers"]["alice"][1]["text"],
+            json!("Bear")
+        );
+    }
+
+    #[test]
+    fn bad_submissions_are_refused() {
+        let mut g = game();
+        let now = g.deadline.unwrap();
+        let res = g.submit("alice", vec!["Banana".to_owned()], now);
+        assert!(matches!(res, Err(InvalidMove::NotAllowed { .. })));
+        assert!(matches!(
+            submit(&mut g, "zed", ["Banana", "Bear"]),
+            Err(InvalidMove::InvalidUser { .. })
+        ));
+
+        submit(&mut g, "alice", ["Banana", "Bear"]).unwrap();
+        assert!(matches!(
+            submit(&mut g, "alice", ["Banana", "Bear"]),
+            Err(InvalidMove::WrongState { .. })
+        ));
+    }
+
+    #[test]
+    fn duplicates_and_wrong_letters_are_flagged() {
+        let mut g = game();
+        submit(&mut g, "alice", ["Banana", "Bear"]).unwrap();
+        submit(&mut g, "bob", ["bananas", "Bison"]).unwrap();
+        submit(&mut g, "carol", ["Apple", ""]).unwrap();
+
+        assert!(g.answers["alice"][0].duplicate);
+        assert!(g.answers["bob"][0].duplicate);
+        assert!(!g.answers["alice"][1].duplicate);
+        assert!(g.answers["carol"][0].wrong_letter);
+        assert!(!g.answers["carol"][1].wrong_letter);
+    }
+
+    #[test]
+    fn the_table_can_overrule_duplicates() {
+        let mut g = game();
+        submit(&mut g, "alice", ["Bread", "Bear"]).unwrap();
+        submit(&mut g, "bob", ["Breads", "Bison"]).unwrap();
+        submit(&mut g, "carol", ["Apple", ""]).unwrap();
+        let now = g.deadline.unwrap();
+        assert!(g.answers["alice"][0].duplicate);
+
+        g.vote("carol", "alice", 0, false).unwrap();
+        g.vote("carol", "bob", 0, false).unwrap();
+        assert!(!g.answers["alice"][0].duplicate);
+
+        g.score(now);
+        assert_eq!(points(&g, "alice"), [1, 1]);
+        assert_eq!(points(&g, "bob"), [1, 1]);
+    }
+
+    #[test]
+    fn answer_time_is_clamped() {
+        let categories = WordList {
+            description: "Test".to_owned(),
+            words: vec!["Fruits".to_owned()],
+        };
+        let settings = CategoriesSettings {
+            categories: 1,
+            answer_secs: u64::MAX,
+            ..CategoriesSettings::default()
+        };
+        let g = Categories::new(&players(), &categories, settings).unwrap();
+        assert_eq!(g.settings.answer_secs, MAX_TIMER_SECS);
+    }
+
+    #[test]
+    fn unique_accepted_answers_score() {
+        let mut g = game();
+        submit(&mut g, "alice", ["Banana", "Bear"]).unwrap();
+        submit(&mut g, "bob", ["Banana", "Bison"]).unwrap();
+        submit(&mut g, "carol", ["Apple", ""]).unwrap();
+        everyone_done(&mut g);
+
+        assert_eq!(g.phase, Phase::Scores);
+        assert_eq!(points(&g, "alice"), [0, 1]);
+        assert_eq!(points(&g, "bob"), [0, 1]);
+        assert_eq!(points(&g, "carol"), [0, 0]);
+        assert_eq!(g.scores["alice"], 1);
+    }
+
+    #[test]
+    fn majority_votes_reject_answers() {
+        let mut g = game();
+        submit(&mut g, "alice", ["Blueberry", "Bear"]).unwrap();
+        submit(&mut g, "bob", ["Banana", "Bison"]).unwrap();
+        submit(&mut g, "carol", ["Blackberry", "Boat"]).unwrap();
+        let now = g.deadline.unwrap();
+
+        g.vote("alice", "carol", 1, true).unwrap();
+        g.vote("bob", "carol", 1, true).unwrap();
+        // One vote of two isn't enough
+        g.vote("carol", "bob", 0, true).unwrap();
+        // Votes can be taken back
+        g.vote("alice", "bob", 1, true).unwrap();
+        g.vote("alice", "bob", 1, false).unwrap();
+        assert_eq!(g.answers["bob"][1].rejected_by, Vec::<String>::new());
+
+        assert!(matches!(
+            g.vote("alice", "alice", 0, true),
+            Err(InvalidMove::NotAllowed { .. })
+        ));
+        assert!(matches!(
+            g.vote("alice", "bob", 5, true),
+            Err(InvalidMove::NotAllowed { .. })
+        ));
+
+        g.score(now);
+        assert_eq!(points(&g, "carol"), [1, 0]);
+        assert_eq!(points(&g, "bob"), [1, 1]);
+    }
+
+    #[test]
+    fn timers_move_the_game_on() {
+        let mut g = game();
+        submit(&mut g, "alice", ["Banana", "Bear"]).unwrap();
+
+        assert!(g.tick(g.deadline.unwrap()));
+        assert_eq!(g.phase, Phase::Voting);
+        // Players who didn't answer in time get blanks
+        assert_eq!(g.answers["bob"][0].text, "");
+
+        assert!(g.tick(g.deadline.unwrap()));
+        assert_eq!(g.phase, Phase::Scores);
+        assert_eq!(g.scores["alice"], 2);
+
+        assert!(g.tick(g.deadline.unwrap()));
+        assert_eq!(g.phase, Phase::Answering);
+        assert_eq!(g.round, 2);
+        assert!(g.answers.is_empty());
+    }
+
+    #[test]
+    fn game_ends_after_the_last_round() {
+        let mut g = game();
+        while g.phase != Phase::GameOver {
+            g.tick(g.deadline.unwrap());
+        }
+        assert_eq!(g.round, g.rounds);
+        assert_eq!(g.deadline(), None);
+    }
+}
@@ -0,0 +1,662 @@
+//! Codenames: two teams race to find their agents on a grid of words. Each
+//! team's spymaster sees the key card and gives one word clues, their
+//! operatives guess which cards the clue points at.
+
+use rand::{seq::SliceRandom, thread_rng};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashSet;
+
+use super::{parse_move, to_json, word_list::WordList, Game, InvalidMove};
+use crate::text;
+
+const GRID_SIZE: usize = 25;
+const FIRST_TEAM_AGENTS: usize = 9;
+const SECOND_TEAM_AGENTS: usize = 8;
+const ASSASSINS: usize = 1;
+const MIN_PLAYERS: usize = 4;
+const MAX_CLUE_CHARS: usize = 32;
+const MAX_CLUE_COUNT: u8 = 9;
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Team {
+    Red,
+    Blue,
+}
+
+impl Team {
+    fn other(self) -> Team {
+        match self {
+            Team::Red => Team::Blue,
+            Team::Blue => Team::Red,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Copy)]
+pub enum CardColor {
+    Red,
+    Blue,
+    Neutral,
+    Assassin,
+}
+
+impl From<Team> for CardColor {
+    fn from(team: Team) -> CardColor {
+        match team {
+            Team::Red => CardColor::Red,
+            Team::Blue => CardColor::Blue,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Copy)]
+pub enum Phase {
+    GivingClue,
+    Guessing,
+    GameOver,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Card {
+    word: String,
+    /// Which side of the key card the word is on, `None` while the viewer
+    /// isn't allowed to know
+    color: Option<CardColor>,
+    revealed: bool,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct TeamData {
+    spymaster: String,
+    operatives: Vec<String>,
+    agents_left: usize,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Clue {
+    team: Team,
+    word: String,
+    count: u8,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Codenames {
+    red: TeamData,
+    blue: TeamData,
+    cards: Vec<Card>,
+    turn: Team,
+    phase: Phase,
+    clues: Vec<Clue>,
+    guesses_left: u8,
+    winner: Option<Team>,
+    /// Whether the game ended because the assassin was found
+    assassin_found: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "actionType", content = "data")]
+#[serde(rename_all = "camelCase")]
+pub enum CodenamesMove {
+    GiveClue {
+        word: String,
+        count: u8,
+    },
+    /// Reveal the card at this position in the grid
+    Guess(usize),
+    EndTurn,
+}
+
+impl Codenames {
+    /// Split `players` into two teams, the first player of each being the
+    /// spymaster, and lay out a grid of words with a random key card
+    pub fn new(players: &[String], words: &WordList) -> Result<Codenames, String> {
+        if players.len() < MIN_PLAYERS {
+            return Err(format!("Codenames needs at least {} players", MIN_PLAYERS));
+        }
+
+        let mut seen = HashSet::new();
+        let grid: Vec<String> = words
+            .shuffled()
+            .words
+            .into_iter()
+            .filter(|w| seen.insert(text::fold(w)))
+            .take(GRID_SIZE)
+            .collect();
+        if grid.len() < GRID_SIZE {
+            return Err(format!(
+                "Codenames needs at least {} different words",
+                GRID_SIZE
+            ));
+        }
+
+        let mut rng = thread_rng();
+        let mut players = players.to_vec();
+        players.shuffle(&mut rng);
+        let (red, blue): (Vec<_>, Vec<_>) = players
+            .into_iter()
+            .enumerate()
+            .partition(|(i, _)| i % 2 == 0);
+
+        let turn = *[Team::Red, Team::Blue].choose(&mut rng).unwrap();
+        let mut key = [
+            (CardColor::from(turn), FIRST_TEAM_AGENTS),
+            (CardColor::from(turn.other()), SECOND_TEAM_AGENTS),
+            (CardColor::Assassin, ASSASSINS),
+            (
+                CardColor::Neutral,
+                GRID_SIZE - FIRST_TEAM_AGENTS - SECOND_TEAM_AGENTS - ASSASSINS,
+            ),
+        ]
+        .into_iter()
+        .flat_map(|(color, n)| std::iter::repeat_n(color, n))
+        .collect::<Vec<_>>();
+        key.shuffle(&mut rng);
+
+        let cards = grid
+            .into_iter()
+            .zip(key)
+            .map(|(word, color)| Card {
+                word,
+                color: Some(color),
+                revealed: false,
+            })
+            .collect();
+
+        let team = |members: Vec<(usize, String)>, t: Team| {
+            let mut members = members.into_iter().map(|(_, p)| p);
+            TeamData {
+                spymaster: members.next().expect("Every team has a player"),
+                operatives: members.collect(),
+                agents_left: if t == turn {
+                    FIRST_TEAM_AGENTS
+                } else {
+                    SECOND_TEAM_AGENTS
+                },
+            }
+        };
+
+        Ok(Codenames {
+            red: team(red, Team::Red),
+            blue: team(blue, Team::Blue),
+            cards,
+            turn,
+            phase: Phase::GivingClue,
+            clues: Vec::new(),
+            guesses_left: 0,
+            winner: None,
+            assassin_found: false,
+        })
+    }
+
+    fn team(&self, team: Team) -> &TeamData {
+        match team {
+            Team::Red => &self.red,
+            Team::Blue => &self.blue,
+        }
+    }
+
+    fn team_mut(&mut self, team: Team) -> &mut TeamData {
+        match team {
+            Team::Red => &mut self.red,
+            Team::Blue => &mut self.blue,
+        }
+    }
+
+    fn is_spymaster(&self, user: &str) -> bool {
+        self.red.spymaster == user || self.blue.spymaster == user
+    }
+
+    fn check_phase(&self, phase: Phase) -> Result<(), InvalidMove> {
+        if self.phase != phase {
+            return Err(InvalidMove::WrongState {
+                msg: format!("Can't do that during {:?}", self.phase),
+            });
+        }
+        Ok(())
+    }
+
+    fn give_clue(&mut self, user: &str, word: String, count: u8) -> Result<(), InvalidMove> {
+        self.check_phase(Phase::GivingClue)?;
+        if self.team(self.turn).spymaster != user {
+            return Err(InvalidMove::NotYourTurn {
+                msg: "Only the spymaster whose turn it is can give a clue".to_owned(),
+            });
+        }
+
+        let word =
+            text::clean(&word, MAX_CLUE_CHARS).map_err(|e| InvalidMove::from_text("Clue", e))?;
+        if !word
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '-' || c == '\'')
+        {
+            return Err(InvalidMove::NotOneWord {
+                msg: "Clues must be a single word".to_owned(),
+            });
+        }
+        let folded = text::fold(&word);
+        if self
+            .cards
+            .iter()
+            .any(|c| !c.revealed && text::fold(&c.word) == folded)
+        {
+            return Err(InvalidMove::NotAllowed {
+                msg: "Clues can't be a word on the board".to_owned(),
+            });
+        }
+        if count == 0 || count > MAX_CLUE_COUNT {
+            return Err(InvalidMove::NotAllowed {
+                msg: format!("Clue numbers must be between 1 and {}", MAX_CLUE_COUNT),
+            });
+        }
+
+        self.clues.push(Clue {
+            team: self.turn,
+            word,
+            count,
+        });
+        // Operatives get one guess more than the clue number
+        self.guesses_left = count + 1;
+        self.phase = Phase::Guessing;
+
+        Ok(())
+    }
+
+    fn check_operative(&self, user: &str) -> Result<(), InvalidMove> {
+        if !self.team(self.turn).operatives.iter().any(|o| o == user) {
+            return Err(InvalidMove::NotYourTurn {
+                msg: "Only operatives whose turn it is can guess".to_owned(),
+            });
+        }
+        Ok(())
+    }
+
+    fn guess(&mut self, user: &str, card: usize) -> Result<(), InvalidMove> {
+        self.check_phase(Phase::Guessing)?;
+        self.check_operative(user)?;
+
+        let card = match self.cards.get_mut(card) {
+            Some(c) if !c.revealed => c,
+            Some(_) => {
+                return Err(InvalidMove::NotAllowed {
+                    msg: "That card has already been revealed".to_owned(),
+                })
+            }
+            None => {
+                return Err(InvalidMove::NotAllowed {
+                    msg: format!("There is no card {}", card),
+                })
+            }
+        };
+        card.revealed = true;
+        let color = card.color.expect("The full game knows every color");
+
+        let owner = match color {
+            CardColor::Red => Some(Team::Red),
+            CardColor::Blue => Some(Team::Blue),
+            CardColor::Neutral => None,
+            CardColor::Assassin => {
+                self.assassin_found = true;
+                self.finish(self.turn.other());
+                return Ok(());
+            }
+        };
+
+        if let Some(team) = owner {
+            let data = self.team_mut(team);
+            data.agents_left -= 1;
+            if data.agents_left == 0 {
+                self.finish(team);
+                return Ok(());
+            }
+        }
+
+        self.guesses_left -= 1;
+        if owner != Some(self.turn) || self.guesses_left == 0 {
+            self.end_turn();
+        }
+
+        Ok(())
+    }
+
+    fn end_turn(&mut self) {
+        self.turn = self.turn.other();
+        self.guesses_left = 0;
+        self.phase = Phase::GivingClue;
+    }
+
+    fn finish(&mut self, winner: Team) {
+        self.winner = Some(winner);
+        self.guesses_left = 0;
+        self.phase = Phase::GameOver;
+    }
+}
+
+impl Game for Codenames {
+    fn make_move(&mut self, user: &str, action: Value) -> Result<(), InvalidMove> {
+        match parse_move(action)? {
+            CodenamesMove::GiveClue { word, count } => self.give_clue(user, word, count),
+            CodenamesMove::Guess(card) => self.guess(user, card),
+            CodenamesMove::EndTurn => {
+                self.check_phase(Phase::Guessing)?;
+                self.check_operative(user)?;
+                self.end_turn();
+                Ok(())
+            }
+        }
+    }
+
+    /// Spymasters see the whole key card, everyone else only the colors of
+    /// revealed cards until the game is over
+    fn filter(&self, user: &str) -> Value {
+        if self.phase == Phase::GameOver || self.is_spymaster(user) {
+            return to_json(self);
+        }
+
+        let mut view = self.clone();
+        for card in view.cards.iter_mut().filter(|c| !c.revealed) {
+            card.color = None;
+        }
+        to_json(&view)
+    }
+
+    fn state(&self) -> Value {
+        to_json(self)
+    }
+
+    fn phase(&self) -> String {
+        format!("{:?}", self.phase)
+    }
+
+    fn is_over(&self) -> bool {
+        self.phase == Phase::GameOver
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn players() -> Vec<String> {
+        ["alice", "bob", "carol", "dave", "erin"]
+            .map(String::from)
+            .to_vec()
+    }
+
+    fn words(n: usize) -> WordList {
+        WordList {
+            description: "Test".to_owned(),
+            words: (0..n).map(|i| format!("Word{}", i)).collect(),
+        }
+    }
+
+    fn game() -> Codenames {
+        Codenames::new(&players(), &words(40)).unwrap()
+    }
+
+    fn spymaster(g: &Codenames) -> String {
+        g.team(g.turn).spymaster.clone()
+    }
+
+    fn operative(g: &Codenames) -> String {
+        g.team(g.turn).operatives[0].clone()
+    }
+
+    /// Position of a hidden card of the given color
+    fn hidden(g: &Codenames, color: CardColor) -> usize {
+        g.cards
+            .iter()
+            .position(|c| !c.revealed && c.color == Some(color))
+            .unwrap()
+    }
+
+    fn clue(g: &mut Codenames, count: u8) {
+        let user = spymaster(g);
+        g.make_move(
+            &user,
+            json!({ "actionType": "giveClue", "data": { "word": "hint", "count": count } }),
+        )
+        .unwrap();
+    }
+
+    fn guess(g: &mut Codenames, card: usize) -> Result<(), InvalidMove> {
+        let user = operative(g);
+        g.make_move(&user, json!({ "actionType": "guess", "data": card }))
+    }
+
+    fn colors(v: &Value) -> Vec<Value> {
+        v["cards"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|c| c["color"].clone())
+            .collect()
+    }
+
+    #[test]
+    fn teams_are_split_with_one_spymaster_each() {
+        let g = game();
+        assert_eq!(g.red.operatives.len() + g.blue.operatives.len(), 3);
+        assert!(g.red.operatives.len().abs_diff(g.blue.operatives.len()) <= 1);
+        assert_ne!(g.red.spymaster, g.blue.spymaster);
+        assert_eq!(g.phase, Phase::GivingClue);
+    }
+
+    #[test]
+    fn starting_team_has_an_extra_agent() {
+        let g = game();
+        let count = |color| g.cards.iter().filter(|c| c.color == Some(color)).count();
+
+        assert_eq!(g.cards.len(), GRID_SIZE);
+        assert_eq!(count(g.turn.into()), FIRST_TEAM_AGENTS);
+        assert_eq!(count(g.turn.other().into()), SECOND_TEAM_AGENTS);
+        assert_eq!(count(CardColor::Assassin), 1);
+        assert_eq!(count(CardColor::Neutral), 7);
+        assert_eq!(g.team(g.turn).agents_left, FIRST_TEAM_AGENTS);
+    }
+
+    #[test]
+    fn needs_enough_players_and_words() {
+        let three = players()[..3].to_vec();
+        assert!(Codenames::new(&three, &words(40)).is_err());
+        assert!(Codenames::new(&players(), &words(24)).is_err());
+
+        let mut repeated = words(24);
+        repeated.words.push("WORD0".to_owned());
+        assert!(Codenames::new(&players(), &repeated).is_err());
+    }
+
+    #[test]
+    fn only_spymasters_see_the_key_card() {
+        let g = game();
+        let red_spy = g.red.spymaster.clone();
+        let blue_op = g.blue.operatives[0].clone();
+
+        assert!(colors(&g.filter(&red_spy)).iter().all(|c| !c.is_null()));
+        assert!(colors(&g.filter(&blue_op)).iter().all(|c| c.is_null()));
+        assert!(colors(&g.filter("spectator")).iter().all(|c| c.is_null()));
+    }
+
+    #[test]
+    fn revealed_cards_show_their_color_to_everyone() {
+        let mut g = game();
+        clue(&mut g, 2);
+        let card = hidden(&g, CardColor::Neutral);
+        guess(&mut g, card).unwrap();
+
+        let view = g.filter(&operative(&g));
+        assert_eq!(colors(&view)[card], json!("Neutral"));
+        assert_eq!(colors(&view).iter().filter(|c| !c.is_null()).count(), 1);
+    }
+
+    #[test]
+    fn only_the_current_spymaster_gives_clues() {
+        let mut g = game();
+        let other = g.team(g.turn.other()).spymaster.clone();
+        let op = operative(&g);
+        for user in [other, op] {
+            let res = g.make_move(
+                &user,
+                json!({ "actionType": "giveClue", "data": { "word": "hint", "count": 1 } }),
+            );
+            assert!(matches!(res, Err(InvalidMove::NotYourTurn { .. })));
+        }
+    }
+
+    #[test]
+    fn clues_are_checked() {
+        let mut g = game();
+        let user = spymaster(&g);
+        let board_word = g.cards[0].word.to_uppercase();
+
+        for (word, count) in [
+            (board_word.as_str(), 1),
+            ("hint", 0),
+            ("hint", 10),
+            ("two words", 1),
+            ("", 1),
+        ] {
+            let res = g.make_move(
+                &user,
+                json!({ "actionType": "giveClue", "data": { "word": word, "count": count } }),
+            );
+            assert!(res.is_err(), "{} {}", word, count);
+        }
+        assert_eq!(g.phase, Phase::GivingClue);
+    }
+
+    #[test]
+    fn spymasters_cannot_guess() {
+        let mut g = game();
+        clue(&mut g, 1);
+        let user = spymaster(&g);
+        let res = g.make_move(&user, json!({ "actionType": "guess", "data": 0 }));
+        assert!(matches!(res, Err(InvalidMove::NotYourTurn { .. })));
+    }
+
+    #[test]
+    fn guessing_before_a_clue_is_refused() {
+        let mut g = game();
+        let res = guess(&mut g, 0);
+        assert!(matches!(res, Err(InvalidMove::WrongState { .. })));
+    }
+
+    #[test]
+    fn bad_cards_are_refused() {
+        let mut g = game();
+        clue(&mut g, 2);
+        let own = hidden(&g, g.turn.into());
+        guess(&mut g, own).unwrap();
+
+        assert!(matches!(
+            guess(&mut g, own),
+            Err(InvalidMove::NotAllowed { .. })
+        ));
+        assert!(matches!(
+            guess(&mut g, GRID_SIZE),
+            Err(InvalidMove::NotAllowed { .. })
+        ));
+    }
+
+    #[test]
+    fn operatives_get_one_extra_guess() {
+        let mut g = game();
+        let team = g.turn;
+        clue(&mut g, 1);
+
+        for _ in 0..2 {
+            assert_eq!(g.turn, team);
+            let card = hidden(&g, team.into());
+            guess(&mut g, card).unwrap();
+        }
+        assert_eq!(g.turn, team.other());
+        assert_eq!(g.phase, Phase::GivingClue);
+        assert_eq!(g.team(team).agents_left, FIRST_TEAM_AGENTS - 2);
+    }
+
+    #[test]
+    fn wrong_guess_ends_the_turn() {
+        let mut g = game();
+        let team = g.turn;
+        clue(&mut g, 3);
+        let card = hidden(&g, team.other().into());
+        guess(&mut g, card).unwrap();
+
+        assert_eq!(g.turn, team.other());
+        assert_eq!(g.team(team.other()).agents_left, SECOND_TEAM_AGENTS - 1);
+    }
+
+    #[test]
+    fn operatives_can_stop_guessing() {
+        let mut g = game();
+        let team = g.turn;
+        clue(&mut g, 3);
+        let user = operative(&g);
+        g.make_move(&user, json!({ "actionType": "endTurn" }))
+            .unwrap();
+        assert_eq!(g.turn, team.other());
+        assert_eq!(g.phase, Phase::GivingClue);
+    }
+
+    #[test]
+    fn assassin_loses_the_game() {
+        let mut g = game();
+        let team = g.turn;
+        clue(&mut g, 1);
+        let card = hidden(&g, CardColor::Assassin);
+        guess(&mut g, card).unwrap();
+
+        assert_eq!(g.phase, Phase::GameOver);
+        assert_eq!(g.winner, Some(team.other()));
+        assert!(g.assassin_found);
+        // Everyone sees the key once the game is over
+        assert!(colors(&g.filter("spectator")).iter().all(|c| !c.is_null()));
+    }
+
+    #[test]
+    fn finding_every_agent_wins() {
+        let mut g = game();
+        let team = g.turn;
+        let other = team.other();
+
+        clue(&mut g, 1);
+        let card = hidden(&g, CardColor::Neutral);
+        guess(&mut g, card).unwrap();
+        assert_eq!(g.turn, other);
+
+        clue(&mut g, MAX_CLUE_COUNT);
+        for _ in 0..SECOND_TEAM_AGENTS - 1 {
+            let card = hidden(&g, other.into());
+            guess(&mut g, card).unwrap();
+        }
+        assert_eq!(g.team(other).agents_left, 1);
+        assert_eq!(g.phase, Phase::Guessing);
+
+        let card = hidden(&g, other.into());
+        guess(&mut g, card).unwrap();
+        assert_eq!(g.winner, Some(other));
+        assert_eq!(g.phase, Phase::GameOver);
+
+        let res = g.make_move(&operative(&g), json!({ "actionType": "endTurn" }));
+        assert!(matches!(res, Err(InvalidMove::WrongState { .. })));
+    }
+
+    #[test]
+    fn unparseable_moves() {
+        let mut g = game();
+        let user = spymaster(&g);
+        for m in [
+            json!({ "actionType": "giveClue", "data": "hint" }),
+            json!({ "actionType": "guess", "data": "first" }),
+            json!({ "actionType": "guess", "data": -1 }),
+        ] {
+            let res = g.make_move(&user, m);
+            assert!(matches!(res, Err(InvalidMove::CouldNotParse { .. })));
+        }
+    }
+}
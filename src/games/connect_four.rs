@@ -0,0 +1,162 @@
+//! Connect Four: two players take turns dropping discs into the columns of
+//! an upright grid, the first with four in a row wins.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::{grid::GridGame, parse_move, to_json, Game, InvalidMove};
+
+const ROWS: usize = 6;
+const COLS: usize = 7;
+const LINE: usize = 4;
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ConnectFour {
+    #[serde(flatten)]
+    grid: GridGame,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "actionType", content = "data")]
+#[serde(rename_all = "camelCase")]
+pub enum ConnectFourMove {
+    /// Drop a disc into a column, counting from the left
+    Drop(usize),
+}
+
+impl ConnectFour {
+    pub fn new(players: &[String]) -> Result<ConnectFour, String> {
+        Ok(ConnectFour {
+            grid: GridGame::new("Connect Four", players, ROWS, COLS, LINE)?,
+        })
+    }
+
+    /// Discs fall to the lowest empty cell of the column
+    fn drop(&mut self, user: &str, col: usize) -> Result<(), InvalidMove> {
+        self.grid.check_turn(user)?;
+        if col >= COLS {
+            return Err(InvalidMove::NotAllowed {
+                msg: format!("There are {} columns", COLS),
+            });
+        }
+        let Some(row) = (0..ROWS).rev().find(|r| self.grid.cell(*r, col).is_none()) else {
+            return Err(InvalidMove::NotAllowed {
+                msg: "That column is full".to_owned(),
+            });
+        };
+
+        self.grid.claim(row, col);
+        Ok(())
+    }
+}
+
+impl Game for ConnectFour {
+    fn make_move(&mut self, user: &str, action: Value) -> Result<(), InvalidMove> {
+        match parse_move(action)? {
+            ConnectFourMove::Drop(col) => self.drop(user, col),
+        }
+    }
+
+    fn filter(&self, _user: &str) -> Value {
+        to_json(self)
+    }
+
+    /// Nothing is hidden so everyone shares one view
+    fn audience(&self, _user: &str) -> Option<String> {
+        Some("everyone".to_owned())
+    }
+
+    fn state(&self) -> Value {
+        to_json(self)
+    }
+
+    fn phase(&self) -> String {
+        format!("{:?}", self.grid.phase())
+    }
+
+    fn is_over(&self) -> bool {
+        self.grid.is_over()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::games::grid::Phase;
+    use serde_json::json;
+
+    fn game() -> ConnectFour {
+        ConnectFour::new(&["alice", "bob"].map(String::from)).unwrap()
+    }
+
+    /// Drop discs in turn, starting with whoever goes first
+    fn play(g: &mut ConnectFour, cols: &[usize]) {
+        for col in cols {
+            let user = g.grid.current().to_owned();
+            g.make_move(&user, json!({ "actionType": "drop", "data": col }))
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn discs_fall_to_the_bottom() {
+        let mut g = game();
+        let first = g.grid.current().to_owned();
+        play(&mut g, &[3, 3]);
+
+        assert!(g.grid.cell(ROWS - 1, 3).is_some());
+        assert!(g.grid.cell(ROWS - 2, 3).is_some());
+        assert_eq!(g.grid.cell(ROWS - 3, 3), None);
+        assert_eq!(g.grid.current(), first);
+    }
+
+    #[test]
+    fn four_in_a_column_wins() {
+        let mut g = game();
+        let first = g.grid.current().to_owned();
+        play(&mut g, &[0, 1, 0, 1, 0, 1, 0]);
+
+        assert_eq!(g.grid.phase(), Phase::GameOver);
+        assert_eq!(g.grid.winner(), Some(first.as_str()));
+    }
+
+    #[test]
+    fn four_on_a_diagonal_wins() {
+        let mut g = game();
+        let first = g.grid.current().to_owned();
+        play(&mut g, &[0, 1, 1, 2, 2, 3, 2, 3, 3, 6, 3]);
+
+        assert_eq!(g.grid.winner(), Some(first.as_str()));
+        assert_eq!(g.filter("alice")["line"].as_array().unwrap().len(), 4);
+    }
+
+    #[test]
+    fn full_columns_are_refused() {
+        let mut g = game();
+        play(&mut g, &[0; ROWS]);
+        let user = g.grid.current().to_owned();
+        assert!(matches!(
+            g.drop(&user, 0),
+            Err(InvalidMove::NotAllowed { .. })
+        ));
+        assert!(matches!(
+            g.drop(&user, COLS),
+            Err(InvalidMove::NotAllowed { .. })
+        ));
+    }
+
+    #[test]
+    fn full_board_is_a_draw() {
+        let mut g = game();
+        // Filling columns in pairs, shifted every other pair, never lines up four
+        let order = [0, 1, 0, 1, 0, 1, 1, 0, 1, 0, 1, 0];
+        for pair in [0, 2, 4] {
+            play(&mut g, &order.map(|c| c + pair));
+        }
+        play(&mut g, &[6; ROWS]);
+
+        assert_eq!(g.grid.phase(), Phase::GameOver);
+        assert_eq!(g.grid.winner(), None);
+    }
+}
@@ -0,0 +1,665 @@
+//! Decrypto: two teams each have four secret keywords. Every round each
+//! team's encryptor draws a three digit code and gives a clue for each digit
+//! hinting at the keyword in that position. Teams decode their own code and,
+//! once they've heard enough clues, try to intercept the other team's.
+
+use rand::{seq::SliceRandom, thread_rng};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashSet;
+
+use super::{just_one::validation, parse_move, to_json, word_list::WordList, Game, InvalidMove};
+use crate::text;
+
+const MIN_PLAYERS: usize = 4;
+const KEYWORDS: usize = 4;
+const CODE_LEN: usize = 3;
+const MAX_CLUE_CHARS: usize = 32;
+/// Teams can only try to intercept once they've heard a round of clues
+const FIRST_INTERCEPT_ROUND: u32 = 2;
+/// Tokens of either kind that end the game
+const TOKENS_TO_END: u32 = 2;
+/// The game is scored after this many rounds if nobody has won
+const MAX_ROUNDS: u32 = 8;
+
+/// Positions of keywords, from 1 to `KEYWORDS`
+pub type Code = [u8; CODE_LEN];
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Copy)]
+pub enum Phase {
+    Encrypting,
+    Guessing,
+    GameOver,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct TeamData {
+    players: Vec<String>,
+    /// Only the team sees its keywords until the game is over
+    keywords: Vec<String>,
+    encryptor: String,
+    /// Index of the player who encrypts next round
+    next_encryptor: usize,
+    /// Only the encryptor sees the code until the round is over
+    code: Option<Code>,
+    clues: Option<Vec<String>>,
+    /// The team's guess at its own code
+    decode: Option<Code>,
+    /// The team's guess at the other team's code
+    intercept: Option<Code>,
+    /// Times the team cracked the other team's code
+    interceptions: u32,
+    /// Times the team failed to decode its own code
+    miscommunications: u32,
+}
+
+/// A team's clues and the guesses at them, public once the round is over
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RoundRecord {
+    round: u32,
+    team: usize,
+    clues: Vec<String>,
+    code: Code,
+    decode: Option<Code>,
+    intercept: Option<Code>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Decrypto {
+    teams: [TeamData; 2],
+    round: u32,
+    phase: Phase,
+    history: Vec<RoundRecord>,
+    /// Index of the winning team, `None` at the end of the game is a draw
+    winner: Option<usize>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "actionType", content = "data")]
+#[serde(rename_all = "camelCase")]
+pub enum DecryptoMove {
+    /// One clue for each digit of the code, in order
+    Clues(Vec<String>),
+    Decode(Code),
+    Intercept(Code),
+}
+
+impl Decrypto {
+    /// Split `players` into two teams and deal each four keywords
+    pub fn new(players: &[String], words: &WordList) -> Result<Decrypto, String> {
+        if players.len() < MIN_PLAYERS {
+            return Err(format!("Decrypto needs at least {} players", MIN_PLAYERS));
+        }
+
+        let mut seen = HashSet::new();
+        let mut keywords: Vec<String> = words
+            .shuffled()
+            .words
+            .into_iter()
+            .filter(|w| seen.insert(validation::letters(w)))
+            .take(KEYWORDS * 2)
+            .collect();
+        if keywords.len() < KEYWORDS * 2 {
+            return Err(format!(
+                "Decrypto needs at least {} different words",
+                KEYWORDS * 2
+            ));
+        }
+
+        let mut players = players.to_vec();
+        players.shuffle(&mut thread_rng());
+        let mut team = |offset: usize| TeamData {
+            players: players.iter().skip(offset).step_by(2).cloned().collect(),
+            keywords: keywords.drain(..KEYWORDS).collect(),
+            encryptor: String::new(),
+            next_encryptor: 0,
+            code: None,
+            clues: None,
+            decode: None,
+            intercept: None,
+            interceptions: 0,
+            miscommunications: 0,
+        };
+
+        let mut game = Decrypto {
+            teams: [team(0), team(1)],
+            round: 0,
+            phase: Phase::Encrypting,
+            history: Vec::new(),
+            winner: None,
+        };
+        game.start_round();
+
+        Ok(game)
+    }
+
+    fn start_round(&mut self) {
+        let mut rng = thread_rng();
+        let positions: Vec<u8> = (1..=KEYWORDS as u8).collect();
+
+        self.round += 1;
+        for team in &mut self.teams {
+            team.encryptor = team.players[team.next_encryptor % team.players.len()].clone();
+            team.next_encryptor += 1;
+
+            let mut code = [0; CODE_LEN];
+            for (digit, pos) in code
+                .iter_mut()
+                .zip(positions.choose_multiple(&mut rng, CODE_LEN))
+            {
+                *digit = *pos;
+            }
+            team.code = Some(code);
+            team.clues = None;
+            team.decode = None;
+            team.intercept = None;
+        }
+        self.phase = Phase::Encrypting;
+    }
+
+    fn check_phase(&self, phase: Phase) -> Result<(), InvalidMove> {
+        if self.phase != phase {
+            return Err(InvalidMove::WrongState {
+                msg: format!("Can't do that during {:?}", self.phase),
+            });
+        }
+        Ok(())
+    }
+
+    fn team_of(&self, user: &str) -> Option<usize> {
+        self.teams
+            .iter()
+            .position(|t| t.players.iter().any(|p| p == user))
+    }
+
+    fn check_team(&self, user: &str) -> Result<usize, InvalidMove> {
+        self.team_of(user).ok_or_else(|| InvalidMove::InvalidUser {
+            msg: format!("{} is not playing", user),
+        })
+    }
+
+    fn is_encryptor(&self, user: &str) -> bool {
+        self.teams.iter().any(|t| t.encryptor == user)
+    }
+
+    fn clues(&mut self, user: &str, clues: Vec<String>) -> Result<(), InvalidMove> {
+        self.check_phase(Phase::Encrypting)?;
+        let t = self.check_team(user)?;
+        let team = &self.teams[t];
+        if team.encryptor != user {
+            return Err(InvalidMove::NotYourTurn {
+                msg: format!("{} is encrypting for your team", team.encryptor),
+            });
+        }
+        if team.clues.is_some() {
+            return Err(InvalidMove::WrongState {
+                msg: "Your team's clues are already in".to_owned(),
+            });
+        }
+        if clues.len() != CODE_LEN {
+            return Err(InvalidMove::NotAllowed {
+                msg: format!("Give one clue for each of the {} digits", CODE_LEN),
+            });
+        }
+
+        let clues = clues
+            .iter()
+            .map(|c| text::clean(c, MAX_CLUE_CHARS).map_err(|e| InvalidMove::from_text("Clue", e)))
+            .collect::<Result<Vec<_>, _>>()?;
+        if let Some(c) = clues
+            .iter()
+            .find(|c| team.keywords.iter().any(|k| validation::matches(c, k)))
+        {
+            return Err(InvalidMove::HintIsWord {
+                msg: format!("{} is one of your keywords", c),
+            });
+        }
+
+        self.teams[t].clues = Some(clues);
+        if self.teams.iter().all(|t| t.clues.is_some()) {
+            self.phase = Phase::Guessing;
+        }
+        Ok(())
+    }
+
+    /// Anyone on a team but its encryptor decodes the team's own code
+    fn decode(&mut self, user: &str, code: Code) -> Result<(), InvalidMove> {
+        self.check_phase(Phase::Guessing)?;
+        let t = self.check_team(user)?;
+        if self.teams[t].encryptor == user {
+            return Err(InvalidMove::NotYourTurn {
+                msg: "The encryptor already knows the code".to_owned(),
+            });
+        }
+        if self.teams[t].decode.is_some() {
+            return Err(InvalidMove::WrongState {
+                msg: "Your team has already decoded".to_owned(),
+            });
+        }
+        check_code(&code)?;
+
+        self.teams[t].decode = Some(code);
+        self.try_finish_round();
+        Ok(())
+    }
+
+    fn intercept(&mut self, user: &str, code: Code) -> Result<(), InvalidMove> {
+        self.check_phase(Phase::Guessing)?;
+        let t = self.check_team(user)?;
+        if self.round < FIRST_INTERCEPT_ROUND {
+            return Err(InvalidMove::NotAllowed {
+                msg: format!("Intercepting starts in round {}", FIRST_INTERCEPT_ROUND),
+            });
+        }
+        if self.teams[t].intercept.is_some() {
+            return Err(InvalidMove::WrongState {
+                msg: "Your team has already intercepted".to_owned(),
+            });
+        }
+        check_code(&code)?;
+
+        self.teams[t].intercept = Some(code);
+        self.try_finish_round();
+        Ok(())
+    }
+
+    /// Score the round once every team has made all its guesses
+    fn try_finish_round(&mut self) {
+        let intercepting = self.round >= FIRST_INTERCEPT_ROUND;
+        if !self
+            .teams
+            .iter()
+            .all(|t| t.decode.is_some() && (t.intercept.is_some() || !intercepting))
+        {
+            return;
+        }
+
+        for t in 0..self.teams.len() {
+            let o = 1 - t;
+            let code = self.teams[t].code.expect("Every team has a code");
+            let intercept = self.teams[o].intercept;
+            if self.teams[t].decode != Some(code) {
+                self.teams[t].miscommunications += 1;
+            }
+            if intercept == Some(code) {
+                self.teams[o].interceptions += 1;
+            }
+
+            let team = &self.teams[t];
+            self.history.push(RoundRecord {
+                round: self.round,
+                team: t,
+                clues: team.clues.clone().unwrap_or_default(),
+                code,
+                decode: team.decode,
+                intercept,
+            });
+        }
+
+        let ended = self
+            .teams
+            .iter()
+            .any(|t| t.interceptions >= TOKENS_TO_END || t.miscommunications >= TOKENS_TO_END);
+        if ended || self.round >= MAX_ROUNDS {
+            self.finish();
+        } else {
+            self.start_round();
+        }
+    }
+
+    /// The team with more interceptions than miscommunications wins, when
+    /// both teams are level the game is a draw
+    fn finish(&mut self) {
+        let score = |t: &TeamData| t.interceptions as i64 - t.miscommunications as i64;
+        let (a, b) = (score(&self.teams[0]), score(&self.teams[1]));
+        self.winner = match a.cmp(&b) {
+            std::cmp::Ordering::Greater => Some(0),
+            std::cmp::Ordering::Less => Some(1),
+            std::cmp::Ordering::Equal => None,
+        };
+        self.phase = Phase::GameOver;
+    }
+
+    /// What everyone on team `team` sees, or spectators for `None`
+    fn team_view(&self, team: Option<usize>) -> Decrypto {
+        let mut view = self.clone();
+        for (t, data) in view.teams.iter_mut().enumerate() {
+            data.code = None;
+            if Some(t) == team {
+                continue;
+            }
+            data.keywords.clear();
+            if self.phase == Phase::Encrypting {
+                data.clues = None;
+            }
+            data.decode = None;
+            data.intercept = None;
+        }
+        view
+    }
+}
+
+fn check_code(code: &Code) -> Result<(), InvalidMove> {
+    let distinct: HashSet<_> = code.iter().collect();
+    if distinct.len() != CODE_LEN || code.iter().any(|d| !(1..=KEYWORDS as u8).contains(d)) {
+        return Err(InvalidMove::NotAllowed {
+            msg: format!(
+                "Codes are {} different digits from 1 to {}",
+                CODE_LEN, KEYWORDS
+            ),
+        });
+    }
+    Ok(())
+}
+
+impl Game for Decrypto {
+    fn make_move(&mut self, user: &str, action: Value) -> Result<(), InvalidMove> {
+        match parse_move(action)? {
+            DecryptoMove::Clues(clues) => self.clues(user, clues),
+            DecryptoMove::Decode(code) => self.decode(user, code),
+            DecryptoMove::Intercept(code) => self.intercept(user, code),
+        }
+    }
+
+    /// Teams see their own keywords, clues and guesses. Only the encryptor
+    /// sees their team's code.
+    fn filter(&self, user: &str) -> Value {
+        if self.phase == Phase::GameOver {
+            return to_json(self);
+        }
+
+        let team = self.team_of(user);
+        let mut view = self.team_view(team);
+        if let Some(t) = team.filter(|t| self.teams[*t].encryptor == user) {
+            view.teams[t].code = self.teams[t].code;
+        }
+        to_json(&view)
+    }
+
+    /// Everyone on a team sees the same thing, apart from the encryptors
+    fn audience(&self, user: &str) -> Option<String> {
+        if self.phase == Phase::GameOver {
+            return Some("everyone".to_owned());
+        }
+        if self.is_encryptor(user) {
+            return None;
+        }
+        Some(match self.team_of(user) {
+            Some(t) => format!("team {}", t),
+            None => "spectators".to_owned(),
+        })
+    }
+
+    fn state(&self) -> Value {
+        to_json(self)
+    }
+
+    fn phase(&self) -> String {
+        format!("{:?}", self.phase)
+    }
+
+    fn is_over(&self) -> bool {
+        self.phase == Phase::GameOver
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn players() -> Vec<String> {
+        ["alice", "bob", "carol", "dave"].map(String::from).to_vec()
+    }
+
+    fn words(n: usize) -> WordList {
+        WordList {
+            description: "Test".to_owned(),
+            words: (0..n).map(|i| format!("word{}", i)).collect(),
+        }
+    }
+
+    fn game() -> Decrypto {
+        Decrypto::new(&players(), &words(20)).unwrap()
+    }
+
+    fn encryptor(g: &Decrypto, t: usize) -> String {
+        g.teams[t].encryptor.clone()
+    }
+
+    /// A player on team `t` who isn't encrypting
+    fn guesser(g: &Decrypto, t: usize) -> String {
+        let team = &g.teams[t];
+        team.players
+            .iter()
+            .find(|p| **p != team.encryptor)
+            .cloned()
+            .unwrap()
+    }
+
+    fn code(g: &Decrypto, t: usize) -> Code {
+        g.teams[t].code.unwrap()
+    }
+
+    /// Some code other than `code`
+    fn wrong(code: Code) -> Code {
+        [code[1], code[0], code[2]]
+    }
+
+    fn give_clues(g: &mut Decrypto) {
+        for t in 0..2 {
+            let clues = json!({ "actionType": "clues", "data": ["sun", "moon", "star"] });
+            g.make_move(&encryptor(g, t), clues).unwrap();
+        }
+    }
+
+    /// Play a round where each team decodes as given and intercepts when it
+    /// can, correctly if `intercepts` says so
+    fn play_round(g: &mut Decrypto, decodes: [bool; 2], intercepts: [bool; 2]) {
+        give_clues(g);
+        let round = g.round;
+        for t in 0..2 {
+            let own = code(g, t);
+            let theirs = code(g, 1 - t);
+            let user = guesser(g, t);
+            g.decode(&user, if decodes[t] { own } else { wrong(own) })
+                .unwrap();
+            if round >= FIRST_INTERCEPT_ROUND {
+                let guess = if intercepts[t] { theirs } else { wrong(theirs) };
+                g.intercept(&user, guess).unwrap();
+            }
+        }
+    }
+
+    /// Both teams decode correctly, for rounds without interceptions
+    fn play_round_guesses(g: &mut Decrypto) {
+        for t in 0..2 {
+            let own = code(g, t);
+            g.decode(&guesser(g, t), own).unwrap();
+        }
+    }
+
+    #[test]
+    fn teams_get_different_keywords() {
+        let g = game();
+        assert_eq!(g.teams[0].players.len(), 2);
+        assert_eq!(g.teams[0].keywords.len(), KEYWORDS);
+        assert!(g.teams[0]
+            .keywords
+            .iter()
+            .all(|k| !g.teams[1].keywords.contains(k)));
+    }
+
+    #[test]
+    fn needs_enough_players_and_words() {
+        assert!(Decrypto::new(&players()[..3], &words(20)).is_err());
+        assert!(Decrypto::new(&players(), &words(7)).is_err());
+    }
+
+    #[test]
+    fn codes_are_different_digits() {
+        for _ in 0..20 {
+            assert!(check_code(&code(&game(), 0)).is_ok());
+        }
+        assert!(check_code(&[1, 1, 2]).is_err());
+        assert!(check_code(&[0, 1, 2]).is_err());
+        assert!(check_code(&[5, 1, 2]).is_err());
+    }
+
+    #[test]
+    fn keywords_are_only_seen_by_teammates() {
+        let g = game();
+        let mate = guesser(&g, 0);
+
+        let view = g.filter(&mate);
+        assert_eq!(view["teams"][0]["keywords"], json!(g.teams[0].keywords));
+        assert_eq!(view["teams"][1]["keywords"], json!([]));
+        assert_eq!(g.filter("spectator")["teams"][0]["keywords"], json!([]));
+    }
+
+    #[test]
+    fn only_the_encryptor_sees_the_code() {
+        let g = game();
+        let view = g.filter(&encryptor(&g, 0));
+        assert_eq!(view["teams"][0]["code"], json!(code(&g, 0)));
+        assert_eq!(view["teams"][1]["code"], Value::Null);
+        assert_eq!(g.filter(&guesser(&g, 0))["teams"][0]["code"], Value::Null);
+    }
+
+    #[test]
+    fn teammates_share_a_view() {
+        let g = game();
+        let mate = guesser(&g, 0);
+        assert_eq!(g.audience(&mate), Some("team 0".to_owned()));
+        assert_eq!(g.audience(&encryptor(&g, 0)), None);
+        assert_ne!(g.audience(&mate), g.audience(&guesser(&g, 1)));
+        assert_ne!(g.audience(&mate), g.audience("spectator"));
+    }
+
+    #[test]
+    fn clues_are_checked() {
+        let mut g = game();
+        let enc = encryptor(&g, 0);
+        let keyword = g.teams[0].keywords[0].to_uppercase();
+
+        let res = g.clues(&guesser(&g, 0), vec!["a".into(), "b".into(), "c".into()]);
+        assert!(matches!(res, Err(InvalidMove::NotYourTurn { .. })));
+        let res = g.clues(&enc, vec!["a".into(), "b".into()]);
+        assert!(matches!(res, Err(InvalidMove::NotAllowed { .. })));
+        let res = g.clues(&enc, vec!["a".into(), "".into(), "c".into()]);
+        assert!(matches!(res, Err(InvalidMove::EmptyInput { .. })));
+        let res = g.clues(&enc, vec!["a".into(), keyword, "c".into()]);
+        assert!(matches!(res, Err(InvalidMove::HintIsWord { .. })));
+    }
+
+    #[test]
+    fn clues_are_hidden_from_the_other_team_until_both_are_in() {
+        let mut g = game();
+        let clues = vec!["sun".to_owned(), "moon".into(), "star".into()];
+        g.clues(&encryptor(&g, 0), clues.clone()).unwrap();
+        assert_eq!(g.phase, Phase::Encrypting);
+        assert_eq!(g.filter(&guesser(&g, 0))["teams"][0]["clues"], json!(clues));
+        assert_eq!(g.filter(&guesser(&g, 1))["teams"][0]["clues"], Value::Null);
+
+        g.clues(&encryptor(&g, 1), clues.clone()).unwrap();
+        assert_eq!(g.phase, Phase::Guessing);
+        assert_eq!(g.filter(&guesser(&g, 1))["teams"][0]["clues"], json!(clues));
+    }
+
+    #[test]
+    fn no_interceptions_in_the_first_round() {
+        let mut g = game();
+        give_clues(&mut g);
+        let user = guesser(&g, 0);
+        let theirs = code(&g, 1);
+        assert!(matches!(
+            g.intercept(&user, theirs),
+            Err(InvalidMove::NotAllowed { .. })
+        ));
+        assert!(matches!(
+            g.decode(&encryptor(&g, 0), code(&g, 0)),
+            Err(InvalidMove::NotYourTurn { .. })
+        ));
+
+        play_round_guesses(&mut g);
+        assert_eq!(g.round, 2);
+        assert_eq!(g.phase, Phase::Encrypting);
+        assert_eq!(g.history.len(), 2);
+    }
+
+    #[test]
+    fn guesses_are_hidden_from_the_other_team() {
+        let mut g = game();
+        give_clues(&mut g);
+        let own = code(&g, 0);
+        g.decode(&guesser(&g, 0), own).unwrap();
+
+        assert_eq!(g.filter(&guesser(&g, 0))["teams"][0]["decode"], json!(own));
+        assert_eq!(g.filter(&guesser(&g, 1))["teams"][0]["decode"], Value::Null);
+        assert!(matches!(
+            g.decode(&guesser(&g, 0), own),
+            Err(InvalidMove::WrongState { .. })
+        ));
+    }
+
+    #[test]
+    fn encryptors_rotate() {
+        let mut g = game();
+        let first = encryptor(&g, 0);
+        play_round(&mut g, [true, true], [false, false]);
+        assert_ne!(encryptor(&g, 0), first);
+        play_round(&mut g, [true, true], [false, false]);
+        assert_eq!(encryptor(&g, 0), first);
+    }
+
+    #[test]
+    fn two_interceptions_win() {
+        let mut g = game();
+        play_round(&mut g, [true, true], [false, false]);
+        play_round(&mut g, [true, true], [true, false]);
+        assert_eq!(g.teams[0].interceptions, 1);
+        assert_eq!(g.phase, Phase::Encrypting);
+
+        play_round(&mut g, [true, true], [true, false]);
+        assert_eq!(g.phase, Phase::GameOver);
+        assert_eq!(g.winner, Some(0));
+        // Everything is public at the end
+        assert_eq!(
+            g.filter("spectator")["teams"][1]["keywords"],
+            json!(g.teams[1].keywords)
+        );
+    }
+
+    #[test]
+    fn two_miscommunications_lose() {
+        let mut g = game();
+        play_round(&mut g, [false, true], [false, false]);
+        play_round(&mut g, [false, true], [false, false]);
+        assert_eq!(g.teams[0].miscommunications, 2);
+        assert_eq!(g.phase, Phase::GameOver);
+        assert_eq!(g.winner, Some(1));
+    }
+
+    #[test]
+    fn level_teams_draw() {
+        let mut g = game();
+        play_round(&mut g, [true, true], [false, false]);
+        play_round(&mut g, [true, true], [true, true]);
+        play_round(&mut g, [true, true], [true, true]);
+        assert_eq!(g.phase, Phase::GameOver);
+        assert_eq!(g.winner, None);
+    }
+
+    #[test]
+    fn game_ends_after_the_last_round() {
+        let mut g = game();
+        for _ in 0..MAX_ROUNDS {
+            play_round(&mut g, [true, true], [false, false]);
+        }
+        assert_eq!(g.phase, Phase::GameOver);
+        assert_eq!(g.history.len(), 2 * MAX_ROUNDS as usize);
+    }
+}
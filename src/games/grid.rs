@@ -0,0 +1,212 @@
+//! Board and turn keeping shared by the two player grid games. Two players
+//! take turns claiming cells and the first to hold a long enough line wins.
+//! Anyone else in the lobby watches.
+
+use rand::{thread_rng, Rng};
+use serde::{Deserialize, Serialize};
+
+use super::InvalidMove;
+
+/// Directions a line can run in: across, down and both diagonals
+const DIRECTIONS: [(isize, isize); 4] = [(0, 1), (1, 0), (1, 1), (1, -1)];
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Copy)]
+pub enum Phase {
+    Playing,
+    GameOver,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct GridGame {
+    /// The two players, indexed by the numbers in `cells`
+    players: [String; 2],
+    spectators: Vec<String>,
+    /// Index of the player whose turn it is
+    turn: usize,
+    rows: usize,
+    cols: usize,
+    /// Row by row from the top, the player holding each cell
+    cells: Vec<Option<usize>>,
+    /// Cells making up the winning line
+    line: Vec<usize>,
+    /// `None` at the end of the game is a draw
+    winner: Option<usize>,
+    phase: Phase,
+
+    #[serde(skip)]
+    line_len: usize,
+}
+
+impl GridGame {
+    /// Seat the first two of `players`, the rest watch. Who goes first is
+    /// random.
+    pub fn new(
+        name: &str,
+        players: &[String],
+        rows: usize,
+        cols: usize,
+        line_len: usize,
+    ) -> Result<GridGame, String> {
+        let [a, b, ..] = players else {
+            return Err(format!("{} needs 2 players", name));
+        };
+
+        Ok(GridGame {
+            players: [a.clone(), b.clone()],
+            spectators: players[2..].to_vec(),
+            turn: thread_rng().gen_range(0..2),
+            rows,
+            cols,
+            cells: vec![None; rows * cols],
+            line: Vec::new(),
+            winner: None,
+            phase: Phase::Playing,
+            line_len,
+        })
+    }
+
+    pub fn phase(&self) -> Phase {
+        self.phase
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.phase == Phase::GameOver
+    }
+
+    /// The player whose turn it is
+    pub fn current(&self) -> &str {
+        &self.players[self.turn]
+    }
+
+    #[cfg(test)]
+    pub fn winner(&self) -> Option<&str> {
+        self.winner.map(|w| self.players[w].as_str())
+    }
+
+    /// Check it's `user`'s turn
+    pub fn check_turn(&self, user: &str) -> Result<(), InvalidMove> {
+        if self.phase != Phase::Playing {
+            return Err(InvalidMove::WrongState {
+                msg: "The game is over".to_owned(),
+            });
+        }
+        if !self.players.iter().any(|p| p == user) {
+            return Err(InvalidMove::InvalidUser {
+                msg: format!("{} is watching", user),
+            });
+        }
+        if self.players[self.turn] != user {
+            return Err(InvalidMove::NotYourTurn {
+                msg: format!("It's {}'s turn", self.players[self.turn]),
+            });
+        }
+        Ok(())
+    }
+
+    pub fn cell(&self, row: usize, col: usize) -> Option<usize> {
+        self.cells[row * self.cols + col]
+    }
+
+    /// Claim an empty cell for the player whose turn it is, then end the game
+    /// on a win or a full board, or pass the turn on
+    pub fn claim(&mut self, row: usize, col: usize) {
+        self.cells[row * self.cols + col] = Some(self.turn);
+
+        let line = self.line_through(row, col);
+        if line.len() >= self.line_len {
+            self.line = line;
+            self.winner = Some(self.turn);
+            self.phase = Phase::GameOver;
+        } else if self.cells.iter().all(Option::is_some) {
+            self.phase = Phase::GameOver;
+        } else {
+            self.turn = 1 - self.turn;
+        }
+    }
+
+    /// The longest line of cells through `(row, col)` held by its owner
+    fn line_through(&self, row: usize, col: usize) -> Vec<usize> {
+        let owner = self.cell(row, col);
+        let mut best = Vec::new();
+
+        for (dr, dc) in DIRECTIONS {
+            let mut line = vec![row * self.cols + col];
+            for sign in [1, -1] {
+                let (mut r, mut c) = (row as isize, col as isize);
+                loop {
+                    r += dr * sign;
+                    c += dc * sign;
+                    if r < 0 || c < 0 || r >= self.rows as isize || c >= self.cols as isize {
+                        break;
+                    }
+                    let (r, c) = (r as usize, c as usize);
+                    if self.cell(r, c) != owner {
+                        break;
+                    }
+                    line.push(r * self.cols + c);
+                }
+            }
+            if line.len() > best.len() {
+                best = line;
+            }
+        }
+
+        best.sort_unstable();
+        best
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn game(players: &[&str]) -> Result<GridGame, String> {
+        let players: Vec<String> = players.iter().map(|p| p.to_string()).collect();
+        GridGame::new("Test", &players, 3, 3, 3)
+    }
+
+    #[test]
+    fn two_players_and_the_rest_watch() {
+        assert!(game(&["alice"]).is_err());
+
+        let g = game(&["alice", "bob", "carol"]).unwrap();
+        assert_eq!(g.players, ["alice", "bob"]);
+        assert_eq!(g.spectators, ["carol"]);
+        assert!(matches!(
+            g.check_turn("carol"),
+            Err(InvalidMove::InvalidUser { .. })
+        ));
+    }
+
+    #[test]
+    fn lines_are_found_in_every_direction() {
+        let lines = [
+            [(1, 0), (1, 1), (1, 2)],
+            [(0, 2), (1, 2), (2, 2)],
+            [(0, 0), (1, 1), (2, 2)],
+            [(0, 2), (1, 1), (2, 0)],
+        ];
+        for cells in lines {
+            let mut g = game(&["alice", "bob"]).unwrap();
+            for (r, c) in cells {
+                g.turn = 0;
+                g.claim(r, c);
+            }
+            assert_eq!(g.winner, Some(0), "{:?}", cells);
+            assert_eq!(g.line.len(), 3);
+        }
+    }
+
+    #[test]
+    fn turns_alternate() {
+        let mut g = game(&["alice", "bob"]).unwrap();
+        g.turn = 0;
+        g.claim(0, 0);
+        assert!(matches!(
+            g.check_turn("alice"),
+            Err(InvalidMove::NotYourTurn { .. })
+        ));
+        assert!(g.check_turn("bob").is_ok());
+    }
+}
@@ -1,5 +1,10 @@
+mod duplicates;
 mod game_data;
+mod hint_check;
 mod round;
+pub(crate) mod validation;
 
-pub use game_data::GameData;
-pub use round::RoundData;
+pub use duplicates::DuplicateRules;
+pub use game_data::{GameData, JustOneMove};
+pub use hint_check::HintChecker;
+pub use round::RoundState;
@@ -0,0 +1,187 @@
+use serde::{Deserialize, Serialize};
+
+use super::{
+    hint_check::{stem, MIN_STEM_CHARS},
+    validation::is_plural_of,
+};
+use crate::text;
+
+/// How hints are compared when looking for duplicates. Unicode forms and
+/// surrounding whitespace are always normalised.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(default, rename_all = "camelCase", deny_unknown_fields)]
+pub struct DuplicateRules {
+    // Snake case aliases let the server config use its usual key style
+    #[serde(alias = "ignore_case")]
+    pub ignore_case: bool,
+    /// Treat "café" and "cafe" as the same hint
+    #[serde(alias = "ignore_accents")]
+    pub ignore_accents: bool,
+    /// Treat plurals and words sharing a stem, like "runner" and "running",
+    /// as the same hint
+    pub stemming: bool,
+}
+
+impl Default for DuplicateRules {
+    fn default() -> Self {
+        DuplicateRules {
+            ignore_case: true,
+            ignore_accents: true,
+            stemming: true,
+        }
+    }
+}
+
+impl DuplicateRules {
+    /// Whether each hint matches any of the others
+    pub fn mark<S: AsRef<str>>(&self, hints: &[S]) -> Vec<bool> {
+        let keys: Vec<String> = hints.iter().map(|h| self.key(h.as_ref())).collect();
+
+        keys.iter()
+            .enumerate()
+            .map(|(i, a)| {
+                keys.iter()
+                    .enumerate()
+                    .any(|(j, b)| i != j && self.same(a, b))
+            })
+            .collect()
+    }
+
+    fn key(&self, hint: &str) -> String {
+        let mut key = text::normalize(hint);
+        if self.ignore_accents {
+            key = text::strip_accents(&key);
+        }
+        if self.ignore_case {
+            key = key.to_lowercase();
+        }
+
+        key
+    }
+
+    fn same(&self, a: &str, b: &str) -> bool {
+        a == b || (self.stemming && (is_plural_of(a, b) || is_plural_of(b, a) || same_stem(a, b)))
+    }
+}
+
+fn same_stem(a: &str, b: &str) -> bool {
+    let stem = stem(a);
+    stem.chars().count() >= MIN_STEM_CHARS && stem == self::stem(b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mark(hints: &[&str]) -> Vec<bool> {
+        DuplicateRules::default().mark(hints)
+    }
+
+    #[test]
+    fn identical_hints() {
+        assert_eq!(mark(&["apple", "apple"]), [true, true]);
+    }
+
+    #[test]
+    fn distinct_hints() {
+        assert_eq!(mark(&["apple", "pear", "plum"]), [false, false, false]);
+    }
+
+    #[test]
+    fn non_adjacent_hints() {
+        assert_eq!(
+            mark(&["apple", "pear", "plum", "apple"]),
+            [true, false, false, true]
+        );
+        assert_eq!(
+            mark(&["pear", "apple", "plum", "pear", "apple"]),
+            [true, true, false, true, true]
+        );
+    }
+
+    #[test]
+    fn case() {
+        assert_eq!(mark(&["Apple", "aPPLE"]), [true, true]);
+    }
+
+    #[test]
+    fn whitespace() {
+        assert_eq!(mark(&["  apple", "apple\n"]), [true, true]);
+    }
+
+    #[test]
+    fn unicode_forms() {
+        // Full width letters and a precomposed vs combining accent
+        assert_eq!(mark(&["ａｐｐｌｅ", "apple"]), [true, true]);
+        assert_eq!(mark(&["caf\u{e9}", "cafe\u{301}"]), [true, true]);
+    }
+
+    #[test]
+    fn diacritics() {
+        assert_eq!(mark(&["café", "cafe"]), [true, true]);
+        assert_eq!(mark(&["Über", "uber"]), [true, true]);
+    }
+
+    #[test]
+    fn plurals() {
+        assert_eq!(mark(&["dog", "dogs"]), [true, true]);
+        assert_eq!(mark(&["boxes", "box"]), [true, true]);
+        assert_eq!(mark(&["berries", "berry"]), [true, true]);
+        assert_eq!(mark(&["horses", "horse"]), [true, true]);
+    }
+
+    #[test]
+    fn stems() {
+        assert_eq!(mark(&["running", "runner"]), [true, true]);
+        assert_eq!(mark(&["happiness", "happy"]), [true, true]);
+        assert_eq!(mark(&["sing", "song"]), [false, false]);
+    }
+
+    #[test]
+    fn short_stems_are_not_matched() {
+        assert_eq!(mark(&["bed", "bee"]), [false, false]);
+    }
+
+    #[test]
+    fn case_sensitive() {
+        let rules = DuplicateRules {
+            ignore_case: false,
+            ..DuplicateRules::default()
+        };
+        assert_eq!(rules.mark(&["Apple", "apple"]), [false, false]);
+        assert_eq!(rules.mark(&["apple", "apple"]), [true, true]);
+    }
+
+    #[test]
+    fn accent_sensitive() {
+        let rules = DuplicateRules {
+            ignore_accents: false,
+            ..DuplicateRules::default()
+        };
+        assert_eq!(rules.mark(&["café", "cafe"]), [false, false]);
+        assert_eq!(rules.mark(&["Café", "café"]), [true, true]);
+    }
+
+    #[test]
+    fn without_stemming() {
+        let rules = DuplicateRules {
+            stemming: false,
+            ..DuplicateRules::default()
+        };
+        assert_eq!(rules.mark(&["dog", "dogs"]), [false, false]);
+        assert_eq!(rules.mark(&["running", "runner"]), [false, false]);
+        assert_eq!(rules.mark(&["Dog", "dog"]), [true, true]);
+    }
+
+    #[test]
+    fn rules_from_json() {
+        let rules: DuplicateRules = serde_json::from_str(r#"{"ignoreAccents": false}"#).unwrap();
+        assert_eq!(
+            rules,
+            DuplicateRules {
+                ignore_accents: false,
+                ..DuplicateRules::default()
+            }
+        );
+    }
+}
@@ -1,10 +1,10 @@
-use super::round::*;
+use super::{duplicates::DuplicateRules, hint_check::HintChecker, round::*};
 
-use rand::{seq::SliceRandom, thread_rng};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::sync::Arc;
-use tokio::fs::read_to_string;
+
+use crate::games::{parse_move, to_json, word_list::WordList, Game, InvalidMove};
 
 #[derive(Serialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
@@ -18,11 +18,15 @@ pub struct GameData<'a> {
     words: Arc<WordList>,
     #[serde(skip)]
     cur_word: usize,
+    #[serde(skip)]
+    checker: Arc<HintChecker>,
+    #[serde(skip)]
+    duplicates: DuplicateRules,
 }
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(tag = "actionType", content = "data")]
 #[serde(rename_all = "camelCase")]
-enum JustOneMove {
+pub enum JustOneMove {
     Guess(String),
     Hint(String),
     #[serde(rename_all = "camelCase")]
@@ -39,32 +43,25 @@ enum JustOneMove {
     NextRound,
 }
 
-#[derive(Deserialize, Debug, Clone)]
-struct WordList {
-    description: String,
-    words: Vec<String>,
-}
-
 impl<'a> GameData<'a> {
-    pub async fn new(players: &'a Vec<String>) -> GameData<'a> {
-        let words = read_to_string("assets/nouns.json")
-            .await
-            .expect("Expected nouns file to exist");
-
-        let mut word_list: WordList =
-            serde_json::from_str(&words).expect("Could not parse words from file");
-        word_list.words.shuffle(&mut thread_rng());
-
+    pub fn new(
+        players: &'a Vec<String>,
+        words: &WordList,
+        checker: Arc<HintChecker>,
+        duplicates: DuplicateRules,
+    ) -> GameData<'a> {
         let mut game = GameData {
             players,
             round: 0,
             rounds: Vec::new(),
-            words: Arc::new(word_list),
+            words: Arc::new(words.shuffled()),
             cur_word: 0,
+            checker,
+            duplicates,
         };
         game.new_round();
 
-        return game;
+        game
     }
 
     fn new_round(&mut self) {
@@ -76,13 +73,20 @@ impl<'a> GameData<'a> {
                 .get(self.cur_word % self.words.words.len())
                 .expect("Index out of range")
                 .to_owned(),
+            self.checker.clone(),
+            self.duplicates,
         ));
         self.cur_word += 1;
         self.round += 1;
     }
 
     fn cur_round(&mut self) -> &mut RoundData<'a> {
-        return &mut self.rounds[self.round - 1];
+        &mut self.rounds[self.round - 1]
+    }
+
+    /// State of the round being played
+    pub fn round_state(&self) -> Option<RoundState> {
+        self.rounds.last().map(|r| r.state())
     }
 
     pub fn filter(&self, user: &str) -> GameData<'a> {
@@ -92,34 +96,242 @@ impl<'a> GameData<'a> {
             rounds.push(last.filter(user))
         }
 
-        return GameData {
+        GameData {
             players: self.players,
             round: self.round,
             rounds,
             words: self.words.clone(),
             cur_word: self.cur_word,
+            checker: self.checker.clone(),
+            duplicates: self.duplicates,
+        }
+    }
+}
+
+impl<'a> Game for GameData<'a> {
+    fn make_move(&mut self, user: &str, action: Value) -> Result<(), InvalidMove> {
+        let cur_roud = self.cur_round();
+
+        match parse_move(action)? {
+            JustOneMove::Guess(guess) => cur_roud.guess(user, guess),
+            JustOneMove::Hint(hint) => cur_roud.give_hint(user, hint),
+            JustOneMove::SetDuplicate { hint_id } => cur_roud.set_duplicate(user, &hint_id),
+            JustOneMove::SetUnique { hint_id } => cur_roud.set_unique(user, &hint_id),
+            JustOneMove::RevealHints => cur_roud.done_removing_dupes(user),
+            JustOneMove::CorrectGuess => cur_roud.set_guess_correctness(user, true),
+            JustOneMove::WrongGuess => cur_roud.set_guess_correctness(user, false),
+            JustOneMove::NextRound => {
+                self.new_round();
+                Ok(())
+            }
+        }
+    }
+
+    fn filter(&self, user: &str) -> Value {
+        to_json(&GameData::filter(self, user))
+    }
+
+    fn state(&self) -> Value {
+        to_json(self)
+    }
+
+    fn phase(&self) -> String {
+        self.round_state()
+            .map(|s| format!("{:?}", s))
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn players() -> Vec<String> {
+        ["alice", "bob", "carol"].map(String::from).to_vec()
+    }
+
+    fn game(players: &Vec<String>) -> GameData<'_> {
+        let words = WordList {
+            description: "Test".to_owned(),
+            words: vec!["Apple".to_owned()],
         };
+        GameData::new(
+            players,
+            &words,
+            Arc::new(HintChecker::default()),
+            DuplicateRules::default(),
+        )
+    }
+
+    fn play(g: &mut GameData, user: &str, m: serde_json::Value) {
+        g.make_move(user, m).unwrap();
+    }
+
+    fn guesser(g: &GameData, round: usize) -> String {
+        let state = serde_json::to_value(g).unwrap();
+        state["rounds"][round]["guesser"]
+            .as_str()
+            .unwrap()
+            .to_owned()
+    }
+
+    /// Everything the user would receive, as it is sent over the wire
+    fn view(g: &GameData, user: &str) -> String {
+        serde_json::to_string(&g.filter(user)).unwrap()
     }
 
-    pub fn make_move(&mut self, req_uid: &String, m: Value) -> Result<&GameData<'a>, InvalidMove> {
-        let res = serde_json::from_value(m);
+    #[test]
+    fn first_player_guesses_first() {
+        let players = players();
+        let g = game(&players);
+        assert_eq!(guesser(&g, 0), "alice");
+        assert_eq!(g.round_state(), Some(RoundState::GivingHints));
+    }
 
-        if let Err(e) = res {
-            return Err(InvalidMove::CouldNotParse { msg: e.to_string() });
+    #[test]
+    fn unparseable_moves() {
+        let players = players();
+        let mut g = game(&players);
+        for m in [
+            json!("hint"),
+            json!({ "actionType": "dance" }),
+            json!({ "actionType": "hint" }),
+            json!({ "actionType": "hint", "data": 3 }),
+        ] {
+            let res = g.make_move("bob", m);
+            assert!(matches!(res, Err(InvalidMove::CouldNotParse { .. })));
         }
+    }
 
-        let cur_roud = self.cur_round();
+    #[test]
+    fn full_round() {
+        let players = players();
+        let mut g = game(&players);
+
+        play(
+            &mut g,
+            "bob",
+            json!({ "actionType": "hint", "data": "fruit" }),
+        );
+        play(
+            &mut g,
+            "carol",
+            json!({ "actionType": "hint", "data": "tree" }),
+        );
+        assert_eq!(g.round_state(), Some(RoundState::RemovingDuplicates));
+
+        play(
+            &mut g,
+            "bob",
+            json!({ "actionType": "setDuplicate", "data": { "hintId": "carol" } }),
+        );
+        play(
+            &mut g,
+            "bob",
+            json!({ "actionType": "setUnique", "data": { "hintId": "carol" } }),
+        );
+        play(&mut g, "carol", json!({ "actionType": "revealHints" }));
+        assert_eq!(g.round_state(), Some(RoundState::Guessing));
+
+        play(
+            &mut g,
+            "alice",
+            json!({ "actionType": "guess", "data": "pear" }),
+        );
+        play(&mut g, "bob", json!({ "actionType": "wrongGuess" }));
+        play(
+            &mut g,
+            "alice",
+            json!({ "actionType": "guess", "data": "apple" }),
+        );
+        assert_eq!(g.round_state(), Some(RoundState::RoundFinished));
+
+        play(&mut g, "bob", json!({ "actionType": "nextRound" }));
+        assert_eq!(g.round, 2);
+        assert_eq!(g.round_state(), Some(RoundState::GivingHints));
+        assert_eq!(guesser(&g, 1), "bob");
+    }
 
-        return match res.unwrap() {
-            JustOneMove::Guess(guess) => cur_roud.guess(req_uid, guess),
-            JustOneMove::Hint(hint) => cur_roud.give_hint(req_uid, hint),
-            JustOneMove::SetDuplicate { hint_id } => cur_roud.set_duplicate(&req_uid, &hint_id),
-            JustOneMove::SetUnique { hint_id } => cur_roud.set_unique(&req_uid, &hint_id),
-            JustOneMove::RevealHints => cur_roud.done_removing_dupes(&req_uid),
-            JustOneMove::CorrectGuess => cur_roud.set_guess_correctness(&req_uid, true),
-            JustOneMove::WrongGuess => cur_roud.set_guess_correctness(&req_uid, false),
-            JustOneMove::NextRound => Ok(self.new_round()),
+    #[test]
+    fn guesser_rotates_and_words_repeat_when_list_runs_out() {
+        let players = players();
+        let mut g = game(&players);
+        for _ in 0..3 {
+            play(&mut g, "alice", json!({ "actionType": "nextRound" }));
         }
-        .map(|_| &*self);
+
+        let guessers: Vec<String> = (0..4).map(|r| guesser(&g, r)).collect();
+        assert_eq!(guessers, ["alice", "bob", "carol", "alice"]);
+    }
+
+    #[test]
+    fn guesser_never_receives_word_or_hidden_hints() {
+        let players = players();
+        let mut g = game(&players);
+
+        let secret = |g: &GameData| {
+            let v = view(g, "alice").to_lowercase();
+            (v.contains("apple"), v.contains("fruit"), v.contains("tree"))
+        };
+
+        assert_eq!(secret(&g), (false, false, false));
+        play(
+            &mut g,
+            "bob",
+            json!({ "actionType": "hint", "data": "Fruit" }),
+        );
+        assert_eq!(secret(&g), (false, false, false));
+        play(
+            &mut g,
+            "carol",
+            json!({ "actionType": "hint", "data": "tree" }),
+        );
+        assert_eq!(secret(&g), (false, false, false));
+        play(
+            &mut g,
+            "bob",
+            json!({ "actionType": "setDuplicate", "data": { "hintId": "bob" } }),
+        );
+        play(&mut g, "bob", json!({ "actionType": "revealHints" }));
+
+        // Only the unique hint is revealed
+        assert_eq!(secret(&g), (false, false, true));
+        play(
+            &mut g,
+            "alice",
+            json!({ "actionType": "guess", "data": "pear" }),
+        );
+        assert_eq!(secret(&g), (false, false, true));
+
+        play(
+            &mut g,
+            "alice",
+            json!({ "actionType": "guess", "data": "apple" }),
+        );
+        assert!(secret(&g).0);
+    }
+
+    #[test]
+    fn hint_givers_see_the_word() {
+        let players = players();
+        let g = game(&players);
+        assert!(view(&g, "bob").contains("Apple"));
+        assert!(view(&g, "carol").contains("Apple"));
+    }
+
+    #[test]
+    fn filter_keeps_earlier_rounds() {
+        let players = players();
+        let mut g = game(&players);
+        play(&mut g, "bob", json!({ "actionType": "nextRound" }));
+
+        let filtered = g.filter("bob");
+        assert_eq!(filtered.rounds.len(), 2);
+        assert_eq!(filtered.round, 2);
+        // bob guesses the current round so can't see its word
+        assert!(!serde_json::to_string(&filtered.rounds[1])
+            .unwrap()
+            .contains("Apple"));
     }
 }
@@ -0,0 +1,318 @@
+use serde::{Deserialize, Serialize};
+use std::fmt::Debug;
+
+use super::validation::letters;
+use crate::config::{HintCheckKind, JustOneConfig};
+
+/// Why a hint was flagged as probably breaking the rules
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Copy)]
+pub enum HintFlag {
+    /// Shares a stem or word family with the word
+    SameStem,
+    /// An irregular plural or singular of the word
+    WordForm,
+    /// Spelled, and likely sounds, almost like the word
+    SimilarSpelling,
+}
+
+/// Spots hints that are likely against the rules but not clear cut enough
+/// to refuse outright. Flagged hints are hidden like duplicates until the
+/// hint givers confirm or override the flag.
+pub trait HintCheck: Debug + Send + Sync {
+    /// `hint` and `word` are already folded to lowercase letters and digits
+    fn check(&self, hint: &str, word: &str) -> Option<HintFlag>;
+}
+
+/// The checks a game runs on every hint, in order
+#[derive(Debug)]
+pub struct HintChecker {
+    checks: Vec<Box<dyn HintCheck>>,
+}
+
+impl HintChecker {
+    pub fn new(checks: Vec<Box<dyn HintCheck>>) -> HintChecker {
+        HintChecker { checks }
+    }
+
+    pub fn from_config(config: &JustOneConfig) -> HintChecker {
+        let checks = config
+            .hint_checks
+            .iter()
+            .map(|kind| -> Box<dyn HintCheck> {
+                match kind {
+                    HintCheckKind::SameStem => Box::new(SameStem),
+                    HintCheckKind::WordForm => Box::new(WordForm),
+                    HintCheckKind::EditDistance => Box::new(EditDistance {
+                        max_distance: config.max_edit_distance,
+                    }),
+                }
+            })
+            .collect();
+
+        HintChecker::new(checks)
+    }
+
+    /// The first flag raised for `hint`, if any
+    pub fn check(&self, hint: &str, word: &str) -> Option<HintFlag> {
+        let (hint, word) = (letters(hint), letters(word));
+        self.checks.iter().find_map(|c| c.check(&hint, &word))
+    }
+}
+
+impl Default for HintChecker {
+    fn default() -> Self {
+        HintChecker::from_config(&JustOneConfig::default())
+    }
+}
+
+/// Hints sharing a stem with the word, e.g. "runner" for "running", or
+/// containing the word, e.g. "campfires" for "fire"
+#[derive(Debug)]
+pub struct SameStem;
+
+const SUFFIXES: &[&str] = &[
+    "ations", "ation", "ments", "ment", "ness", "less", "ings", "ing", "able", "ible", "edly",
+    "ers", "ful", "ish", "ed", "er", "ly", "es", "s", "y", "e",
+];
+/// Stems shorter than this are too common to compare on
+pub(super) const MIN_STEM_CHARS: usize = 3;
+const MIN_CONTAINED_CHARS: usize = 4;
+
+impl HintCheck for SameStem {
+    fn check(&self, hint: &str, word: &str) -> Option<HintFlag> {
+        let (short, long) = if hint.len() <= word.len() {
+            (hint, word)
+        } else {
+            (word, hint)
+        };
+        if short.chars().count() >= MIN_CONTAINED_CHARS && long.contains(short) {
+            return Some(HintFlag::SameStem);
+        }
+
+        let stem = stem(word);
+        (stem.chars().count() >= MIN_STEM_CHARS && stem == self::stem(hint))
+            .then_some(HintFlag::SameStem)
+    }
+}
+
+/// Strip the longest common suffix, plus the `i` or doubled consonant it
+/// can leave behind, as in "happiness" and "running"
+pub(super) fn stem(w: &str) -> String {
+    let (stripped, suffix) = SUFFIXES
+        .iter()
+        .filter_map(|s| w.strip_suffix(s).map(|stem| (stem, *s)))
+        .find(|(stem, _)| stem.chars().count() >= MIN_STEM_CHARS)
+        .unwrap_or((w, ""));
+
+    let mut stem: Vec<char> = stripped.chars().collect();
+    if stem.len() > MIN_STEM_CHARS {
+        let last = stem[stem.len() - 1];
+        let doubled =
+            last == stem[stem.len() - 2] && !is_vowel(last) && suffix.starts_with(is_vowel);
+        if last == 'i' || doubled {
+            stem.pop();
+        }
+    }
+
+    stem.into_iter().collect()
+}
+
+fn is_vowel(c: char) -> bool {
+    "aeiou".contains(c)
+}
+
+/// Irregular plurals and singulars that the simple plural rule misses
+#[derive(Debug)]
+pub struct WordForm;
+
+const IRREGULAR_PLURALS: &[(&str, &str)] = &[
+    ("child", "children"),
+    ("foot", "feet"),
+    ("goose", "geese"),
+    ("man", "men"),
+    ("mouse", "mice"),
+    ("ox", "oxen"),
+    ("person", "people"),
+    ("tooth", "teeth"),
+    ("woman", "women"),
+];
+
+impl HintCheck for WordForm {
+    fn check(&self, hint: &str, word: &str) -> Option<HintFlag> {
+        let is_form = |one: &str, many: &str| {
+            IRREGULAR_PLURALS.iter().any(|(s, p)| {
+                matches!(
+                    (one.strip_suffix(s), many.strip_suffix(p)),
+                    (Some(a), Some(b)) if a == b
+                )
+            }) || ves_plural(one, many)
+        };
+
+        (is_form(hint, word) || is_form(word, hint)).then_some(HintFlag::WordForm)
+    }
+}
+
+/// "knife" and "knives", "leaf" and "leaves"
+fn ves_plural(one: &str, many: &str) -> bool {
+    let Some(stem) = many.strip_suffix("ves") else {
+        return false;
+    };
+
+    one.strip_suffix('f') == Some(stem) || one.strip_suffix("fe") == Some(stem)
+}
+
+/// Hints a few letters away from the word, catching misspellings and most
+/// words that sound the same. Short words allow fewer edits.
+#[derive(Debug)]
+pub struct EditDistance {
+    pub max_distance: usize,
+}
+
+impl HintCheck for EditDistance {
+    fn check(&self, hint: &str, word: &str) -> Option<HintFlag> {
+        let max = self.max_distance.min(word.chars().count() / 3);
+
+        (max > 0 && levenshtein(hint, word) <= max).then_some(HintFlag::SimilarSpelling)
+    }
+}
+
+fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut cur = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let cost = usize::from(ca != *cb);
+            cur[j + 1] = (prev[j] + cost).min(prev[j + 1] + 1).min(cur[j] + 1);
+        }
+        prev = cur;
+    }
+
+    prev[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_stem() {
+        for (hint, word) in [
+            ("runner", "running"),
+            ("happiness", "happy"),
+            ("campfires", "fire"),
+            ("fire", "campfires"),
+        ] {
+            assert_eq!(
+                SameStem.check(hint, word),
+                Some(HintFlag::SameStem),
+                "{} {}",
+                hint,
+                word
+            );
+        }
+    }
+
+    #[test]
+    fn different_stems() {
+        for (hint, word) in [("planet", "plant"), ("winter", "window"), ("art", "party")] {
+            assert_eq!(SameStem.check(hint, word), None, "{} {}", hint, word);
+        }
+    }
+
+    #[test]
+    fn stems() {
+        assert_eq!(stem("running"), "run");
+        assert_eq!(stem("happiness"), "happ");
+        assert_eq!(stem("happy"), "happ");
+        // Too little would be left to strip anything
+        assert_eq!(stem("bed"), "bed");
+    }
+
+    #[test]
+    fn word_forms() {
+        for (hint, word) in [
+            ("children", "child"),
+            ("child", "children"),
+            ("firemen", "fireman"),
+            ("knives", "knife"),
+            ("leaves", "leaf"),
+        ] {
+            assert_eq!(
+                WordForm.check(hint, word),
+                Some(HintFlag::WordForm),
+                "{} {}",
+                hint,
+                word
+            );
+        }
+    }
+
+    #[test]
+    fn not_word_forms() {
+        // Regular plurals are left to the other checks
+        for (hint, word) in [
+            ("gloves", "glove"),
+            ("managers", "manager"),
+            ("mean", "men"),
+        ] {
+            assert_eq!(WordForm.check(hint, word), None, "{} {}", hint, word);
+        }
+    }
+
+    #[test]
+    fn similar_spelling() {
+        let c = EditDistance { max_distance: 2 };
+        assert_eq!(
+            c.check("bandana", "banana"),
+            Some(HintFlag::SimilarSpelling)
+        );
+        assert_eq!(
+            c.check("bananna", "banana"),
+            Some(HintFlag::SimilarSpelling)
+        );
+        assert_eq!(c.check("boar", "bear"), Some(HintFlag::SimilarSpelling));
+        assert_eq!(c.check("elephant", "banana"), None);
+    }
+
+    #[test]
+    fn short_words_allow_fewer_edits() {
+        let c = EditDistance { max_distance: 2 };
+        // One edit at most for a four letter word
+        assert_eq!(c.check("pearl", "bear"), None);
+        // None at all for two letters
+        assert_eq!(c.check("on", "ox"), None);
+        // The configured limit still applies to long words
+        assert_eq!(
+            c.check("elephants", "elephant"),
+            Some(HintFlag::SimilarSpelling)
+        );
+        let strict = EditDistance { max_distance: 0 };
+        assert_eq!(strict.check("bananna", "banana"), None);
+    }
+
+    #[test]
+    fn edit_distances() {
+        assert_eq!(levenshtein("kitten", "sitting"), 3);
+        assert_eq!(levenshtein("flaw", "lawn"), 2);
+        assert_eq!(levenshtein("", "abc"), 3);
+        assert_eq!(levenshtein("abc", "abc"), 0);
+    }
+
+    #[test]
+    fn checker_folds_and_reports_the_first_flag() {
+        let checker = HintChecker::default();
+        assert_eq!(
+            checker.check("Camp-Fires!", "FIRE"),
+            Some(HintFlag::SameStem)
+        );
+        // Also one edit away, but the stem check runs first
+        assert_eq!(checker.check("rushed", "rusher"), Some(HintFlag::SameStem));
+        assert_eq!(checker.check("Mice", "mouse"), Some(HintFlag::WordForm));
+        assert_eq!(checker.check("Orchard", "Apple"), None);
+
+        let none = HintChecker::new(Vec::new());
+        assert_eq!(none.check("running", "runner"), None);
+    }
+}
@@ -1,17 +1,15 @@
-use itertools::Itertools;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-
-#[derive(Debug)]
-pub enum InvalidMove {
-    CouldNotParse { msg: String },
-    NotYourTurn { msg: String },
-    WrongState { msg: String },
-    InvalidUser { msg: String },
-}
+use std::{collections::HashMap, sync::Arc};
+
+use super::{
+    duplicates::DuplicateRules,
+    hint_check::{HintChecker, HintFlag},
+    validation,
+};
+use crate::games::InvalidMove;
 
 #[derive(Serialize, Deserialize, PartialEq, PartialOrd, Debug, Clone, Copy)]
-enum RoundState {
+pub enum RoundState {
     GivingHints,
     RemovingDuplicates,
     Guessing,
@@ -31,6 +29,9 @@ pub struct Guess {
 pub struct Hint {
     val: String,
     duplicate: bool,
+    /// Set when the hint looks too close to the word. Flagged hints start
+    /// out marked as duplicates until the table decides otherwise.
+    flag: Option<HintFlag>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -42,21 +43,38 @@ pub struct RoundData<'a> {
     guesses: Vec<Guess>,
     word: String,
     cur_state: RoundState,
+
+    #[serde(skip)]
+    checker: Arc<HintChecker>,
+    #[serde(skip)]
+    duplicates: DuplicateRules,
 }
 
 impl<'a> RoundData<'a> {
-    pub fn new(players: Vec<String>, guesser: &'a str, word: String) -> RoundData<'a> {
-        return RoundData {
+    pub fn new(
+        players: Vec<String>,
+        guesser: &'a str,
+        word: String,
+        checker: Arc<HintChecker>,
+        duplicates: DuplicateRules,
+    ) -> RoundData<'a> {
+        RoundData {
             players,
             guesser,
             hints: HashMap::new(),
             guesses: Vec::new(),
             word,
             cur_state: RoundState::GivingHints,
-        };
+            checker,
+            duplicates,
+        }
+    }
+
+    pub fn state(&self) -> RoundState {
+        self.cur_state
     }
 
-    pub fn give_hint(&mut self, user: &String, hint: String) -> Result<(), InvalidMove> {
+    pub fn give_hint(&mut self, user: &str, hint: String) -> Result<(), InvalidMove> {
         if self.guesser == user {
             return Err(InvalidMove::NotYourTurn {
                 msg: "The guesser cannot give hints".to_owned(),
@@ -68,28 +86,22 @@ impl<'a> RoundData<'a> {
                 msg: format!("Can't give hint during {:?}", self.cur_state),
             });
         }
+        let hint = validation::hint(&hint, &self.word)?;
+        let flag = self.checker.check(&hint, &self.word);
 
-        self.hints
-            .entry(user.clone())
-            .and_modify(|h| {
-                h.val = hint.clone();
-            })
-            .or_insert(Hint {
+        self.hints.insert(
+            user.to_owned(),
+            Hint {
                 val: hint,
                 duplicate: false,
-            });
-
-        let h_count: HashMap<String, usize> = self
-            .hints
-            .iter()
-            .map(|(_, v)| v.val.to_lowercase())
-            .group_by(|k| k.to_owned())
-            .into_iter()
-            .map(|e| (e.0, e.1.count()))
-            .collect();
+                flag,
+            },
+        );
 
-        for (_, h) in self.hints.iter_mut() {
-            h.duplicate = *h_count.get(&h.val.to_lowercase()).unwrap() > 1
+        let vals: Vec<&str> = self.hints.values().map(|h| h.val.as_str()).collect();
+        let duplicates = self.duplicates.mark(&vals);
+        for (h, duplicate) in self.hints.values_mut().zip(duplicates) {
+            h.duplicate = duplicate || h.flag.is_some();
         }
 
         if self.hints.len() == self.players.len() - 1 {
@@ -111,7 +123,8 @@ impl<'a> RoundData<'a> {
             });
         }
 
-        let is_correct = val.to_lowercase().eq(&self.word.to_lowercase());
+        let val = validation::guess(&val)?;
+        let is_correct = validation::matches(&val, &self.word);
         self.guesses.push(Guess {
             val,
             is_correct,
@@ -130,10 +143,15 @@ impl<'a> RoundData<'a> {
                 msg: "The guesser cannot say all duplicates have been removed".to_owned(),
             });
         }
+        if self.cur_state != RoundState::RemovingDuplicates {
+            return Err(InvalidMove::WrongState {
+                msg: format!("Can't reveal hints during {:?}", self.cur_state),
+            });
+        }
 
         self.cur_state = RoundState::Guessing;
 
-        return Ok(());
+        Ok(())
     }
 
     pub fn set_duplicate(&mut self, user: &str, hint_user: &str) -> Result<(), InvalidMove> {
@@ -142,6 +160,11 @@ impl<'a> RoundData<'a> {
                 msg: "Cannot set duplicate when you're the guesser".to_string(),
             });
         }
+        if self.cur_state != RoundState::RemovingDuplicates {
+            return Err(InvalidMove::WrongState {
+                msg: format!("Can't mark hints during {:?}", self.cur_state),
+            });
+        }
         self.hints
             .get_mut(hint_user)
             .map(|hint| {
@@ -158,6 +181,11 @@ impl<'a> RoundData<'a> {
                 msg: "Cannot set duplicate when you're the guesser".to_string(),
             });
         }
+        if self.cur_state != RoundState::RemovingDuplicates {
+            return Err(InvalidMove::WrongState {
+                msg: format!("Can't mark hints during {:?}", self.cur_state),
+            });
+        }
         self.hints
             .get_mut(hint_user)
             .map(|hint| {
@@ -205,6 +233,7 @@ impl<'a> RoundData<'a> {
                         Hint {
                             val: "".to_owned(),
                             duplicate: h.duplicate,
+                            flag: None,
                         },
                     )
                 } else {
@@ -226,6 +255,350 @@ impl<'a> RoundData<'a> {
             guesses: self.guesses.clone(),
             word,
             cur_state: self.cur_state,
+            checker: self.checker.clone(),
+            duplicates: self.duplicates,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GUESSER: &str = "alice";
+
+    fn round() -> RoundData<'static> {
+        RoundData::new(
+            vec!["alice".to_owned(), "bob".to_owned(), "carol".to_owned()],
+            GUESSER,
+            "Apple".to_owned(),
+            Arc::new(HintChecker::default()),
+            DuplicateRules::default(),
+        )
+    }
+
+    fn hint(r: &mut RoundData, user: &str, hint: &str) -> Result<(), InvalidMove> {
+        r.give_hint(user, hint.to_owned())
+    }
+
+    /// A round where bob and carol gave the hints
+    fn hinted(bob: &str, carol: &str) -> RoundData<'static> {
+        let mut r = round();
+        hint(&mut r, "bob", bob).unwrap();
+        hint(&mut r, "carol", carol).unwrap();
+        r
+    }
+
+    fn guessing() -> RoundData<'static> {
+        let mut r = hinted("fruit", "tree");
+        r.done_removing_dupes("bob").unwrap();
+        r
+    }
+
+    #[test]
+    fn starts_giving_hints() {
+        assert_eq!(round().state(), RoundState::GivingHints);
+    }
+
+    #[test]
+    fn hints_stay_in_giving_hints_until_everyone_has_given_one() {
+        let mut r = round();
+        hint(&mut r, "bob", "fruit").unwrap();
+        assert_eq!(r.state(), RoundState::GivingHints);
+
+        // Changing a hint doesn't count as another hint
+        hint(&mut r, "bob", "tree").unwrap();
+        assert_eq!(r.state(), RoundState::GivingHints);
+        assert_eq!(r.hints["bob"].val, "tree");
+
+        hint(&mut r, "carol", "pie").unwrap();
+        assert_eq!(r.state(), RoundState::RemovingDuplicates);
+    }
+
+    #[test]
+    fn hints_are_normalised() {
+        let r = hinted("  Fruit\n", "ｔｒｅｅ");
+        assert_eq!(r.hints["bob"].val, "Fruit");
+        assert_eq!(r.hints["carol"].val, "tree");
+    }
+
+    #[test]
+    fn guesser_cannot_give_hints() {
+        let res = hint(&mut round(), GUESSER, "fruit");
+        assert!(matches!(res, Err(InvalidMove::NotYourTurn { .. })));
+    }
+
+    #[test]
+    fn hints_only_while_giving_hints() {
+        let mut r = hinted("fruit", "tree");
+        let res = hint(&mut r, "bob", "pie");
+        assert!(matches!(res, Err(InvalidMove::WrongState { .. })));
+    }
+
+    #[test]
+    fn invalid_hints() {
+        let long = "a".repeat(33);
+        let cases = [
+            ("", "empty_input"),
+            ("  \n ", "empty_input"),
+            ("---", "empty_input"),
+            ("'-'", "empty_input"),
+            (long.as_str(), "too_long"),
+            ("fr\u{0}uit", "invalid_characters"),
+            ("red fruit", "not_one_word"),
+            ("APPLE", "hint_is_word"),
+            ("apples", "hint_too_similar"),
+        ];
+
+        for (val, kind) in cases {
+            let mut r = round();
+            let err = hint(&mut r, "bob", val).unwrap_err();
+            assert_eq!(err.kind(), kind, "{:?} gave {:?}", val, err);
+            assert!(r.hints.is_empty());
+        }
+    }
+
+    #[test]
+    fn duplicate_hints_are_marked() {
+        let r = hinted("Fruit", "fruits");
+        assert!(r.hints["bob"].duplicate);
+        assert!(r.hints["carol"].duplicate);
+
+        let r = hinted("fruit", "tree");
+        assert!(!r.hints["bob"].duplicate);
+        assert!(!r.hints["carol"].duplicate);
+    }
+
+    #[test]
+    fn flagged_hints_are_marked() {
+        let r = hinted("aple", "tree");
+        assert_eq!(r.hints["bob"].flag, Some(HintFlag::SimilarSpelling));
+        assert!(r.hints["bob"].duplicate);
+        assert!(!r.hints["carol"].duplicate);
+    }
+
+    #[test]
+    fn set_duplicate_and_unique() {
+        let mut r = hinted("fruit", "tree");
+
+        r.set_duplicate("carol", "bob").unwrap();
+        assert!(r.hints["bob"].duplicate);
+
+        r.set_unique("carol", "bob").unwrap();
+        assert!(!r.hints["bob"].duplicate);
+    }
+
+    #[test]
+    fn flags_can_be_overridden() {
+        let mut r = hinted("aple", "tree");
+        r.set_unique("carol", "bob").unwrap();
+        assert!(!r.hints["bob"].duplicate);
+    }
+
+    #[test]
+    fn guesser_cannot_mark_duplicates() {
+        let mut r = hinted("fruit", "tree");
+        let res = r.set_duplicate(GUESSER, "bob");
+        assert!(matches!(res, Err(InvalidMove::NotYourTurn { .. })));
+        let res = r.set_unique(GUESSER, "bob");
+        assert!(matches!(res, Err(InvalidMove::NotYourTurn { .. })));
+    }
+
+    #[test]
+    fn mark_duplicates_of_unknown_user() {
+        let mut r = hinted("fruit", "tree");
+        let res = r.set_duplicate("bob", "dave");
+        assert!(matches!(res, Err(InvalidMove::InvalidUser { .. })));
+        let res = r.set_unique("bob", "dave");
+        assert!(matches!(res, Err(InvalidMove::InvalidUser { .. })));
+    }
+
+    #[test]
+    fn reveal_hints() {
+        let mut r = hinted("fruit", "tree");
+        r.done_removing_dupes("bob").unwrap();
+        assert_eq!(r.state(), RoundState::Guessing);
+    }
+
+    #[test]
+    fn guesser_cannot_reveal_hints() {
+        let mut r = hinted("fruit", "tree");
+        let res = r.done_removing_dupes(GUESSER);
+        assert!(matches!(res, Err(InvalidMove::NotYourTurn { .. })));
+        assert_eq!(r.state(), RoundState::RemovingDuplicates);
+    }
+
+    #[test]
+    fn hints_are_revealed_only_while_removing_duplicates() {
+        let mut r = round();
+        hint(&mut r, "bob", "fruit").unwrap();
+        let res = r.done_removing_dupes("bob");
+        assert!(matches!(res, Err(InvalidMove::WrongState { .. })));
+        assert_eq!(r.state(), RoundState::GivingHints);
+
+        let mut r = guessing();
+        let res = r.done_removing_dupes("bob");
+        assert!(matches!(res, Err(InvalidMove::WrongState { .. })));
+        assert_eq!(r.state(), RoundState::Guessing);
+    }
+
+    #[test]
+    fn duplicates_are_marked_only_while_removing_duplicates() {
+        let mut r = round();
+        hint(&mut r, "bob", "fruit").unwrap();
+        let res = r.set_duplicate("carol", "bob");
+        assert!(matches!(res, Err(InvalidMove::WrongState { .. })));
+        assert!(!r.hints["bob"].duplicate);
+
+        let mut r = hinted("aple", "tree");
+        r.done_removing_dupes("bob").unwrap();
+        let res = r.set_unique("carol", "bob");
+        assert!(matches!(res, Err(InvalidMove::WrongState { .. })));
+        let res = r.set_duplicate("bob", "carol");
+        assert!(matches!(res, Err(InvalidMove::WrongState { .. })));
+        assert!(r.hints["bob"].duplicate);
+        assert!(!r.hints["carol"].duplicate);
+    }
+
+    #[test]
+    fn correct_guess_finishes_round() {
+        let mut r = guessing();
+        r.guess(GUESSER, " apple ".to_owned()).unwrap();
+        assert_eq!(r.state(), RoundState::RoundFinished);
+        assert!(r.guesses[0].is_correct);
+        assert_eq!(r.guesses[0].val, "apple");
+    }
+
+    #[test]
+    fn guesses_ignore_accents() {
+        let mut r = guessing();
+        r.guess(GUESSER, "Applé".to_owned()).unwrap();
+        assert_eq!(r.state(), RoundState::RoundFinished);
+    }
+
+    #[test]
+    fn wrong_guess_keeps_guessing() {
+        let mut r = guessing();
+        r.guess(GUESSER, "pear".to_owned()).unwrap();
+        assert_eq!(r.state(), RoundState::Guessing);
+        assert!(!r.guesses[0].is_correct);
+    }
+
+    #[test]
+    fn only_guesser_guesses() {
+        let mut r = guessing();
+        let res = r.guess("bob", "apple".to_owned());
+        assert!(matches!(res, Err(InvalidMove::NotYourTurn { .. })));
+    }
+
+    #[test]
+    fn guesses_only_while_guessing() {
+        let mut r = round();
+        let res = r.guess(GUESSER, "apple".to_owned());
+        assert!(matches!(res, Err(InvalidMove::WrongState { .. })));
+
+        let mut r = hinted("fruit", "tree");
+        let res = r.guess(GUESSER, "apple".to_owned());
+        assert!(matches!(res, Err(InvalidMove::WrongState { .. })));
+    }
+
+    #[test]
+    fn invalid_guesses() {
+        let mut r = guessing();
+        let res = r.guess(GUESSER, " ".to_owned());
+        assert!(matches!(res, Err(InvalidMove::EmptyInput { .. })));
+        let res = r.guess(GUESSER, "x".repeat(49));
+        assert!(matches!(res, Err(InvalidMove::TooLong { .. })));
+        let res = r.guess(GUESSER, "app\u{7}le".to_owned());
+        assert!(matches!(res, Err(InvalidMove::InvalidCharacters { .. })));
+        assert!(r.guesses.is_empty());
+    }
+
+    #[test]
+    fn table_overrides_guess() {
+        let mut r = guessing();
+        r.guess(GUESSER, "apples".to_owned()).unwrap();
+
+        r.set_guess_correctness("bob", true).unwrap();
+        assert!(r.guesses[0].is_correct);
+        assert!(r.guesses[0].user_check);
+
+        r.set_guess_correctness("carol", false).unwrap();
+        assert!(!r.guesses[0].is_correct);
+    }
+
+    #[test]
+    fn guesser_cannot_judge_guess() {
+        let mut r = guessing();
+        r.guess(GUESSER, "pear".to_owned()).unwrap();
+        let res = r.set_guess_correctness(GUESSER, true);
+        assert!(matches!(res, Err(InvalidMove::NotYourTurn { .. })));
+    }
+
+    #[test]
+    fn judge_guess_only_while_guessing() {
+        let mut r = hinted("fruit", "tree");
+        let res = r.set_guess_correctness("bob", true);
+        assert!(matches!(res, Err(InvalidMove::WrongState { .. })));
+    }
+
+    #[test]
+    fn judge_guess_before_any_guess() {
+        let mut r = guessing();
+        let res = r.set_guess_correctness("bob", true);
+        assert!(matches!(res, Err(InvalidMove::WrongState { .. })));
+    }
+
+    #[test]
+    fn guesser_never_sees_word_before_round_ends() {
+        let mut r = guessing();
+        assert_eq!(r.filter(GUESSER).word, "");
+        r.guess(GUESSER, "pear".to_owned()).unwrap();
+        assert_eq!(r.filter(GUESSER).word, "");
+        r.guess(GUESSER, "apple".to_owned()).unwrap();
+        assert_eq!(r.filter(GUESSER).word, "Apple");
+
+        assert_eq!(round().filter(GUESSER).word, "");
+        assert_eq!(hinted("fruit", "tree").filter(GUESSER).word, "");
+    }
+
+    #[test]
+    fn guesser_sees_no_hints_before_guessing() {
+        for r in [round(), hinted("fruit", "tree")] {
+            let filtered = r.filter(GUESSER);
+            assert!(filtered.hints.values().all(|h| h.val.is_empty()));
+        }
+    }
+
+    #[test]
+    fn guesser_never_sees_duplicates() {
+        let mut r = hinted("fruit", "fruits");
+        r.done_removing_dupes("bob").unwrap();
+        assert!(r.filter(GUESSER).hints.values().all(|h| h.val.is_empty()));
+
+        let mut r = hinted("aple", "tree");
+        r.done_removing_dupes("bob").unwrap();
+        let filtered = r.filter(GUESSER);
+        assert_eq!(filtered.hints["bob"].val, "");
+        assert_eq!(filtered.hints["bob"].flag, None);
+        assert_eq!(filtered.hints["carol"].val, "tree");
+    }
+
+    #[test]
+    fn guesser_sees_unique_hints_while_guessing() {
+        let filtered = guessing().filter(GUESSER);
+        assert_eq!(filtered.hints["bob"].val, "fruit");
+        assert_eq!(filtered.hints["carol"].val, "tree");
+    }
+
+    #[test]
+    fn hint_givers_see_everything() {
+        for r in [round(), hinted("fruit", "fruits"), guessing()] {
+            let filtered = r.filter("bob");
+            assert_eq!(filtered.word, "Apple");
+            for (user, h) in r.hints.iter() {
+                assert_eq!(filtered.hints[user].val, h.val);
+            }
         }
     }
 }
@@ -0,0 +1,82 @@
+use crate::games::InvalidMove;
+use crate::text;
+
+const MAX_HINT_CHARS: usize = 32;
+const MAX_GUESS_CHARS: usize = 48;
+/// Shortest word that is checked for appearing inside a compound hint, so
+/// "fire" can't be hinted with "firehouse" while "car" still allows "carpet"
+const MIN_COMPOUND_CHARS: usize = 4;
+
+/// Normalise a hint and check it follows the Just One rules: a single word
+/// that isn't the secret word or an obvious variant of it
+pub fn hint(hint: &str, word: &str) -> Result<String, InvalidMove> {
+    let hint = text::clean(hint, MAX_HINT_CHARS).map_err(|e| InvalidMove::from_text("Hint", e))?;
+
+    if !hint
+        .chars()
+        .all(|c| c.is_alphanumeric() || c == '-' || c == '\'')
+    {
+        return Err(InvalidMove::NotOneWord {
+            msg: "Hints must be a single word".to_owned(),
+        });
+    }
+
+    let (h, w) = (letters(&hint), letters(word));
+    if h.is_empty() {
+        return Err(InvalidMove::EmptyInput {
+            msg: "Hints need at least one letter or digit".to_owned(),
+        });
+    }
+    if h == w {
+        return Err(InvalidMove::HintIsWord {
+            msg: "Hints can't be the word itself".to_owned(),
+        });
+    }
+    if is_trivial_variant(&h, &w) {
+        return Err(InvalidMove::HintTooSimilar {
+            msg: format!("{} is too close to the word", hint),
+        });
+    }
+
+    Ok(hint)
+}
+
+/// Normalise a guess. Guesses may be several words, e.g. "ice cream".
+pub fn guess(guess: &str) -> Result<String, InvalidMove> {
+    text::clean(guess, MAX_GUESS_CHARS).map_err(|e| InvalidMove::from_text("Guess", e))
+}
+
+/// Whether a guess names the word, ignoring case, accents and punctuation
+pub fn matches(guess: &str, word: &str) -> bool {
+    letters(guess) == letters(word)
+}
+
+/// Folded letters and digits of `s`, dropping spaces and punctuation
+pub fn letters(s: &str) -> String {
+    text::fold(s)
+        .chars()
+        .filter(|c| c.is_alphanumeric())
+        .collect()
+}
+
+/// Plurals of the word and compounds built on it
+fn is_trivial_variant(h: &str, w: &str) -> bool {
+    let (short, long) = if h.len() <= w.len() { (h, w) } else { (w, h) };
+
+    if short.chars().count() >= MIN_COMPOUND_CHARS
+        && (long.starts_with(short) || long.ends_with(short))
+    {
+        return true;
+    }
+
+    is_plural_of(long, short)
+}
+
+pub(super) fn is_plural_of(plural: &str, word: &str) -> bool {
+    plural.strip_suffix('s') == Some(word)
+        || plural.strip_suffix("es") == Some(word)
+        || matches!(
+            (plural.strip_suffix("ies"), word.strip_suffix('y')),
+            (Some(a), Some(b)) if a == b
+        )
+}
@@ -0,0 +1,44 @@
+use eyre::eyre;
+use serde::Deserialize;
+use std::path::Path;
+
+use super::{load_json, Asset};
+
+/// A pack of locations loaded from one of the json files in `assets/`
+#[derive(Deserialize, Debug, Clone)]
+pub struct LocationList {
+    pub description: String,
+    pub locations: Vec<Location>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Location {
+    pub name: String,
+    /// Roles players at this location are dealt
+    pub roles: Vec<String>,
+}
+
+impl LocationList {
+    pub async fn load(path: &Path) -> eyre::Result<LocationList> {
+        let list: LocationList = load_json(path, "location list").await?;
+        if let Some(l) = list.locations.iter().find(|l| l.roles.is_empty()) {
+            return Err(eyre!(
+                "Location {} in {} has no roles",
+                l.name,
+                path.display()
+            ));
+        }
+
+        Ok(list)
+    }
+}
+
+impl Asset for LocationList {
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn entries(&self) -> usize {
+        self.locations.len()
+    }
+}
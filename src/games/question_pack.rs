@@ -0,0 +1,83 @@
+use eyre::eyre;
+use rand::{seq::SliceRandom, thread_rng};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use super::{load_json, Asset};
+use crate::text;
+
+/// A pack of trivia questions loaded from one of the json files in `assets/`
+#[derive(Deserialize, Debug, Clone)]
+pub struct QuestionPack {
+    pub description: String,
+    pub questions: Vec<Question>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Question {
+    pub category: String,
+    pub question: String,
+    /// Options to pick from, free text questions have none
+    #[serde(default)]
+    pub choices: Vec<String>,
+    pub answer: String,
+    /// Other free text answers that also count, e.g. "The Amazon"
+    #[serde(default)]
+    pub alternatives: Vec<String>,
+}
+
+impl QuestionPack {
+    pub async fn load(path: &Path) -> eyre::Result<QuestionPack> {
+        let pack: QuestionPack = load_json(path, "question pack").await?;
+        if let Some(q) = pack
+            .questions
+            .iter()
+            .find(|q| !q.choices.is_empty() && !q.choices.contains(&q.answer))
+        {
+            return Err(eyre!(
+                "Question \"{}\" in {} has an answer that isn't one of its choices",
+                q.question,
+                path.display()
+            ));
+        }
+
+        Ok(pack)
+    }
+
+    /// Every category in the pack, in the order they first appear
+    pub fn categories(&self) -> Vec<String> {
+        let mut categories: Vec<String> = Vec::new();
+        for q in &self.questions {
+            if !categories.contains(&q.category) {
+                categories.push(q.category.clone());
+            }
+        }
+        categories
+    }
+
+    /// Up to `count` questions in a random order from `categories`, ignoring
+    /// case. No categories means every question.
+    pub fn pick(&self, categories: &[String], count: usize) -> Vec<Question> {
+        let categories: Vec<String> = categories.iter().map(|c| text::fold(c)).collect();
+        let mut questions: Vec<Question> = self
+            .questions
+            .iter()
+            .filter(|q| categories.is_empty() || categories.contains(&text::fold(&q.category)))
+            .cloned()
+            .collect();
+        questions.shuffle(&mut thread_rng());
+        questions.truncate(count);
+        questions
+    }
+}
+
+impl Asset for QuestionPack {
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn entries(&self) -> usize {
+        self.questions.len()
+    }
+}
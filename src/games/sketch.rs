@@ -0,0 +1,668 @@
+//! Sketch: players take turns drawing a secret word while everyone else races
+//! to guess it. The drawer's strokes are opaque binary frames the lobby
+//! relays straight to the other players, the game only keeps them so late
+//! joiners can catch up on the canvas.
+//!
+//! Relayed frames start with the `canvasId` they were drawn on, as four big
+//! endian bytes. Strokes can reach a client before the game state that wipes
+//! the canvas, so clients drop frames for an older canvas and wipe theirs
+//! when a frame for a newer one turns up.
+
+use rand::{seq::SliceRandom, thread_rng};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use super::{
+    just_one::validation, parse_move, secs_left, to_json, word_list::WordList, Game, InvalidMove,
+    SketchSettings, MAX_TIMER_SECS,
+};
+
+const MIN_PLAYERS: usize = 2;
+/// Most times each player can be set to draw
+const MAX_ROUNDS: usize = 10;
+/// Most stroke data kept for one drawing, further strokes are refused
+const MAX_CANVAS_BYTES: usize = 1 << 20;
+/// How long the word is shown between turns
+const REVEAL_SECS: u64 = 5;
+/// Every correct guess scores at least this much
+const GUESS_POINTS: u32 = 50;
+/// Extra points for guessing straight away, falling to nothing as time runs out
+const SPEED_POINTS: u32 = 450;
+/// The drawer scores this much for each player who guesses the word
+const DRAWER_POINTS: u32 = 50;
+/// At most one in this many letters is given away as a hint
+const HINT_EVERY: usize = 3;
+const HIDDEN_LETTER: char = '_';
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Copy)]
+pub enum Phase {
+    Drawing,
+    TurnOver,
+    GameOver,
+}
+
+/// A guess as shown in the guess feed. Correct guesses don't show the word.
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct GuessEntry {
+    user: String,
+    text: Option<String>,
+    correct: bool,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Sketch {
+    /// Players in drawing order
+    players: Vec<String>,
+    scores: HashMap<String, u32>,
+    round: usize,
+    rounds: usize,
+    drawer: String,
+    /// Only the drawer and players who have guessed it see the word before
+    /// the turn is over
+    word: Option<String>,
+    /// The word with letters not yet given away replaced by `_`
+    hint: String,
+    /// Players who have guessed the word this turn, fastest first
+    guessed: Vec<String>,
+    guesses: Vec<GuessEntry>,
+    /// Changes whenever the canvas is wiped, so clients can drop strokes
+    canvas_id: u32,
+    phase: Phase,
+    #[serde(rename = "secsLeft", serialize_with = "secs_left")]
+    deadline: Option<Instant>,
+
+    /// Number of turns finished so far
+    #[serde(skip)]
+    turn: usize,
+    #[serde(skip)]
+    turn_started: Instant,
+    /// When each hint letter is given away, with its position in the word
+    #[serde(skip)]
+    hints: Vec<(Instant, usize)>,
+    #[serde(skip)]
+    canvas: Vec<Arc<Vec<u8>>>,
+    #[serde(skip)]
+    canvas_bytes: usize,
+    #[serde(skip)]
+    deck: Vec<String>,
+    #[serde(skip)]
+    settings: SketchSettings,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "actionType", content = "data")]
+#[serde(rename_all = "camelCase")]
+pub enum SketchMove {
+    Guess(String),
+    /// Wipe the canvas, only the drawer can
+    ClearCanvas,
+}
+
+impl Sketch {
+    pub fn new(
+        players: &[String],
+        words: &WordList,
+        settings: SketchSettings,
+    ) -> Result<Sketch, String> {
+        if players.len() < MIN_PLAYERS {
+            return Err(format!("Sketch needs at least {} players", MIN_PLAYERS));
+        }
+        if words.words.is_empty() {
+            return Err("There are no words to draw".to_owned());
+        }
+        if settings.rounds == 0 || settings.draw_secs == 0 {
+            return Err("Sketch needs at least one round with time to draw".to_owned());
+        }
+        let settings = SketchSettings {
+            rounds: settings.rounds.min(MAX_ROUNDS),
+            draw_secs: settings.draw_secs.min(MAX_TIMER_SECS),
+        };
+
+        let mut players = players.to_vec();
+        players.shuffle(&mut thread_rng());
+
+        let mut game = Sketch {
+            scores: players.iter().map(|p| (p.clone(), 0)).collect(),
+            players,
+            round: 1,
+            rounds: settings.rounds,
+            drawer: String::new(),
+            word: None,
+            hint: String::new(),
+            guessed: Vec::new(),
+            guesses: Vec::new(),
+            canvas_id: 0,
+            phase: Phase::Drawing,
+            deadline: None,
+            turn: 0,
+            turn_started: Instant::now(),
+            hints: Vec::new(),
+            canvas: Vec::new(),
+            canvas_bytes: 0,
+            deck: words.shuffled().words,
+            settings,
+        };
+        game.start_turn(Instant::now());
+
+        Ok(game)
+    }
+
+    fn start_turn(&mut self, now: Instant) {
+        let word = self.deck[self.turn % self.deck.len()].clone();
+
+        self.drawer = self.players[self.turn % self.players.len()].clone();
+        self.round = self.turn / self.players.len() + 1;
+        self.hint = word
+            .chars()
+            .map(|c| {
+                if c.is_alphanumeric() {
+                    HIDDEN_LETTER
+                } else {
+                    c
+                }
+            })
+            .collect();
+        self.hints = hint_schedule(&word, now, self.draw_time());
+        self.word = Some(word);
+        self.guessed.clear();
+        self.guesses.clear();
+        self.clear_canvas();
+        self.phase = Phase::Drawing;
+        self.turn_started = now;
+        self.deadline = Some(now + self.draw_time());
+    }
+
+    fn end_turn(&mut self, now: Instant) {
+        self.hints.clear();
+        self.turn += 1;
+        if self.turn >= self.players.len() * self.rounds {
+            self.phase = Phase::GameOver;
+            self.deadline = None;
+        } else {
+            self.phase = Phase::TurnOver;
+            self.deadline = Some(now + Duration::from_secs(REVEAL_SECS));
+        }
+    }
+
+    fn draw_time(&self) -> Duration {
+        Duration::from_secs(self.settings.draw_secs)
+    }
+
+    fn clear_canvas(&mut self) {
+        self.canvas.clear();
+        self.canvas_bytes = 0;
+        self.canvas_id += 1;
+    }
+
+    /// Give away every hint letter that is due by `now`
+    fn reveal_hints(&mut self, now: Instant) -> bool {
+        let due = self.hints.iter().take_while(|(at, _)| *at <= now).count();
+        if due == 0 {
+            return false;
+        }
+
+        let word: Vec<char> = self.word.as_deref().unwrap_or_default().chars().collect();
+        let mut hint: Vec<char> = self.hint.chars().collect();
+        for (_, i) in self.hints.drain(..due) {
+            hint[i] = word[i];
+        }
+        self.hint = hint.into_iter().collect();
+
+        true
+    }
+
+    fn is_player(&self, user: &str) -> bool {
+        self.players.iter().any(|p| p == user)
+    }
+
+    fn wrong_state(&self) -> InvalidMove {
+        InvalidMove::WrongState {
+            msg: format!("Can't do that during {:?}", self.phase),
+        }
+    }
+
+    fn guess(&mut self, user: &str, guess: &str, now: Instant) -> Result<(), InvalidMove> {
+        if self.phase != Phase::Drawing {
+            return Err(self.wrong_state());
+        }
+        if !self.is_player(user) {
+            return Err(InvalidMove::InvalidUser {
+                msg: format!("{} is not playing", user),
+            });
+        }
+        if user == self.drawer {
+            return Err(InvalidMove::NotYourTurn {
+                msg: "The drawer can't guess".to_owned(),
+            });
+        }
+        if self.guessed.iter().any(|p| p == user) {
+            return Err(InvalidMove::WrongState {
+                msg: "You've already guessed the word".to_owned(),
+            });
+        }
+
+        let guess = validation::guess(guess)?;
+        let word = self.word.as_deref().unwrap_or_default();
+        if !validation::matches(&guess, word) {
+            self.guesses.push(GuessEntry {
+                user: user.to_owned(),
+                text: Some(guess),
+                correct: false,
+            });
+            return Ok(());
+        }
+
+        let points = self.guess_points(now);
+        *self.scores.entry(user.to_owned()).or_default() += points;
+        *self.scores.entry(self.drawer.clone()).or_default() += DRAWER_POINTS;
+        self.guessed.push(user.to_owned());
+        self.guesses.push(GuessEntry {
+            user: user.to_owned(),
+            text: None,
+            correct: true,
+        });
+
+        if self.guessed.len() == self.players.len() - 1 {
+            self.end_turn(now);
+        }
+
+        Ok(())
+    }
+
+    /// Points for a correct guess at `now`, more the sooner it comes
+    fn guess_points(&self, now: Instant) -> u32 {
+        let total = self.draw_time().as_millis();
+        let left = self
+            .deadline
+            .map_or(0, |d| d.saturating_duration_since(now).as_millis())
+            .min(total);
+        GUESS_POINTS + (u128::from(SPEED_POINTS) * left / total) as u32
+    }
+
+    fn check_drawer(&self, user: &str) -> Result<(), InvalidMove> {
+        if self.phase != Phase::Drawing {
+            return Err(self.wrong_state());
+        }
+        if user != self.drawer {
+            return Err(InvalidMove::NotYourTurn {
+                msg: format!("{} is drawing", self.drawer),
+            });
+        }
+        Ok(())
+    }
+
+    fn advance(&mut self, now: Instant) -> bool {
+        match (self.phase, self.deadline) {
+            (Phase::Drawing, Some(d)) if now >= d => {
+                self.end_turn(now);
+                true
+            }
+            (Phase::Drawing, _) => self.reveal_hints(now),
+            (Phase::TurnOver, Some(d)) if now >= d => {
+                self.start_turn(now);
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Pick up to one in `HINT_EVERY` letters of `word` to give away, spread
+/// evenly over the drawing time
+fn hint_schedule(word: &str, start: Instant, time: Duration) -> Vec<(Instant, usize)> {
+    let mut letters: Vec<usize> = word
+        .chars()
+        .enumerate()
+        .filter(|(_, c)| c.is_alphanumeric())
+        .map(|(i, _)| i)
+        .collect();
+    letters.shuffle(&mut thread_rng());
+
+    let n = letters.len() / HINT_EVERY;
+    letters
+        .into_iter()
+        .take(n)
+        .enumerate()
+        .map(|(i, pos)| (start + time / (n as u32 + 1) * (i as u32 + 1), pos))
+        .collect()
+}
+
+impl Game for Sketch {
+    fn make_move(&mut self, user: &str, action: Value) -> Result<(), InvalidMove> {
+        match parse_move(action)? {
+            SketchMove::Guess(guess) => self.guess(user, &guess, Instant::now()),
+            SketchMove::ClearCanvas => {
+                self.check_drawer(user)?;
+                self.clear_canvas();
+                Ok(())
+            }
+        }
+    }
+
+    /// The word is hidden from everyone still guessing it
+    fn filter(&self, user: &str) -> Value {
+        if self.phase != Phase::Drawing
+            || user == self.drawer
+            || self.guessed.iter().any(|p| p == user)
+        {
+            return to_json(self);
+        }
+
+        let mut view = self.clone();
+        view.word = None;
+        to_json(&view)
+    }
+
+    fn state(&self) -> Value {
+        to_json(self)
+    }
+
+    fn phase(&self) -> String {
+        format!("{:?}", self.phase)
+    }
+
+    fn is_over(&self) -> bool {
+        self.phase == Phase::GameOver
+    }
+
+    /// The next hint letter or the end of the turn, whichever is sooner
+    fn deadline(&self) -> Option<Instant> {
+        let next_hint = self.hints.first().map(|(at, _)| *at);
+        match (next_hint, self.deadline) {
+            (Some(h), Some(d)) => Some(h.min(d)),
+            (h, d) => h.or(d),
+        }
+    }
+
+    fn tick(&mut self, now: Instant) -> bool {
+        self.advance(now)
+    }
+
+    /// Strokes from the drawer, tagged with the canvas and kept until it's
+    /// next wiped
+    fn binary(
+        &mut self,
+        user: &str,
+        data: &Arc<Vec<u8>>,
+    ) -> Result<Option<Arc<Vec<u8>>>, InvalidMove> {
+        self.check_drawer(user)?;
+        if data.is_empty() {
+            return Err(InvalidMove::EmptyInput {
+                msg: "Strokes can't be empty".to_owned(),
+            });
+        }
+        if self.canvas_bytes + data.len() > MAX_CANVAS_BYTES {
+            return Err(InvalidMove::TooLong {
+                msg: "The canvas is full, clear it to keep drawing".to_owned(),
+            });
+        }
+
+        let mut frame = Vec::with_capacity(4 + data.len());
+        frame.extend_from_slice(&self.canvas_id.to_be_bytes());
+        frame.extend_from_slice(data);
+        let frame = Arc::new(frame);
+
+        self.canvas_bytes += data.len();
+        self.canvas.push(frame.clone());
+        Ok(Some(frame))
+    }
+
+    fn binary_history(&self, _user: &str) -> Vec<Arc<Vec<u8>>> {
+        self.canvas.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn players() -> Vec<String> {
+        ["alice", "bob", "carol"].map(String::from).to_vec()
+    }
+
+    fn game() -> Sketch {
+        let words = WordList {
+            description: "Test".to_owned(),
+            words: vec!["Ice Cream".to_owned()],
+        };
+        Sketch::new(&players(), &words, SketchSettings::default()).unwrap()
+    }
+
+    fn guessers(g: &Sketch) -> Vec<String> {
+        g.players
+            .iter()
+            .filter(|p| **p != g.drawer)
+            .cloned()
+            .collect()
+    }
+
+    fn stroke(bytes: &[u8]) -> Arc<Vec<u8>> {
+        Arc::new(bytes.to_vec())
+    }
+
+    /// A stroke as relayed, behind the id of the canvas it was drawn on
+    fn tagged(canvas_id: u32, bytes: &[u8]) -> Arc<Vec<u8>> {
+        let mut frame = canvas_id.to_be_bytes().to_vec();
+        frame.extend_from_slice(bytes);
+        Arc::new(frame)
+    }
+
+    #[test]
+    fn needs_enough_players() {
+        let words = WordList {
+            description: "Test".to_owned(),
+            words: vec!["Apple".to_owned()],
+        };
+        let res = Sketch::new(&players()[..1], &words, SketchSettings::default());
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn settings_are_clamped() {
+        let words = WordList {
+            description: "Test".to_owned(),
+            words: vec!["Apple".to_owned()],
+        };
+        let settings = SketchSettings {
+            rounds: usize::MAX,
+            draw_secs: u64::MAX,
+        };
+        let g = Sketch::new(&players(), &words, settings).unwrap();
+        assert_eq!(g.rounds, MAX_ROUNDS);
+        assert_eq!(g.draw_time(), Duration::from_secs(MAX_TIMER_SECS));
+    }
+
+    #[test]
+    fn word_is_hidden_until_guessed() {
+        let mut g = game();
+        let [first, second] = <[String; 2]>::try_from(guessers(&g)).unwrap();
+
+        assert_eq!(g.filter(&g.drawer)["word"], json!("Ice Cream"));
+        assert_eq!(g.filter(&first)["word"], Value::Null);
+        assert_eq!(g.filter("spectator")["word"], Value::Null);
+        assert_eq!(g.filter(&first)["hint"], json!("___ _____"));
+
+        let start = g.turn_started;
+        g.guess(&first, "ICE-CREAM", start).unwrap();
+        assert_eq!(g.filter(&first)["word"], json!("Ice Cream"));
+        assert_eq!(g.filter(&second)["word"], Value::Null);
+        // The feed doesn't give the word away
+        assert_eq!(g.filter(&second)["guesses"][0]["text"], Value::Null);
+    }
+
+    #[test]
+    fn wrong_guesses_are_shown() {
+        let mut g = game();
+        let guesser = guessers(&g)[0].clone();
+        let res = g.make_move(&guesser, json!({ "actionType": "guess", "data": "cake" }));
+        assert!(res.is_ok());
+        assert_eq!(g.guesses[0].text.as_deref(), Some("cake"));
+        assert!(!g.guesses[0].correct);
+        assert!(g.guessed.is_empty());
+    }
+
+    #[test]
+    fn faster_guesses_score_more() {
+        let mut g = game();
+        let [first, second] = <[String; 2]>::try_from(guessers(&g)).unwrap();
+        let start = g.turn_started;
+        let drawer = g.drawer.clone();
+
+        g.guess(&first, "ice cream", start).unwrap();
+        g.guess(&second, "ice cream", start + Duration::from_secs(60))
+            .unwrap();
+
+        assert_eq!(g.scores[&first], GUESS_POINTS + SPEED_POINTS);
+        assert!(g.scores[&second] > GUESS_POINTS);
+        assert!(g.scores[&second] < g.scores[&first]);
+        assert_eq!(g.scores[&drawer], 2 * DRAWER_POINTS);
+        // Everyone guessed so the turn is over
+        assert_eq!(g.phase, Phase::TurnOver);
+    }
+
+    #[test]
+    fn bad_guesses_are_refused() {
+        let mut g = game();
+        let guesser = guessers(&g)[0].clone();
+        let drawer = g.drawer.clone();
+        let now = g.turn_started;
+
+        assert!(matches!(
+            g.guess(&drawer, "ice cream", now),
+            Err(InvalidMove::NotYourTurn { .. })
+        ));
+        assert!(matches!(
+            g.guess("spectator", "ice cream", now),
+            Err(InvalidMove::InvalidUser { .. })
+        ));
+        assert!(matches!(
+            g.guess(&guesser, "  ", now),
+            Err(InvalidMove::EmptyInput { .. })
+        ));
+        g.guess(&guesser, "ice cream", now).unwrap();
+        assert!(matches!(
+            g.guess(&guesser, "ice cream", now),
+            Err(InvalidMove::WrongState { .. })
+        ));
+    }
+
+    #[test]
+    fn hints_are_given_away_over_time() {
+        let mut g = game();
+        let start = g.turn_started;
+        // "Ice Cream" has 8 letters so 2 are given away
+        assert_eq!(g.hints.len(), 2);
+
+        let first = g.deadline().unwrap();
+        assert!(first < start + g.draw_time());
+        assert!(!g.tick(first - Duration::from_millis(1)));
+        assert!(g.tick(first));
+        assert_eq!(g.hint.chars().filter(|c| *c == HIDDEN_LETTER).count(), 7);
+
+        let second = g.deadline().unwrap();
+        assert!(g.tick(second));
+        assert_eq!(g.hint.chars().filter(|c| *c == HIDDEN_LETTER).count(), 6);
+        assert_eq!(g.deadline(), g.deadline);
+    }
+
+    #[test]
+    fn running_out_of_time_ends_the_turn() {
+        let mut g = game();
+        let end = g.turn_started + g.draw_time();
+        let drawer = g.drawer.clone();
+
+        assert!(g.tick(end));
+        assert_eq!(g.phase, Phase::TurnOver);
+        // Everyone sees the word between turns
+        assert_eq!(g.filter(&guessers(&g)[0])["word"], json!("Ice Cream"));
+
+        let reveal_end = g.deadline().unwrap();
+        assert!(g.tick(reveal_end));
+        assert_eq!(g.phase, Phase::Drawing);
+        assert_ne!(g.drawer, drawer);
+        assert!(g.canvas.is_empty());
+    }
+
+    #[test]
+    fn game_ends_after_every_round() {
+        let mut g = game();
+        let mut drawers = Vec::new();
+        while g.phase != Phase::GameOver {
+            drawers.push(g.drawer.clone());
+            let end = g.deadline.unwrap();
+            g.tick(end);
+            if let Some(reveal_end) = g.deadline {
+                g.tick(reveal_end);
+            }
+        }
+
+        assert_eq!(drawers.len(), players().len() * g.rounds);
+        assert_eq!(drawers[..3], drawers[3..6]);
+        assert_eq!(g.round, g.rounds);
+        assert_eq!(g.deadline(), None);
+    }
+
+    #[test]
+    fn only_the_drawer_draws() {
+        let mut g = game();
+        let drawer = g.drawer.clone();
+        let guesser = guessers(&g)[0].clone();
+
+        let relayed = g.binary(&drawer, &stroke(&[1, 2, 3])).unwrap();
+        assert_eq!(relayed, Some(tagged(g.canvas_id, &[1, 2, 3])));
+        assert!(matches!(
+            g.binary(&guesser, &stroke(&[1])),
+            Err(InvalidMove::NotYourTurn { .. })
+        ));
+        assert!(matches!(
+            g.binary(&drawer, &stroke(&[])),
+            Err(InvalidMove::EmptyInput { .. })
+        ));
+        assert_eq!(
+            g.binary_history(&guesser),
+            vec![tagged(g.canvas_id, &[1, 2, 3])]
+        );
+    }
+
+    #[test]
+    fn canvas_has_a_size_limit() {
+        let mut g = game();
+        let drawer = g.drawer.clone();
+        let big = Arc::new(vec![0; MAX_CANVAS_BYTES]);
+
+        g.binary(&drawer, &big).unwrap();
+        assert!(matches!(
+            g.binary(&drawer, &stroke(&[1])),
+            Err(InvalidMove::TooLong { .. })
+        ));
+    }
+
+    #[test]
+    fn clearing_the_canvas() {
+        let mut g = game();
+        let drawer = g.drawer.clone();
+        let guesser = guessers(&g)[0].clone();
+        let id = g.canvas_id;
+        g.binary(&drawer, &stroke(&[1])).unwrap();
+
+        let clear = json!({ "actionType": "clearCanvas" });
+        let res = g.make_move(&guesser, clear.clone());
+        assert!(matches!(res, Err(InvalidMove::NotYourTurn { .. })));
+
+        g.make_move(&drawer, clear).unwrap();
+        assert!(g.binary_history(&guesser).is_empty());
+        assert_eq!(g.canvas_id, id + 1);
+
+        // Strokes on the new canvas can be told apart from older ones
+        let relayed = g.binary(&drawer, &stroke(&[2])).unwrap();
+        assert_eq!(relayed, Some(tagged(id + 1, &[2])));
+    }
+}
@@ -0,0 +1,42 @@
+use rand::{seq::SliceRandom, thread_rng};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use super::{load_json, Asset};
+
+/// A deck of spectrum cards loaded from one of the json files in `assets/`
+#[derive(Deserialize, Debug, Clone)]
+pub struct SpectrumList {
+    pub description: String,
+    pub cards: Vec<Spectrum>,
+}
+
+/// Two opposite ends of a scale, e.g. "Hot" and "Cold"
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Spectrum {
+    pub left: String,
+    pub right: String,
+}
+
+impl SpectrumList {
+    pub async fn load(path: &Path) -> eyre::Result<SpectrumList> {
+        load_json(path, "spectrum list").await
+    }
+
+    /// A copy of the deck in a random order
+    pub fn shuffled(&self) -> SpectrumList {
+        let mut list = self.clone();
+        list.cards.shuffle(&mut thread_rng());
+        list
+    }
+}
+
+impl Asset for SpectrumList {
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn entries(&self) -> usize {
+        self.cards.len()
+    }
+}
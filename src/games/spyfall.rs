@@ -0,0 +1,619 @@
+//! Spyfall: everyone but the spy is dealt a role at a secret location. The
+//! table questions each other to find the spy without giving the location
+//! away, while the spy tries to work the location out from the answers.
+
+use rand::{seq::SliceRandom, thread_rng};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use super::{
+    location_list::LocationList, parse_move, secs_left, to_json, Game, InvalidMove,
+    SpyfallSettings, MAX_TIMER_SECS,
+};
+use crate::text;
+
+const MIN_PLAYERS: usize = 3;
+/// Time players get to vote before the votes cast so far are counted
+const VOTE_SECS: u64 = 60;
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Copy)]
+pub enum Phase {
+    Questioning,
+    Voting,
+    /// The spy was caught and gets one guess at the location
+    SpyGuessing,
+    GameOver,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Copy)]
+pub enum Winner {
+    Spy,
+    Players,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Spyfall {
+    players: Vec<String>,
+    /// Every location in the pack, for the spy to pick from
+    locations: Vec<String>,
+    location: Option<String>,
+    spy: Option<String>,
+    /// Role each player was dealt, the spy has none
+    roles: HashMap<String, String>,
+    phase: Phase,
+    #[serde(rename = "secsLeft", serialize_with = "secs_left")]
+    deadline: Option<Instant>,
+    /// Players who have voted so far
+    voted: Vec<String>,
+    /// Who each player voted for, kept secret until the votes are counted
+    votes: HashMap<String, String>,
+    accused: Option<String>,
+    spy_guess: Option<String>,
+    winner: Option<Winner>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "actionType", content = "data")]
+#[serde(rename_all = "camelCase")]
+pub enum SpyfallMove {
+    /// Stop questioning early and vote on who the spy is
+    CallVote,
+    Vote(String),
+    /// The spy names the location, ending the game
+    GuessLocation(String),
+}
+
+impl Spyfall {
+    pub fn new(
+        players: &[String],
+        locations: &LocationList,
+        settings: SpyfallSettings,
+    ) -> Result<Spyfall, String> {
+        if players.len() < MIN_PLAYERS {
+            return Err(format!("Spyfall needs at least {} players", MIN_PLAYERS));
+        }
+
+        let mut rng = thread_rng();
+        let location = locations
+            .locations
+            .choose(&mut rng)
+            .ok_or("There are no locations to play at")?;
+        let spy = players.choose(&mut rng).expect("There are players").clone();
+
+        let round_secs = settings.round_secs.min(MAX_TIMER_SECS);
+
+        let mut roles = location.roles.clone();
+        roles.shuffle(&mut rng);
+        // Roles repeat when there are more players than roles
+        let roles = players
+            .iter()
+            .filter(|p| **p != spy)
+            .cloned()
+            .zip(roles.into_iter().cycle())
+            .collect();
+
+        Ok(Spyfall {
+            players: players.to_vec(),
+            locations: locations.locations.iter().map(|l| l.name.clone()).collect(),
+            location: Some(location.name.clone()),
+            spy: Some(spy),
+            roles,
+            phase: Phase::Questioning,
+            deadline: Some(Instant::now() + Duration::from_secs(round_secs)),
+            voted: Vec::new(),
+            votes: HashMap::new(),
+            accused: None,
+            spy_guess: None,
+            winner: None,
+        })
+    }
+
+    fn is_spy(&self, user: &str) -> bool {
+        self.spy.as_deref() == Some(user)
+    }
+
+    fn check_player(&self, user: &str) -> Result<(), InvalidMove> {
+        if !self.players.iter().any(|p| p == user) {
+            return Err(InvalidMove::InvalidUser {
+                msg: format!("{} is not playing", user),
+            });
+        }
+        Ok(())
+    }
+
+    fn wrong_state(&self) -> InvalidMove {
+        InvalidMove::WrongState {
+            msg: format!("Can't do that during {:?}", self.phase),
+        }
+    }
+
+    fn start_vote(&mut self, now: Instant) {
+        self.phase = Phase::Voting;
+        self.deadline = Some(now + Duration::from_secs(VOTE_SECS));
+    }
+
+    fn vote(&mut self, user: &str, target: String) -> Result<(), InvalidMove> {
+        if self.phase != Phase::Voting {
+            return Err(self.wrong_state());
+        }
+        self.check_player(user)?;
+        self.check_player(&target)?;
+        if target == user {
+            return Err(InvalidMove::NotAllowed {
+                msg: "You can't vote for yourself".to_owned(),
+            });
+        }
+
+        if self.votes.insert(user.to_owned(), target).is_none() {
+            self.voted.push(user.to_owned());
+        }
+        if self.votes.len() == self.players.len() {
+            self.count_votes();
+        }
+
+        Ok(())
+    }
+
+    /// A player is accused when more than half the table votes for them. The
+    /// spy gets away if nobody is, or the wrong player is.
+    fn count_votes(&mut self) {
+        self.deadline = None;
+        let mut counts: HashMap<&str, usize> = HashMap::new();
+        for target in self.votes.values() {
+            *counts.entry(target).or_default() += 1;
+        }
+        self.accused = counts
+            .into_iter()
+            .find(|(_, n)| *n > self.players.len() / 2)
+            .map(|(target, _)| target.to_owned());
+
+        match &self.accused {
+            Some(accused) if self.is_spy(accused) => self.phase = Phase::SpyGuessing,
+            _ => self.finish(Winner::Spy),
+        }
+    }
+
+    fn guess_location(&mut self, user: &str, guess: String) -> Result<(), InvalidMove> {
+        if !matches!(self.phase, Phase::Questioning | Phase::SpyGuessing) {
+            return Err(self.wrong_state());
+        }
+        if !self.is_spy(user) {
+            return Err(InvalidMove::NotYourTurn {
+                msg: "Only the spy can guess the location".to_owned(),
+            });
+        }
+
+        let guess = text::fold(&guess);
+        let Some(guess) = self
+            .locations
+            .iter()
+            .find(|l| text::fold(l) == guess)
+            .cloned()
+        else {
+            return Err(InvalidMove::NotAllowed {
+                msg: "That isn't one of the locations".to_owned(),
+            });
+        };
+
+        let correct = self.location.as_ref() == Some(&guess);
+        self.spy_guess = Some(guess);
+        self.finish(if correct {
+            Winner::Spy
+        } else {
+            Winner::Players
+        });
+
+        Ok(())
+    }
+
+    fn finish(&mut self, winner: Winner) {
+        self.winner = Some(winner);
+        self.phase = Phase::GameOver;
+        self.deadline = None;
+    }
+}
+
+impl Game for Spyfall {
+    fn make_move(&mut self, user: &str, action: Value) -> Result<(), InvalidMove> {
+        match parse_move(action)? {
+            SpyfallMove::CallVote => {
+                if self.phase != Phase::Questioning {
+                    return Err(self.wrong_state());
+                }
+                self.check_player(user)?;
+                self.start_vote(Instant::now());
+                Ok(())
+            }
+            SpyfallMove::Vote(target) => self.vote(user, target),
+            SpyfallMove::GuessLocation(guess) => self.guess_location(user, guess),
+        }
+    }
+
+    /// The spy never sees the location and players never see the spy, other
+    /// players' roles or votes still being cast, until the game is over
+    fn filter(&self, user: &str) -> Value {
+        if self.phase == Phase::GameOver {
+            return to_json(self);
+        }
+
+        let mut view = self.clone();
+        let is_spy = self.is_spy(user);
+        if is_spy || !self.players.iter().any(|p| p == user) {
+            view.location = None;
+        }
+        // Once caught the spy is known to everyone
+        if !is_spy && self.phase != Phase::SpyGuessing {
+            view.spy = None;
+        }
+        view.roles.retain(|p, _| p == user);
+        if self.phase == Phase::Voting {
+            view.votes.retain(|p, _| p == user);
+        }
+
+        to_json(&view)
+    }
+
+    fn state(&self) -> Value {
+        to_json(self)
+    }
+
+    fn phase(&self) -> String {
+        format!("{:?}", self.phase)
+    }
+
+    fn is_over(&self) -> bool {
+        self.phase == Phase::GameOver
+    }
+
+    fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    /// Questions stop and voting starts once the round timer runs out. Votes
+    /// are counted once the vote timer does, even if some players never voted.
+    fn tick(&mut self, now: Instant) -> bool {
+        match (self.phase, self.deadline) {
+            (Phase::Questioning, Some(d)) if now >= d => {
+                self.start_vote(now);
+                true
+            }
+            (Phase::Voting, Some(d)) if now >= d => {
+                self.count_votes();
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::games::location_list::Location;
+    use serde_json::json;
+
+    fn players() -> Vec<String> {
+        ["alice", "bob", "carol", "dave"].map(String::from).to_vec()
+    }
+
+    fn locations() -> LocationList {
+        let location = |name: &str, roles: &[&str]| Location {
+            name: name.to_owned(),
+            roles: roles.iter().map(|r| r.to_string()).collect(),
+        };
+        LocationList {
+            description: "Test".to_owned(),
+            locations: vec![
+                location("Beach", &["Lifeguard", "Surfer"]),
+                location("Bank", &["Teller", "Robber"]),
+            ],
+        }
+    }
+
+    fn game() -> Spyfall {
+        Spyfall::new(&players(), &locations(), SpyfallSettings::default()).unwrap()
+    }
+
+    fn spy(g: &Spyfall) -> String {
+        g.spy.clone().unwrap()
+    }
+
+    fn innocent(g: &Spyfall, n: usize) -> String {
+        g.players
+            .iter()
+            .filter(|p| !g.is_spy(p))
+            .nth(n)
+            .unwrap()
+            .clone()
+    }
+
+    fn wrong_location(g: &Spyfall) -> String {
+        g.locations
+            .iter()
+            .find(|l| Some(*l) != g.location.as_ref())
+            .unwrap()
+            .clone()
+    }
+
+    fn vote(g: &mut Spyfall, user: &str, target: &str) -> Result<(), InvalidMove> {
+        g.make_move(user, json!({ "actionType": "vote", "data": target }))
+    }
+
+    fn call_vote(g: &mut Spyfall) {
+        g.make_move("alice", json!({ "actionType": "callVote" }))
+            .unwrap();
+    }
+
+    /// Every player votes for `target`, who votes for someone else
+    fn everyone_votes_for(g: &mut Spyfall, target: &str) {
+        let other = g.players.iter().find(|p| *p != target).unwrap().clone();
+        for p in g.players.clone() {
+            let t = if p == target { &other } else { target };
+            vote(g, &p, t).unwrap();
+        }
+    }
+
+    #[test]
+    fn everyone_but_the_spy_gets_a_role() {
+        let g = game();
+        assert_eq!(g.roles.len(), 3);
+        assert!(!g.roles.contains_key(&spy(&g)));
+
+        let location = locations()
+            .locations
+            .into_iter()
+            .find(|l| Some(&l.name) == g.location.as_ref())
+            .unwrap();
+        assert!(g.roles.values().all(|r| location.roles.contains(r)));
+        assert_eq!(g.phase, Phase::Questioning);
+    }
+
+    #[test]
+    fn needs_enough_players() {
+        let two = players()[..2].to_vec();
+        assert!(Spyfall::new(&two, &locations(), SpyfallSettings::default()).is_err());
+    }
+
+    #[test]
+    fn round_length_is_clamped() {
+        let settings = SpyfallSettings {
+            round_secs: u64::MAX,
+        };
+        let g = Spyfall::new(&players(), &locations(), settings).unwrap();
+        let limit = Instant::now() + Duration::from_secs(MAX_TIMER_SECS);
+        assert!(g.deadline.unwrap() <= limit);
+    }
+
+    #[test]
+    fn spy_never_sees_the_location() {
+        let g = game();
+        let view = g.filter(&spy(&g));
+        assert!(view["location"].is_null());
+        assert_eq!(view["spy"], json!(spy(&g)));
+        assert_eq!(view["roles"], json!({}));
+    }
+
+    #[test]
+    fn players_see_the_location_and_only_their_role() {
+        let g = game();
+        let user = innocent(&g, 0);
+        let view = g.filter(&user);
+
+        assert_eq!(view["location"], json!(g.location));
+        assert!(view["spy"].is_null());
+        assert_eq!(view["roles"], json!({ user.clone(): g.roles[&user] }));
+    }
+
+    #[test]
+    fn spectators_see_neither() {
+        let g = game();
+        let view = g.filter("spectator");
+        assert!(view["location"].is_null());
+        assert!(view["spy"].is_null());
+        assert_eq!(view["roles"], json!({}));
+    }
+
+    #[test]
+    fn voting_starts_when_time_runs_out() {
+        let mut g = game();
+        let deadline = g.deadline().unwrap();
+
+        assert!(!g.tick(deadline - Duration::from_secs(1)));
+        assert_eq!(g.phase, Phase::Questioning);
+
+        assert!(g.tick(deadline));
+        assert_eq!(g.phase, Phase::Voting);
+        assert_eq!(
+            g.deadline(),
+            Some(deadline + Duration::from_secs(VOTE_SECS))
+        );
+        assert!(!g.tick(deadline + Duration::from_secs(10)));
+    }
+
+    #[test]
+    fn votes_are_counted_when_time_runs_out() {
+        let mut g = game();
+        let spy = spy(&g);
+        call_vote(&mut g);
+
+        // The spy never votes but the rest of the table still catches them
+        for p in g.players.clone().iter().filter(|p| **p != spy) {
+            vote(&mut g, p, &spy).unwrap();
+        }
+        assert_eq!(g.phase, Phase::Voting);
+
+        let deadline = g.deadline().unwrap();
+        assert!(!g.tick(deadline - Duration::from_secs(1)));
+        assert!(g.tick(deadline));
+        assert_eq!(g.accused, Some(spy));
+        assert_eq!(g.phase, Phase::SpyGuessing);
+        assert_eq!(g.deadline(), None);
+    }
+
+    #[test]
+    fn anyone_can_call_a_vote_early() {
+        let mut g = game();
+        call_vote(&mut g);
+        assert_eq!(g.phase, Phase::Voting);
+
+        let res = g.make_move("bob", json!({ "actionType": "callVote" }));
+        assert!(matches!(res, Err(InvalidMove::WrongState { .. })));
+    }
+
+    #[test]
+    fn voting_only_during_the_vote() {
+        let mut g = game();
+        let res = vote(&mut g, "alice", "bob");
+        assert!(matches!(res, Err(InvalidMove::WrongState { .. })));
+    }
+
+    #[test]
+    fn bad_votes_are_refused() {
+        let mut g = game();
+        call_vote(&mut g);
+
+        assert!(matches!(
+            vote(&mut g, "alice", "alice"),
+            Err(InvalidMove::NotAllowed { .. })
+        ));
+        assert!(matches!(
+            vote(&mut g, "alice", "zed"),
+            Err(InvalidMove::InvalidUser { .. })
+        ));
+        assert!(matches!(
+            vote(&mut g, "spectator", "alice"),
+            Err(InvalidMove::InvalidUser { .. })
+        ));
+    }
+
+    #[test]
+    fn votes_are_secret_until_everyone_has_voted() {
+        let mut g = game();
+        call_vote(&mut g);
+        vote(&mut g, "alice", "bob").unwrap();
+        vote(&mut g, "bob", "carol").unwrap();
+        // Changing a vote doesn't count twice
+        vote(&mut g, "bob", "alice").unwrap();
+
+        let view = g.filter("bob");
+        assert_eq!(view["votes"], json!({ "bob": "alice" }));
+        assert_eq!(view["voted"], json!(["alice", "bob"]));
+        assert_eq!(g.phase, Phase::Voting);
+    }
+
+    #[test]
+    fn catching_the_spy_gives_them_a_last_guess() {
+        let mut g = game();
+        let spy = spy(&g);
+        call_vote(&mut g);
+        everyone_votes_for(&mut g, &spy);
+
+        assert_eq!(g.phase, Phase::SpyGuessing);
+        assert_eq!(g.accused, Some(spy.clone()));
+        // The caught spy is public but the location still isn't
+        let view = g.filter(&innocent(&g, 0));
+        assert_eq!(view["spy"], json!(spy));
+        assert!(g.filter(&spy)["location"].is_null());
+
+        let guess = wrong_location(&g);
+        g.make_move(
+            &spy,
+            json!({ "actionType": "guessLocation", "data": guess }),
+        )
+        .unwrap();
+        assert_eq!(g.winner, Some(Winner::Players));
+        assert_eq!(g.phase, Phase::GameOver);
+    }
+
+    #[test]
+    fn caught_spy_can_still_win() {
+        let mut g = game();
+        let spy = spy(&g);
+        call_vote(&mut g);
+        everyone_votes_for(&mut g, &spy);
+
+        let guess = g.location.clone().unwrap().to_uppercase();
+        g.make_move(
+            &spy,
+            json!({ "actionType": "guessLocation", "data": guess }),
+        )
+        .unwrap();
+        assert_eq!(g.winner, Some(Winner::Spy));
+    }
+
+    #[test]
+    fn accusing_the_wrong_player_lets_the_spy_win() {
+        let mut g = game();
+        let innocent = innocent(&g, 0);
+        call_vote(&mut g);
+        everyone_votes_for(&mut g, &innocent);
+
+        assert_eq!(g.accused, Some(innocent));
+        assert_eq!(g.winner, Some(Winner::Spy));
+        assert_eq!(g.phase, Phase::GameOver);
+    }
+
+    #[test]
+    fn split_vote_lets_the_spy_win() {
+        let mut g = game();
+        call_vote(&mut g);
+        for (p, t) in [
+            ("alice", "bob"),
+            ("bob", "alice"),
+            ("carol", "dave"),
+            ("dave", "carol"),
+        ] {
+            vote(&mut g, p, t).unwrap();
+        }
+
+        assert_eq!(g.accused, None);
+        assert_eq!(g.winner, Some(Winner::Spy));
+    }
+
+    #[test]
+    fn spy_can_guess_during_questions() {
+        let mut g = game();
+        let user = innocent(&g, 0);
+        let guess = g.location.clone().unwrap();
+        let res = g.make_move(
+            &user,
+            json!({ "actionType": "guessLocation", "data": guess }),
+        );
+        assert!(matches!(res, Err(InvalidMove::NotYourTurn { .. })));
+
+        let res = g.make_move(
+            &spy(&g),
+            json!({ "actionType": "guessLocation", "data": "Moon" }),
+        );
+        assert!(matches!(res, Err(InvalidMove::NotAllowed { .. })));
+
+        g.make_move(
+            &spy(&g),
+            json!({ "actionType": "guessLocation", "data": guess }),
+        )
+        .unwrap();
+        assert_eq!(g.winner, Some(Winner::Spy));
+        assert_eq!(g.deadline(), None);
+    }
+
+    #[test]
+    fn everything_is_revealed_once_the_game_is_over() {
+        let mut g = game();
+        let guess = wrong_location(&g);
+        g.make_move(
+            &spy(&g),
+            json!({ "actionType": "guessLocation", "data": guess }),
+        )
+        .unwrap();
+
+        let view = g.filter("spectator");
+        assert_eq!(view["location"], json!(g.location));
+        assert_eq!(view["spy"], json!(g.spy));
+        assert_eq!(view["roles"].as_object().unwrap().len(), 3);
+    }
+}
@@ -0,0 +1,599 @@
+//! Telephone: everyone starts a notebook with a secret prompt. Notebooks are
+//! passed around the table, each player drawing the description on the page
+//! before or describing the drawing before, until every notebook has been
+//! round once. Then the host reveals the notebooks a page at a time, pages
+//! turning by themselves if the host leaves them too long.
+//!
+//! Drawings are sent as one binary frame each, an image the game stores but
+//! never looks inside. Players fetch the drawings they can see with
+//! `getCanvas`, in the order the drawing pages appear in their view.
+
+use rand::{seq::SliceRandom, thread_rng};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use super::{
+    parse_move, secs_left, to_json, word_list::WordList, Game, InvalidMove, TelephoneSettings,
+    MAX_TIMER_SECS,
+};
+use crate::text;
+
+const MIN_PLAYERS: usize = 3;
+const MAX_DESCRIPTION_CHARS: usize = 96;
+/// Largest drawing a player can hand in
+const MAX_IMAGE_BYTES: usize = 512 * 1024;
+/// How long a revealed page is shown before the next one, unless the host
+/// moves on sooner. Keeps the game going should the host leave.
+const REVEAL_PAGE_SECS: u64 = 30;
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Copy)]
+pub enum Phase {
+    Drawing,
+    Describing,
+    Reveal,
+    GameOver,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Copy)]
+pub enum PageKind {
+    Prompt,
+    Drawing,
+    Description,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Page {
+    kind: PageKind,
+    author: Option<String>,
+    text: Option<String>,
+    /// Drawings left blank when time ran out have no image
+    has_image: bool,
+    #[serde(skip)]
+    image: Option<Arc<Vec<u8>>>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Notebook {
+    owner: String,
+    pages: Vec<Page>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Telephone {
+    /// Players in the order notebooks are passed
+    players: Vec<String>,
+    /// The player who started the game, who moves the reveal on
+    host: String,
+    /// Every player fills in one page per step
+    step: usize,
+    steps: usize,
+    /// Players who have handed in this step's page
+    submitted: Vec<String>,
+    /// Nobody sees a notebook until it's revealed
+    notebooks: Vec<Notebook>,
+    /// The page a player is drawing or describing, only in their own view
+    task: Option<Page>,
+    /// The notebook being revealed and the last page of it shown
+    reveal_notebook: usize,
+    reveal_page: usize,
+    phase: Phase,
+    #[serde(rename = "secsLeft", serialize_with = "secs_left")]
+    deadline: Option<Instant>,
+
+    #[serde(skip)]
+    settings: TelephoneSettings,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "actionType", content = "data")]
+#[serde(rename_all = "camelCase")]
+pub enum TelephoneMove {
+    Describe(String),
+    /// Show the next page of the reveal, only the host can
+    NextPage,
+}
+
+impl Telephone {
+    pub fn new(
+        players: &[String],
+        host: &str,
+        prompts: &WordList,
+        settings: TelephoneSettings,
+    ) -> Result<Telephone, String> {
+        if players.len() < MIN_PLAYERS {
+            return Err(format!("Telephone needs at least {} players", MIN_PLAYERS));
+        }
+        if prompts.words.is_empty() {
+            return Err("There are no prompts to draw".to_owned());
+        }
+        if settings.draw_secs == 0 || settings.describe_secs == 0 {
+            return Err("Telephone needs time to draw and describe".to_owned());
+        }
+        let settings = TelephoneSettings {
+            draw_secs: settings.draw_secs.min(MAX_TIMER_SECS),
+            describe_secs: settings.describe_secs.min(MAX_TIMER_SECS),
+        };
+
+        let mut players = players.to_vec();
+        players.shuffle(&mut thread_rng());
+        let prompts = prompts.shuffled().words;
+        let notebooks = players
+            .iter()
+            .zip(prompts.iter().cycle())
+            .map(|(p, prompt)| Notebook {
+                owner: p.clone(),
+                pages: vec![Page {
+                    kind: PageKind::Prompt,
+                    author: None,
+                    text: Some(prompt.clone()),
+                    has_image: false,
+                    image: None,
+                }],
+            })
+            .collect();
+
+        let mut game = Telephone {
+            host: host.to_owned(),
+            steps: players.len(),
+            players,
+            step: 0,
+            submitted: Vec::new(),
+            notebooks,
+            task: None,
+            reveal_notebook: 0,
+            reveal_page: 0,
+            phase: Phase::Drawing,
+            deadline: None,
+            settings,
+        };
+        game.start_step(Instant::now());
+
+        Ok(game)
+    }
+
+    /// Drawing and describing take turns, starting with drawing the prompt
+    fn start_step(&mut self, now: Instant) {
+        self.submitted.clear();
+        let secs = if self.step.is_multiple_of(2) {
+            self.phase = Phase::Drawing;
+            self.settings.draw_secs
+        } else {
+            self.phase = Phase::Describing;
+            self.settings.describe_secs
+        };
+        self.deadline = Some(now + Duration::from_secs(secs));
+    }
+
+    /// Index of the notebook `user` fills in this step
+    fn notebook_of(&self, user: &str) -> Option<usize> {
+        let seat = self.players.iter().position(|p| p == user)?;
+        let n = self.players.len();
+        Some((seat + n - self.step % n) % n)
+    }
+
+    /// Check `user` can hand in a page during `phase` and find their notebook
+    fn check_turn(&self, user: &str, phase: Phase) -> Result<usize, InvalidMove> {
+        if self.phase != phase {
+            return Err(InvalidMove::WrongState {
+                msg: format!("Can't do that during {:?}", self.phase),
+            });
+        }
+        let Some(notebook) = self.notebook_of(user) else {
+            return Err(InvalidMove::InvalidUser {
+                msg: format!("{} is not playing", user),
+            });
+        };
+        if self.submitted.iter().any(|p| p == user) {
+            return Err(InvalidMove::WrongState {
+                msg: "You've already handed in this page".to_owned(),
+            });
+        }
+        Ok(notebook)
+    }
+
+    fn add_page(&mut self, user: &str, notebook: usize, page: Page, now: Instant) {
+        self.notebooks[notebook].pages.push(page);
+        self.submitted.push(user.to_owned());
+        if self.submitted.len() == self.players.len() {
+            self.finish_step(now);
+        }
+    }
+
+    fn describe(&mut self, user: &str, text: &str, now: Instant) -> Result<(), InvalidMove> {
+        let notebook = self.check_turn(user, Phase::Describing)?;
+        let text = text::clean(text, MAX_DESCRIPTION_CHARS)
+            .map_err(|e| InvalidMove::from_text("Description", e))?;
+
+        let page = Page {
+            kind: PageKind::Description,
+            author: Some(user.to_owned()),
+            text: Some(text),
+            has_image: false,
+            image: None,
+        };
+        self.add_page(user, notebook, page, now);
+        Ok(())
+    }
+
+    fn draw(&mut self, user: &str, image: &Arc<Vec<u8>>, now: Instant) -> Result<(), InvalidMove> {
+        let notebook = self.check_turn(user, Phase::Drawing)?;
+        if image.is_empty() {
+            return Err(InvalidMove::EmptyInput {
+                msg: "Drawings can't be empty".to_owned(),
+            });
+        }
+        if image.len() > MAX_IMAGE_BYTES {
+            return Err(InvalidMove::TooLong {
+                msg: format!("Drawings can be at most {} bytes", MAX_IMAGE_BYTES),
+            });
+        }
+
+        let page = Page {
+            kind: PageKind::Drawing,
+            author: Some(user.to_owned()),
+            text: None,
+            has_image: true,
+            image: Some(image.clone()),
+        };
+        self.add_page(user, notebook, page, now);
+        Ok(())
+    }
+
+    /// Every page is in, or time ran out and missing pages are left blank.
+    /// Notebooks move on to the next player, or the reveal starts once they
+    /// have been all the way round.
+    fn finish_step(&mut self, now: Instant) {
+        let kind = match self.phase {
+            Phase::Drawing => PageKind::Drawing,
+            _ => PageKind::Description,
+        };
+        for p in self.players.clone() {
+            if self.submitted.contains(&p) {
+                continue;
+            }
+            let notebook = self.notebook_of(&p).expect("Players have a notebook");
+            self.notebooks[notebook].pages.push(Page {
+                kind,
+                author: Some(p),
+                text: (kind == PageKind::Description).then(String::new),
+                has_image: false,
+                image: None,
+            });
+        }
+
+        self.step += 1;
+        if self.step < self.steps {
+            self.start_step(now);
+        } else {
+            self.submitted.clear();
+            self.phase = Phase::Reveal;
+            self.deadline = Some(now + Duration::from_secs(REVEAL_PAGE_SECS));
+        }
+    }
+
+    fn next_page(&mut self, user: &str, now: Instant) -> Result<(), InvalidMove> {
+        if self.phase != Phase::Reveal {
+            return Err(InvalidMove::WrongState {
+                msg: format!("Can't do that during {:?}", self.phase),
+            });
+        }
+        if user != self.host {
+            return Err(InvalidMove::NotYourTurn {
+                msg: format!("Only {} can move the reveal on", self.host),
+            });
+        }
+
+        self.turn_page(now);
+        Ok(())
+    }
+
+    /// Show the next page, moving on to the next notebook after the last
+    fn turn_page(&mut self, now: Instant) {
+        self.deadline = Some(now + Duration::from_secs(REVEAL_PAGE_SECS));
+        if self.reveal_page + 1 < self.notebooks[self.reveal_notebook].pages.len() {
+            self.reveal_page += 1;
+        } else if self.reveal_notebook + 1 < self.notebooks.len() {
+            self.reveal_notebook += 1;
+            self.reveal_page = 0;
+        } else {
+            self.phase = Phase::GameOver;
+            self.deadline = None;
+        }
+    }
+
+    /// The game as `user` sees it: the page they're working from while
+    /// playing, and only the pages revealed so far after
+    fn view(&self, user: &str) -> Telephone {
+        let mut view = self.clone();
+        match self.phase {
+            Phase::Drawing | Phase::Describing => {
+                view.notebooks.clear();
+                view.task = self
+                    .notebook_of(user)
+                    .filter(|_| !self.submitted.iter().any(|p| p == user))
+                    .and_then(|n| self.notebooks[n].pages.last().cloned());
+            }
+            Phase::Reveal => {
+                view.notebooks.truncate(self.reveal_notebook + 1);
+                view.notebooks[self.reveal_notebook]
+                    .pages
+                    .truncate(self.reveal_page + 1);
+            }
+            Phase::GameOver => {}
+        }
+        view
+    }
+}
+
+impl Game for Telephone {
+    fn make_move(&mut self, user: &str, action: Value) -> Result<(), InvalidMove> {
+        match parse_move(action)? {
+            TelephoneMove::Describe(text) => self.describe(user, &text, Instant::now()),
+            TelephoneMove::NextPage => self.next_page(user, Instant::now()),
+        }
+    }
+
+    fn filter(&self, user: &str) -> Value {
+        to_json(&self.view(user))
+    }
+
+    fn state(&self) -> Value {
+        to_json(self)
+    }
+
+    fn phase(&self) -> String {
+        format!("{:?}", self.phase)
+    }
+
+    fn is_over(&self) -> bool {
+        self.phase == Phase::GameOver
+    }
+
+    fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    fn tick(&mut self, now: Instant) -> bool {
+        match self.deadline {
+            Some(d) if now >= d => {
+                match self.phase {
+                    Phase::Reveal => self.turn_page(now),
+                    _ => self.finish_step(now),
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// A finished drawing, kept private until the reveal
+    fn binary(
+        &mut self,
+        user: &str,
+        data: &Arc<Vec<u8>>,
+    ) -> Result<Option<Arc<Vec<u8>>>, InvalidMove> {
+        self.draw(user, data, Instant::now())?;
+        Ok(None)
+    }
+
+    /// Images of the drawing pages in `user`'s view, in order
+    fn binary_history(&self, user: &str) -> Vec<Arc<Vec<u8>>> {
+        let view = self.view(user);
+        view.task
+            .iter()
+            .chain(view.notebooks.iter().flat_map(|n| &n.pages))
+            .filter_map(|p| p.image.clone())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn players() -> Vec<String> {
+        ["alice", "bob", "carol"].map(String::from).to_vec()
+    }
+
+    fn game() -> Telephone {
+        let prompts = WordList {
+            description: "Test".to_owned(),
+            words: ["Apple", "Boat", "Castle"].map(String::from).to_vec(),
+        };
+        Telephone::new(&players(), "alice", &prompts, TelephoneSettings::default()).unwrap()
+    }
+
+    fn image(byte: u8) -> Arc<Vec<u8>> {
+        Arc::new(vec![byte; 4])
+    }
+
+    fn now(g: &Telephone) -> Instant {
+        g.deadline.unwrap()
+    }
+
+    /// Everyone hands in a page for the current step
+    fn play_step(g: &mut Telephone) {
+        let now = now(g);
+        for (i, p) in g.players.clone().iter().enumerate() {
+            match g.phase {
+                Phase::Drawing => g.draw(p, &image(i as u8 + 1), now).unwrap(),
+                _ => g.describe(p, &format!("guess {}", i), now).unwrap(),
+            }
+        }
+    }
+
+    #[test]
+    fn needs_enough_players() {
+        let prompts = WordList {
+            description: "Test".to_owned(),
+            words: vec!["Apple".to_owned()],
+        };
+        let res = Telephone::new(
+            &players()[..2],
+            "alice",
+            &prompts,
+            TelephoneSettings::default(),
+        );
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn everyone_starts_drawing_their_own_prompt() {
+        let g = game();
+        assert_eq!(g.phase, Phase::Drawing);
+        assert_eq!(g.steps, 3);
+        for (i, p) in g.players.iter().enumerate() {
+            assert_eq!(g.notebook_of(p), Some(i));
+            let view = g.filter(p);
+            assert_eq!(view["task"]["kind"], json!("Prompt"));
+            assert_eq!(view["task"]["text"], json!(g.notebooks[i].pages[0].text));
+            assert_eq!(view["notebooks"], json!([]));
+        }
+    }
+
+    #[test]
+    fn notebooks_pass_round_the_table() {
+        let mut g = game();
+        play_step(&mut g);
+        assert_eq!(g.phase, Phase::Describing);
+
+        // Each player now describes the drawing of the player before them
+        let first = g.players[0].clone();
+        let last = g.players[2].clone();
+        assert_eq!(g.notebook_of(&first), Some(2));
+        let view = g.filter(&first);
+        assert_eq!(view["task"]["kind"], json!("Drawing"));
+        assert_eq!(view["task"]["author"], json!(last));
+        assert_eq!(g.binary_history(&first), vec![image(3)]);
+    }
+
+    #[test]
+    fn drawings_are_not_relayed() {
+        let mut g = game();
+        let p = g.players[0].clone();
+        assert!(matches!(g.binary(&p, &image(1)), Ok(None)));
+        assert_eq!(g.submitted, [p.as_str()]);
+        // The drawing is handed in so there's nothing left to work on
+        assert_eq!(g.filter(&p)["task"], Value::Null);
+        assert!(g.binary_history(&g.players[1]).is_empty());
+    }
+
+    #[test]
+    fn pages_are_checked() {
+        let mut g = game();
+        let p = g.players[0].clone();
+        let now = now(&g);
+
+        assert!(matches!(
+            g.describe(&p, "a red fruit", now),
+            Err(InvalidMove::WrongState { .. })
+        ));
+        assert!(matches!(
+            g.draw(&p, &Arc::new(Vec::new()), now),
+            Err(InvalidMove::EmptyInput { .. })
+        ));
+        assert!(matches!(
+            g.draw(&p, &Arc::new(vec![0; MAX_IMAGE_BYTES + 1]), now),
+            Err(InvalidMove::TooLong { .. })
+        ));
+        assert!(matches!(
+            g.draw("spectator", &image(1), now),
+            Err(InvalidMove::InvalidUser { .. })
+        ));
+        g.draw(&p, &image(1), now).unwrap();
+        assert!(matches!(
+            g.draw(&p, &image(1), now),
+            Err(InvalidMove::WrongState { .. })
+        ));
+    }
+
+    #[test]
+    fn missing_pages_are_left_blank_when_time_runs_out() {
+        let mut g = game();
+        let p = g.players[0].clone();
+        g.draw(&p, &image(1), now(&g)).unwrap();
+
+        assert!(g.tick(now(&g)));
+        assert_eq!(g.phase, Phase::Describing);
+        assert!(g.notebooks.iter().all(|n| n.pages.len() == 2));
+        assert!(g.notebooks[0].pages[1].has_image);
+        assert!(!g.notebooks[1].pages[1].has_image);
+    }
+
+    #[test]
+    fn host_steps_through_the_reveal() {
+        let mut g = game();
+        for _ in 0..3 {
+            play_step(&mut g);
+        }
+        assert_eq!(g.phase, Phase::Reveal);
+        assert!(g.notebooks.iter().all(|n| n.pages.len() == 4));
+
+        let view = g.filter("bob");
+        assert_eq!(view["notebooks"].as_array().unwrap().len(), 1);
+        assert_eq!(view["notebooks"][0]["pages"].as_array().unwrap().len(), 1);
+        assert!(g.binary_history("bob").is_empty());
+
+        let now = Instant::now();
+        assert!(matches!(
+            g.next_page("bob", now),
+            Err(InvalidMove::NotYourTurn { .. })
+        ));
+        g.next_page("alice", now).unwrap();
+        assert_eq!(
+            g.filter("bob")["notebooks"][0]["pages"][1]["kind"],
+            json!("Drawing")
+        );
+        assert_eq!(g.binary_history("bob").len(), 1);
+
+        // The rest of the first notebook then the other two
+        for _ in 0..10 {
+            g.next_page("alice", now).unwrap();
+        }
+        assert_eq!(g.phase, Phase::Reveal);
+        g.next_page("alice", now).unwrap();
+        assert_eq!(g.phase, Phase::GameOver);
+        assert_eq!(g.deadline(), None);
+        // Two drawings in each notebook
+        assert_eq!(g.binary_history("bob").len(), 6);
+    }
+
+    #[test]
+    fn reveal_moves_on_without_the_host() {
+        let mut g = game();
+        for _ in 0..3 {
+            play_step(&mut g);
+        }
+
+        // Nobody touches the reveal, as when the host has left
+        for _ in 0..12 {
+            assert_eq!(g.phase, Phase::Reveal);
+            assert!(g.tick(now(&g)));
+        }
+        assert_eq!(g.phase, Phase::GameOver);
+        assert_eq!(g.deadline(), None);
+    }
+
+    #[test]
+    fn timers_are_clamped() {
+        let prompts = WordList {
+            description: "Test".to_owned(),
+            words: vec!["Apple".to_owned()],
+        };
+        let settings = TelephoneSettings {
+            draw_secs: u64::MAX,
+            describe_secs: u64::MAX,
+        };
+        let g = Telephone::new(&players(), "alice", &prompts, settings).unwrap();
+        assert_eq!(g.settings.draw_secs, MAX_TIMER_SECS);
+        assert_eq!(g.settings.describe_secs, MAX_TIMER_SECS);
+    }
+}
@@ -0,0 +1,167 @@
+//! Tic-Tac-Toe: two players take turns marking cells of a 3x3 grid, the
+//! first with three in a row wins.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::{grid::GridGame, parse_move, to_json, Game, InvalidMove};
+
+const SIZE: usize = 3;
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct TicTacToe {
+    #[serde(flatten)]
+    grid: GridGame,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "actionType", content = "data")]
+#[serde(rename_all = "camelCase")]
+pub enum TicTacToeMove {
+    /// Mark the cell at `row` and `col`, counting from the top left
+    Mark { row: usize, col: usize },
+}
+
+impl TicTacToe {
+    pub fn new(players: &[String]) -> Result<TicTacToe, String> {
+        Ok(TicTacToe {
+            grid: GridGame::new("Tic-Tac-Toe", players, SIZE, SIZE, SIZE)?,
+        })
+    }
+
+    fn mark(&mut self, user: &str, row: usize, col: usize) -> Result<(), InvalidMove> {
+        self.grid.check_turn(user)?;
+        if row >= SIZE || col >= SIZE {
+            return Err(InvalidMove::NotAllowed {
+                msg: format!("The grid is {} by {}", SIZE, SIZE),
+            });
+        }
+        if self.grid.cell(row, col).is_some() {
+            return Err(InvalidMove::NotAllowed {
+                msg: "That cell is taken".to_owned(),
+            });
+        }
+
+        self.grid.claim(row, col);
+        Ok(())
+    }
+}
+
+impl Game for TicTacToe {
+    fn make_move(&mut self, user: &str, action: Value) -> Result<(), InvalidMove> {
+        match parse_move(action)? {
+            TicTacToeMove::Mark { row, col } => self.mark(user, row, col),
+        }
+    }
+
+    fn filter(&self, _user: &str) -> Value {
+        to_json(self)
+    }
+
+    /// Nothing is hidden so everyone shares one view
+    fn audience(&self, _user: &str) -> Option<String> {
+        Some("everyone".to_owned())
+    }
+
+    fn state(&self) -> Value {
+        to_json(self)
+    }
+
+    fn phase(&self) -> String {
+        format!("{:?}", self.grid.phase())
+    }
+
+    fn is_over(&self) -> bool {
+        self.grid.is_over()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::games::grid::Phase;
+    use serde_json::json;
+
+    fn game() -> TicTacToe {
+        TicTacToe::new(&["alice", "bob", "carol"].map(String::from)).unwrap()
+    }
+
+    /// Mark cells in turn, starting with whoever goes first
+    fn play(g: &mut TicTacToe, cells: &[(usize, usize)]) {
+        for (row, col) in cells {
+            let user = g.grid.current().to_owned();
+            let m = json!({ "actionType": "mark", "data": { "row": row, "col": col } });
+            g.make_move(&user, m).unwrap();
+        }
+    }
+
+    #[test]
+    fn three_in_a_row_wins() {
+        let mut g = game();
+        let first = g.grid.current().to_owned();
+        play(&mut g, &[(0, 0), (1, 0), (0, 1), (1, 1), (0, 2)]);
+
+        assert_eq!(g.grid.phase(), Phase::GameOver);
+        assert_eq!(g.grid.winner(), Some(first.as_str()));
+        assert_eq!(g.filter("carol")["line"], json!([0, 1, 2]));
+    }
+
+    #[test]
+    fn full_board_is_a_draw() {
+        let mut g = game();
+        play(
+            &mut g,
+            &[
+                (0, 0),
+                (0, 1),
+                (0, 2),
+                (1, 1),
+                (1, 0),
+                (1, 2),
+                (2, 1),
+                (2, 0),
+                (2, 2),
+            ],
+        );
+
+        assert_eq!(g.grid.phase(), Phase::GameOver);
+        assert_eq!(g.grid.winner(), None);
+    }
+
+    #[test]
+    fn bad_marks_are_refused() {
+        let mut g = game();
+        let first = g.grid.current().to_owned();
+        let second = if first == "alice" { "bob" } else { "alice" };
+
+        assert!(matches!(
+            g.mark(second, 0, 0),
+            Err(InvalidMove::NotYourTurn { .. })
+        ));
+        assert!(matches!(
+            g.mark("carol", 0, 0),
+            Err(InvalidMove::InvalidUser { .. })
+        ));
+        assert!(matches!(
+            g.mark(&first, 3, 0),
+            Err(InvalidMove::NotAllowed { .. })
+        ));
+        g.mark(&first, 0, 0).unwrap();
+        assert!(matches!(
+            g.mark(second, 0, 0),
+            Err(InvalidMove::NotAllowed { .. })
+        ));
+    }
+
+    #[test]
+    fn no_moves_after_the_game() {
+        let mut g = game();
+        play(&mut g, &[(0, 0), (1, 0), (0, 1), (1, 1), (0, 2)]);
+        let user = g.grid.current().to_owned();
+        assert!(matches!(
+            g.mark(&user, 2, 2),
+            Err(InvalidMove::WrongState { .. })
+        ));
+    }
+}
@@ -0,0 +1,480 @@
+//! Trivia: everyone answers the same question against the clock. Answers
+//! stay hidden until time is up or everyone is in, then they're revealed
+//! together with the leaderboard.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use super::{
+    just_one::validation,
+    parse_move,
+    question_pack::{Question, QuestionPack},
+    secs_left, to_json, Game, InvalidMove, TriviaSettings, MAX_TIMER_SECS,
+};
+use crate::text;
+
+/// How long answers and the leaderboard are shown between questions
+const REVEAL_SECS: u64 = 8;
+/// Every correct answer scores at least this much
+const CORRECT_POINTS: u32 = 100;
+/// Extra points for answering straight away, falling to nothing as time runs out
+const SPEED_POINTS: u32 = 400;
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Copy)]
+pub enum Phase {
+    Answering,
+    Reveal,
+    GameOver,
+}
+
+/// The question as players see it while answering
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Prompt {
+    category: String,
+    question: String,
+    choices: Vec<String>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Answer {
+    text: String,
+    /// Scored once answers are revealed
+    correct: bool,
+    points: u32,
+    #[serde(skip)]
+    at: Instant,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Standing {
+    player: String,
+    score: u32,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Trivia {
+    players: Vec<String>,
+    /// Players by score, highest first, as of the last reveal
+    leaderboard: Vec<Standing>,
+    /// Which question this is, counting from 1
+    number: usize,
+    questions: usize,
+    prompt: Prompt,
+    /// Hidden until answers are revealed
+    answer: Option<String>,
+    /// Players who have answered, the answers themselves stay hidden
+    answered: Vec<String>,
+    answers: HashMap<String, Answer>,
+    phase: Phase,
+    #[serde(rename = "secsLeft", serialize_with = "secs_left")]
+    deadline: Option<Instant>,
+
+    #[serde(skip)]
+    scores: HashMap<String, u32>,
+    /// Questions still to come, the current one first
+    #[serde(skip)]
+    remaining: Vec<Question>,
+    #[serde(skip)]
+    settings: TriviaSettings,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "actionType", content = "data")]
+#[serde(rename_all = "camelCase")]
+pub enum TriviaMove {
+    /// One of the choices, or any text for free text questions
+    Answer(String),
+}
+
+impl Trivia {
+    /// Pick questions from the categories in `settings` and ask the first
+    pub fn new(
+        players: &[String],
+        pack: &QuestionPack,
+        settings: TriviaSettings,
+    ) -> Result<Trivia, String> {
+        if settings.questions == 0 || settings.answer_secs == 0 {
+            return Err("Trivia needs at least one question with time to answer".to_owned());
+        }
+        let settings = TriviaSettings {
+            answer_secs: settings.answer_secs.min(MAX_TIMER_SECS),
+            ..settings
+        };
+        let remaining = pack.pick(&settings.categories, settings.questions);
+        if remaining.is_empty() {
+            return Err(format!(
+                "There are no questions in those categories, pick from {}",
+                pack.categories().join(", ")
+            ));
+        }
+
+        let mut game = Trivia {
+            players: players.to_vec(),
+            leaderboard: players
+                .iter()
+                .map(|p| Standing {
+                    player: p.clone(),
+                    score: 0,
+                })
+                .collect(),
+            number: 0,
+            questions: remaining.len(),
+            prompt: Prompt {
+                category: String::new(),
+                question: String::new(),
+                choices: Vec::new(),
+            },
+            answer: None,
+            answered: Vec::new(),
+            answers: HashMap::new(),
+            phase: Phase::Answering,
+            deadline: None,
+            scores: players.iter().map(|p| (p.clone(), 0)).collect(),
+            remaining,
+            settings,
+        };
+        game.ask(Instant::now());
+
+        Ok(game)
+    }
+
+    fn ask(&mut self, now: Instant) {
+        let q = &self.remaining[0];
+        self.number += 1;
+        self.prompt = Prompt {
+            category: q.category.clone(),
+            question: q.question.clone(),
+            choices: q.choices.clone(),
+        };
+        self.answer = None;
+        self.answered.clear();
+        self.answers.clear();
+        self.phase = Phase::Answering;
+        self.deadline = Some(now + self.answer_time());
+    }
+
+    fn answer_time(&self) -> Duration {
+        Duration::from_secs(self.settings.answer_secs)
+    }
+
+    fn submit(&mut self, user: &str, text: &str, now: Instant) -> Result<(), InvalidMove> {
+        if self.phase != Phase::Answering {
+            return Err(InvalidMove::WrongState {
+                msg: format!("Can't do that during {:?}", self.phase),
+            });
+        }
+        if !self.players.iter().any(|p| p == user) {
+            return Err(InvalidMove::InvalidUser {
+                msg: format!("{} is not playing", user),
+            });
+        }
+        if self.answers.contains_key(user) {
+            return Err(InvalidMove::WrongState {
+                msg: "You've already answered".to_owned(),
+            });
+        }
+
+        let text = if self.prompt.choices.is_empty() {
+            validation::guess(text)?
+        } else {
+            let folded = text::fold(text);
+            self.prompt
+                .choices
+                .iter()
+                .find(|c| text::fold(c) == folded)
+                .cloned()
+                .ok_or_else(|| InvalidMove::NotAllowed {
+                    msg: "That isn't one of the choices".to_owned(),
+                })?
+        };
+
+        self.answers.insert(
+            user.to_owned(),
+            Answer {
+                text,
+                correct: false,
+                points: 0,
+                at: now,
+            },
+        );
+        self.answered.push(user.to_owned());
+        if self.answered.len() == self.players.len() {
+            self.reveal(now);
+        }
+
+        Ok(())
+    }
+
+    /// Score every answer, faster correct answers scoring more, and show
+    /// them along with the leaderboard
+    fn reveal(&mut self, now: Instant) {
+        let q = self.remaining.remove(0);
+        let total = self.answer_time().as_millis();
+        let deadline = self.deadline.unwrap_or(now);
+
+        for (player, a) in self.answers.iter_mut() {
+            a.correct = if q.choices.is_empty() {
+                std::iter::once(&q.answer)
+                    .chain(&q.alternatives)
+                    .any(|ans| validation::matches(&a.text, ans))
+            } else {
+                a.text == q.answer
+            };
+            if a.correct {
+                let left = deadline
+                    .saturating_duration_since(a.at)
+                    .as_millis()
+                    .min(total);
+                a.points = CORRECT_POINTS + (u128::from(SPEED_POINTS) * left / total) as u32;
+                *self.scores.entry(player.clone()).or_default() += a.points;
+            }
+        }
+
+        self.leaderboard = self
+            .players
+            .iter()
+            .map(|p| Standing {
+                player: p.clone(),
+                score: self.scores.get(p).copied().unwrap_or_default(),
+            })
+            .collect();
+        self.leaderboard
+            .sort_by(|a, b| b.score.cmp(&a.score).then_with(|| a.player.cmp(&b.player)));
+
+        self.answer = Some(q.answer);
+        if self.remaining.is_empty() {
+            self.phase = Phase::GameOver;
+            self.deadline = None;
+        } else {
+            self.phase = Phase::Reveal;
+            self.deadline = Some(now + Duration::from_secs(REVEAL_SECS));
+        }
+    }
+}
+
+impl Game for Trivia {
+    fn make_move(&mut self, user: &str, action: Value) -> Result<(), InvalidMove> {
+        match parse_move(action)? {
+            TriviaMove::Answer(text) => self.submit(user, &text, Instant::now()),
+        }
+    }
+
+    /// Players only see their own answer until everyone's are revealed
+    fn filter(&self, user: &str) -> Value {
+        if self.phase != Phase::Answering {
+            return to_json(self);
+        }
+
+        let mut view = self.clone();
+        view.answers.retain(|p, _| p == user);
+        to_json(&view)
+    }
+
+    fn state(&self) -> Value {
+        to_json(self)
+    }
+
+    fn phase(&self) -> String {
+        format!("{:?}", self.phase)
+    }
+
+    fn is_over(&self) -> bool {
+        self.phase == Phase::GameOver
+    }
+
+    fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    /// Answers are revealed when time runs out, and the next question is
+    /// asked once everyone has seen them
+    fn tick(&mut self, now: Instant) -> bool {
+        match (self.phase, self.deadline) {
+            (Phase::Answering, Some(d)) if now >= d => self.reveal(now),
+            (Phase::Reveal, Some(d)) if now >= d => self.ask(now),
+            _ => return false,
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn players() -> Vec<String> {
+        ["alice", "bob", "carol"].map(String::from).to_vec()
+    }
+
+    fn pack() -> QuestionPack {
+        QuestionPack {
+            description: "Test".to_owned(),
+            questions: vec![
+                Question {
+                    category: "Geography".to_owned(),
+                    question: "Longest river in South America?".to_owned(),
+                    choices: Vec::new(),
+                    answer: "Amazon".to_owned(),
+                    alternatives: vec!["The Amazon".to_owned()],
+                },
+                Question {
+                    category: "Science".to_owned(),
+                    question: "The Red Planet?".to_owned(),
+                    choices: ["Venus", "Mars"].map(String::from).to_vec(),
+                    answer: "Mars".to_owned(),
+                    alternatives: Vec::new(),
+                },
+            ],
+        }
+    }
+
+    fn settings(categories: &[&str]) -> TriviaSettings {
+        TriviaSettings {
+            categories: categories.iter().map(|c| c.to_string()).collect(),
+            ..TriviaSettings::default()
+        }
+    }
+
+    fn game(category: &str) -> Trivia {
+        Trivia::new(&players(), &pack(), settings(&[category])).unwrap()
+    }
+
+    fn start(g: &Trivia) -> Instant {
+        g.deadline.unwrap() - g.answer_time()
+    }
+
+    #[test]
+    fn questions_come_from_the_chosen_categories() {
+        let g = game("science");
+        assert_eq!(g.questions, 1);
+        assert_eq!(g.prompt.question, "The Red Planet?");
+
+        let g = Trivia::new(&players(), &pack(), settings(&[])).unwrap();
+        assert_eq!(g.questions, 2);
+    }
+
+    #[test]
+    fn question_count_is_capped() {
+        let s = TriviaSettings {
+            questions: 1,
+            ..settings(&[])
+        };
+        assert_eq!(Trivia::new(&players(), &pack(), s).unwrap().questions, 1);
+    }
+
+    #[test]
+    fn answer_time_is_clamped() {
+        let s = TriviaSettings {
+            answer_secs: u64::MAX,
+            ..settings(&[])
+        };
+        let g = Trivia::new(&players(), &pack(), s).unwrap();
+        assert_eq!(g.answer_time(), Duration::from_secs(MAX_TIMER_SECS));
+    }
+
+    #[test]
+    fn unknown_categories_are_refused() {
+        let res = Trivia::new(&players(), &pack(), settings(&["Sport"]));
+        assert_eq!(
+            res.err(),
+            Some("There are no questions in those categories, pick from Geography, Science".into())
+        );
+    }
+
+    #[test]
+    fn answers_are_hidden_until_revealed() {
+        let mut g = game("geography");
+        let now = start(&g);
+        g.submit("alice", "amazon", now).unwrap();
+
+        let view = g.filter("bob");
+        assert_eq!(view["answers"], json!({}));
+        assert_eq!(view["answered"], json!(["alice"]));
+        assert_eq!(view["answer"], Value::Null);
+        assert_eq!(
+            g.filter("alice")["answers"]["alice"]["text"],
+            json!("amazon")
+        );
+    }
+
+    #[test]
+    fn answers_are_locked_in() {
+        let mut g = game("geography");
+        let now = start(&g);
+        g.submit("alice", "amazon", now).unwrap();
+        assert!(matches!(
+            g.submit("alice", "nile", now),
+            Err(InvalidMove::WrongState { .. })
+        ));
+        assert!(matches!(
+            g.submit("spectator", "nile", now),
+            Err(InvalidMove::InvalidUser { .. })
+        ));
+    }
+
+    #[test]
+    fn choices_must_be_picked() {
+        let mut g = game("science");
+        let now = start(&g);
+        assert!(matches!(
+            g.submit("alice", "Pluto", now),
+            Err(InvalidMove::NotAllowed { .. })
+        ));
+        g.submit("alice", "mars", now).unwrap();
+        assert_eq!(g.answers["alice"].text, "Mars");
+    }
+
+    #[test]
+    fn faster_correct_answers_score_more() {
+        let mut g = game("geography");
+        let now = start(&g);
+        g.submit("alice", "The Amazon", now).unwrap();
+        g.submit("bob", "amazon", now + Duration::from_secs(10))
+            .unwrap();
+        g.submit("carol", "Nile", now).unwrap();
+
+        // Everyone answered so it's revealed straight away
+        assert_eq!(g.phase, Phase::GameOver);
+        assert_eq!(g.answer.as_deref(), Some("Amazon"));
+        assert_eq!(g.answers["alice"].points, CORRECT_POINTS + SPEED_POINTS);
+        assert!(g.answers["bob"].points > CORRECT_POINTS);
+        assert!(g.answers["bob"].points < g.answers["alice"].points);
+        assert!(!g.answers["carol"].correct);
+        assert_eq!(g.answers["carol"].points, 0);
+
+        let order: Vec<&str> = g.leaderboard.iter().map(|s| s.player.as_str()).collect();
+        assert_eq!(order, ["alice", "bob", "carol"]);
+    }
+
+    #[test]
+    fn time_running_out_reveals_answers() {
+        let mut g = Trivia::new(&players(), &pack(), settings(&[])).unwrap();
+        let deadline = g.deadline().unwrap();
+        g.submit("alice", &g.remaining[0].answer.clone(), start(&g))
+            .unwrap();
+
+        assert!(!g.tick(deadline - Duration::from_secs(1)));
+        assert!(g.tick(deadline));
+        assert_eq!(g.phase, Phase::Reveal);
+        assert_eq!(g.filter("bob")["answers"]["alice"]["correct"], json!(true));
+
+        let next = g.deadline().unwrap();
+        assert!(g.tick(next));
+        assert_eq!(g.phase, Phase::Answering);
+        assert_eq!(g.number, 2);
+        assert!(g.answers.is_empty());
+
+        assert!(g.tick(g.deadline().unwrap()));
+        assert_eq!(g.phase, Phase::GameOver);
+        assert_eq!(g.deadline(), None);
+    }
+}
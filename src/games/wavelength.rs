@@ -0,0 +1,502 @@
+//! Wavelength: a psychic sees where a hidden target sits on a spectrum, e.g.
+//! between "Hot" and "Cold", and gives a clue. Their team turns a dial to
+//! where they think the target is, then the other team calls whether the
+//! target is left or right of the dial.
+
+use rand::{seq::SliceRandom, thread_rng, Rng};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::{
+    parse_move,
+    spectrum_list::{Spectrum, SpectrumList},
+    to_json, Game, InvalidMove, WavelengthSettings,
+};
+use crate::text;
+
+const MIN_PLAYERS: usize = 4;
+const MAX_CLUE_CHARS: usize = 48;
+/// Highest position on the dial, the left end is 0
+pub const DIAL_MAX: u8 = 100;
+/// Points for landing within each distance of the target, closest first
+const SCORE_BANDS: [(u8, u32); 3] = [(4, 4), (12, 3), (20, 2)];
+const BULLSEYE_POINTS: u32 = 4;
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Copy)]
+pub enum Phase {
+    GivingClue,
+    Guessing,
+    CallingSide,
+    Reveal,
+    GameOver,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Copy)]
+pub enum Side {
+    Left,
+    Right,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct TeamData {
+    players: Vec<String>,
+    score: u32,
+    /// Index of the player who is psychic next time it's this team's turn
+    next_psychic: usize,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct TurnScore {
+    team: u32,
+    opponents: u32,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Wavelength {
+    teams: [TeamData; 2],
+    /// Index of the team whose turn it is
+    turn: usize,
+    psychic: String,
+    card: Spectrum,
+    /// Where the target is, from 0 to `DIAL_MAX`. Only the psychic sees it
+    /// before the reveal.
+    target: Option<u8>,
+    clue: Option<String>,
+    dial: u8,
+    call: Option<Side>,
+    phase: Phase,
+    last_score: Option<TurnScore>,
+    winner: Option<usize>,
+
+    #[serde(skip)]
+    deck: Vec<Spectrum>,
+    #[serde(skip)]
+    settings: WavelengthSettings,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "actionType", content = "data")]
+#[serde(rename_all = "camelCase")]
+pub enum WavelengthMove {
+    Clue(String),
+    /// Turn the dial to a position from 0 to `DIAL_MAX`
+    SetDial(u8),
+    LockDial,
+    CallSide(Side),
+    NextTurn,
+}
+
+impl Wavelength {
+    /// Split `players` into two teams and deal the first card
+    pub fn new(
+        players: &[String],
+        cards: &SpectrumList,
+        settings: WavelengthSettings,
+    ) -> Result<Wavelength, String> {
+        if players.len() < MIN_PLAYERS {
+            return Err(format!("Wavelength needs at least {} players", MIN_PLAYERS));
+        }
+        if cards.cards.is_empty() {
+            return Err("There are no spectrum cards to play with".to_owned());
+        }
+
+        let mut players = players.to_vec();
+        players.shuffle(&mut thread_rng());
+        let team = |offset: usize| TeamData {
+            players: players.iter().skip(offset).step_by(2).cloned().collect(),
+            score: 0,
+            next_psychic: 0,
+        };
+
+        let deck = cards.shuffled().cards;
+        let mut game = Wavelength {
+            teams: [team(0), team(1)],
+            turn: 0,
+            psychic: String::new(),
+            card: deck[0].clone(),
+            target: None,
+            clue: None,
+            dial: DIAL_MAX / 2,
+            call: None,
+            phase: Phase::GivingClue,
+            last_score: None,
+            winner: None,
+            deck,
+            settings,
+        };
+        game.start_turn();
+
+        Ok(game)
+    }
+
+    fn start_turn(&mut self) {
+        let team = &mut self.teams[self.turn];
+        self.psychic = team.players[team.next_psychic % team.players.len()].clone();
+        team.next_psychic += 1;
+
+        // Cards go to the bottom of the deck once played
+        self.card = self.deck.remove(0);
+        self.deck.push(self.card.clone());
+
+        self.target = Some(thread_rng().gen_range(0..=DIAL_MAX));
+        self.clue = None;
+        self.dial = DIAL_MAX / 2;
+        self.call = None;
+        self.phase = Phase::GivingClue;
+    }
+
+    fn check_phase(&self, phase: Phase) -> Result<(), InvalidMove> {
+        if self.phase != phase {
+            return Err(InvalidMove::WrongState {
+                msg: format!("Can't do that during {:?}", self.phase),
+            });
+        }
+        Ok(())
+    }
+
+    fn team_of(&self, user: &str) -> Option<usize> {
+        self.teams
+            .iter()
+            .position(|t| t.players.iter().any(|p| p == user))
+    }
+
+    /// Players on the team whose turn it is, other than the psychic
+    fn check_guesser(&self, user: &str) -> Result<(), InvalidMove> {
+        if self.team_of(user) != Some(self.turn) || self.psychic == user {
+            return Err(InvalidMove::NotYourTurn {
+                msg: "Only the psychic's teammates can move the dial".to_owned(),
+            });
+        }
+        Ok(())
+    }
+
+    fn clue(&mut self, user: &str, clue: String) -> Result<(), InvalidMove> {
+        self.check_phase(Phase::GivingClue)?;
+        if self.psychic != user {
+            return Err(InvalidMove::NotYourTurn {
+                msg: "Only the psychic can give the clue".to_owned(),
+            });
+        }
+
+        let clue =
+            text::clean(&clue, MAX_CLUE_CHARS).map_err(|e| InvalidMove::from_text("Clue", e))?;
+        self.clue = Some(clue);
+        self.phase = Phase::Guessing;
+        Ok(())
+    }
+
+    fn set_dial(&mut self, user: &str, position: u8) -> Result<(), InvalidMove> {
+        self.check_phase(Phase::Guessing)?;
+        self.check_guesser(user)?;
+        if position > DIAL_MAX {
+            return Err(InvalidMove::NotAllowed {
+                msg: format!("The dial goes from 0 to {}", DIAL_MAX),
+            });
+        }
+
+        self.dial = position;
+        Ok(())
+    }
+
+    fn call_side(&mut self, user: &str, side: Side) -> Result<(), InvalidMove> {
+        self.check_phase(Phase::CallingSide)?;
+        if self.team_of(user) != Some(1 - self.turn) {
+            return Err(InvalidMove::NotYourTurn {
+                msg: "Only the other team can call left or right".to_owned(),
+            });
+        }
+
+        self.call = Some(side);
+        self.score();
+        Ok(())
+    }
+
+    /// The team scores by how close the dial is to the target. The other
+    /// team gets a point for calling the right side, unless it was a
+    /// bullseye.
+    fn score(&mut self) {
+        let target = self.target.expect("The target is set during a turn");
+        let team = points(self.dial, target);
+
+        let actual = match target.cmp(&self.dial) {
+            std::cmp::Ordering::Less => Some(Side::Left),
+            std::cmp::Ordering::Greater => Some(Side::Right),
+            std::cmp::Ordering::Equal => None,
+        };
+        let opponents = if team < BULLSEYE_POINTS && actual.is_some() && actual == self.call {
+            1
+        } else {
+            0
+        };
+
+        self.teams[self.turn].score += team;
+        self.teams[1 - self.turn].score += opponents;
+        self.last_score = Some(TurnScore { team, opponents });
+
+        let target_score = self.settings.target_score;
+        // The team playing wins ties, they reached the target on their turn
+        self.winner = [self.turn, 1 - self.turn]
+            .into_iter()
+            .find(|t| self.teams[*t].score >= target_score);
+        self.phase = if self.winner.is_some() {
+            Phase::GameOver
+        } else {
+            Phase::Reveal
+        };
+    }
+}
+
+/// Points for a dial position, by its distance from the target
+pub fn points(dial: u8, target: u8) -> u32 {
+    let distance = dial.abs_diff(target);
+    SCORE_BANDS
+        .iter()
+        .find(|(within, _)| distance <= *within)
+        .map(|(_, points)| *points)
+        .unwrap_or(0)
+}
+
+impl Game for Wavelength {
+    fn make_move(&mut self, user: &str, action: Value) -> Result<(), InvalidMove> {
+        match parse_move(action)? {
+            WavelengthMove::Clue(clue) => self.clue(user, clue),
+            WavelengthMove::SetDial(position) => self.set_dial(user, position),
+            WavelengthMove::LockDial => {
+                self.check_phase(Phase::Guessing)?;
+                self.check_guesser(user)?;
+                self.phase = Phase::CallingSide;
+                Ok(())
+            }
+            WavelengthMove::CallSide(side) => self.call_side(user, side),
+            WavelengthMove::NextTurn => {
+                self.check_phase(Phase::Reveal)?;
+                if self.team_of(user).is_none() {
+                    return Err(InvalidMove::InvalidUser {
+                        msg: format!("{} is not playing", user),
+                    });
+                }
+                self.turn = 1 - self.turn;
+                self.start_turn();
+                Ok(())
+            }
+        }
+    }
+
+    /// Only the psychic sees the target until it's revealed
+    fn filter(&self, user: &str) -> Value {
+        if matches!(self.phase, Phase::Reveal | Phase::GameOver) || self.psychic == user {
+            return to_json(self);
+        }
+
+        let mut view = self.clone();
+        view.target = None;
+        to_json(&view)
+    }
+
+    fn state(&self) -> Value {
+        to_json(self)
+    }
+
+    fn phase(&self) -> String {
+        format!("{:?}", self.phase)
+    }
+
+    fn is_over(&self) -> bool {
+        self.phase == Phase::GameOver
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn game() -> Wavelength {
+        let players: Vec<String> = ["alice", "bob", "carol", "dave"].map(String::from).to_vec();
+        let cards = SpectrumList {
+            description: "Test".to_owned(),
+            cards: vec![
+                Spectrum {
+                    left: "Hot".to_owned(),
+                    right: "Cold".to_owned(),
+                },
+                Spectrum {
+                    left: "Good".to_owned(),
+                    right: "Bad".to_owned(),
+                },
+            ],
+        };
+        Wavelength::new(&players, &cards, WavelengthSettings::default()).unwrap()
+    }
+
+    fn guesser(g: &Wavelength) -> String {
+        g.teams[g.turn]
+            .players
+            .iter()
+            .find(|p| **p != g.psychic)
+            .unwrap()
+            .clone()
+    }
+
+    fn opponent(g: &Wavelength) -> String {
+        g.teams[1 - g.turn].players[0].clone()
+    }
+
+    fn play(g: &mut Wavelength, user: &str, m: Value) -> Result<(), InvalidMove> {
+        g.make_move(user, m)
+    }
+
+    /// Play a turn up to the other team calling a side
+    fn turn(g: &mut Wavelength, target: u8, dial: u8, side: &str) {
+        g.target = Some(target);
+        let psychic = g.psychic.clone();
+        play(g, &psychic, json!({ "actionType": "clue", "data": "Soup" })).unwrap();
+        let user = guesser(g);
+        play(g, &user, json!({ "actionType": "setDial", "data": dial })).unwrap();
+        play(g, &user, json!({ "actionType": "lockDial" })).unwrap();
+        let user = opponent(g);
+        play(g, &user, json!({ "actionType": "callSide", "data": side })).unwrap();
+    }
+
+    #[test]
+    fn points_by_distance() {
+        assert_eq!(points(50, 50), 4);
+        assert_eq!(points(46, 50), 4);
+        assert_eq!(points(62, 50), 3);
+        assert_eq!(points(30, 50), 2);
+        assert_eq!(points(29, 50), 0);
+        assert_eq!(points(0, DIAL_MAX), 0);
+    }
+
+    #[test]
+    fn teams_are_split_evenly() {
+        let g = game();
+        assert_eq!(g.teams[0].players.len(), 2);
+        assert_eq!(g.teams[1].players.len(), 2);
+        assert_eq!(g.psychic, g.teams[0].players[0]);
+        assert!(g.target.unwrap() <= DIAL_MAX);
+    }
+
+    #[test]
+    fn needs_enough_players() {
+        let players = ["alice".to_owned(), "bob".to_owned(), "carol".to_owned()];
+        let cards = SpectrumList {
+            description: "Test".to_owned(),
+            cards: vec![],
+        };
+        assert!(Wavelength::new(&players, &cards, WavelengthSettings::default()).is_err());
+    }
+
+    #[test]
+    fn only_the_psychic_sees_the_target() {
+        let g = game();
+        let psychic = g.psychic.clone();
+        assert_eq!(g.filter(&psychic)["target"], json!(g.target));
+        assert!(g.filter(&guesser(&g))["target"].is_null());
+        assert!(g.filter(&opponent(&g))["target"].is_null());
+        assert!(g.filter("spectator")["target"].is_null());
+    }
+
+    #[test]
+    fn target_is_revealed_after_the_call() {
+        let mut g = game();
+        turn(&mut g, 30, 50, "Left");
+        assert_eq!(g.phase, Phase::Reveal);
+        assert_eq!(g.filter("spectator")["target"], json!(30));
+    }
+
+    #[test]
+    fn moves_are_checked() {
+        let mut g = game();
+        let psychic = g.psychic.clone();
+        let user = guesser(&g);
+        let other = opponent(&g);
+        let err = |r: Result<(), InvalidMove>| r.unwrap_err().kind();
+
+        let clue = json!({ "actionType": "clue", "data": "Soup" });
+        assert_eq!(err(play(&mut g, &user, clue.clone())), "not_your_turn");
+        let dial = json!({ "actionType": "setDial", "data": 10 });
+        assert_eq!(err(play(&mut g, &user, dial.clone())), "wrong_state");
+
+        play(&mut g, &psychic, clue).unwrap();
+        assert_eq!(err(play(&mut g, &psychic, dial.clone())), "not_your_turn");
+        assert_eq!(err(play(&mut g, &other, dial)), "not_your_turn");
+        for bad in [json!(101), json!(-1), json!(2.5), json!("left")] {
+            let res = play(
+                &mut g,
+                &user,
+                json!({ "actionType": "setDial", "data": bad }),
+            );
+            assert!(res.is_err());
+        }
+
+        play(&mut g, &user, json!({ "actionType": "lockDial" })).unwrap();
+        let call = json!({ "actionType": "callSide", "data": "Left" });
+        assert_eq!(err(play(&mut g, &user, call)), "not_your_turn");
+    }
+
+    #[test]
+    fn bullseye_scores_four_and_blocks_the_call() {
+        let mut g = game();
+        let team = g.turn;
+        turn(&mut g, 50, 52, "Left");
+
+        assert_eq!(g.teams[team].score, 4);
+        assert_eq!(g.teams[1 - team].score, 0);
+    }
+
+    #[test]
+    fn right_call_scores_a_point() {
+        let mut g = game();
+        let team = g.turn;
+        turn(&mut g, 40, 50, "Left");
+
+        assert_eq!(g.teams[team].score, 3);
+        assert_eq!(g.teams[1 - team].score, 1);
+
+        let mut g = game();
+        turn(&mut g, 40, 50, "Right");
+        assert_eq!(g.teams[1 - team].score, 0);
+    }
+
+    #[test]
+    fn turns_alternate_and_psychics_rotate() {
+        let mut g = game();
+        let first = g.psychic.clone();
+        turn(&mut g, 0, 100, "Left");
+        play(&mut g, "alice", json!({ "actionType": "nextTurn" })).unwrap();
+
+        assert_eq!(g.turn, 1);
+        assert_eq!(g.psychic, g.teams[1].players[0]);
+        assert_eq!(g.phase, Phase::GivingClue);
+        assert_eq!(g.dial, DIAL_MAX / 2);
+        assert_eq!(g.clue, None);
+
+        turn(&mut g, 0, 100, "Left");
+        play(&mut g, "alice", json!({ "actionType": "nextTurn" })).unwrap();
+        assert_eq!(g.turn, 0);
+        assert_ne!(g.psychic, first);
+        assert_eq!(g.psychic, g.teams[0].players[1]);
+    }
+
+    #[test]
+    fn first_team_to_the_target_score_wins() {
+        let mut g = game();
+        g.settings.target_score = 6;
+        let team = g.turn;
+
+        turn(&mut g, 50, 50, "Left");
+        play(&mut g, "alice", json!({ "actionType": "nextTurn" })).unwrap();
+        turn(&mut g, 0, 100, "Left");
+        play(&mut g, "alice", json!({ "actionType": "nextTurn" })).unwrap();
+        turn(&mut g, 50, 50, "Left");
+
+        assert_eq!(g.winner, Some(team));
+        assert_eq!(g.phase, Phase::GameOver);
+        let res = play(&mut g, "alice", json!({ "actionType": "nextTurn" }));
+        assert!(matches!(res, Err(InvalidMove::WrongState { .. })));
+    }
+}
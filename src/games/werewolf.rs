@@ -0,0 +1,787 @@
+//! Werewolf: a village hunts the werewolves hidden among it. At night the
+//! werewolves pick a victim while the seer and doctor act in secret, by day
+//! the survivors vote on who to lynch. The server runs every phase, there is
+//! no narrator.
+
+use rand::{seq::SliceRandom, thread_rng};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use super::{parse_move, secs_left, to_json, Game, InvalidMove, WerewolfSettings, MAX_TIMER_SECS};
+use crate::text;
+
+const MIN_PLAYERS: usize = 4;
+const MAX_CHAT_CHARS: usize = 200;
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Role {
+    Werewolf,
+    Seer,
+    Doctor,
+    Villager,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Copy)]
+pub enum Phase {
+    Night,
+    Day,
+    GameOver,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Copy)]
+pub enum Winner {
+    Werewolves,
+    Villagers,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Copy)]
+pub enum Cause {
+    Killed,
+    Lynched,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Elimination {
+    player: String,
+    role: Role,
+    cause: Cause,
+    day: usize,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Inspection {
+    seer: String,
+    day: usize,
+    target: String,
+    werewolf: bool,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ChatMessage {
+    from: String,
+    msg: String,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Werewolf {
+    players: Vec<String>,
+    /// Everyone's role. Players see their own, werewolves see each other.
+    roles: HashMap<String, Role>,
+    alive: Vec<String>,
+    eliminated: Vec<Elimination>,
+    phase: Phase,
+    day: usize,
+    #[serde(rename = "secsLeft", serialize_with = "secs_left")]
+    deadline: Option<Instant>,
+
+    /// Who each werewolf wants to kill tonight
+    wolf_votes: HashMap<String, String>,
+    /// Werewolves talking at night, only they can see it
+    wolf_chat: Vec<ChatMessage>,
+    /// Every seer's findings, each seer only sees their own
+    inspections: Vec<Inspection>,
+    /// Who each doctor is protecting tonight
+    protections: HashMap<String, String>,
+
+    /// Who each player wants to lynch today, public
+    lynch_votes: HashMap<String, String>,
+    winner: Option<Winner>,
+
+    #[serde(skip)]
+    settings: WerewolfSettings,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "actionType", content = "data")]
+#[serde(rename_all = "camelCase")]
+pub enum WerewolfMove {
+    /// A werewolf picks tonight's victim
+    Kill(String),
+    /// A seer learns whether a player is a werewolf
+    Inspect(String),
+    /// A doctor saves a player from tonight's attack
+    Protect(String),
+    /// A werewolf talks to the other werewolves
+    Chat(String),
+    /// Vote to lynch a player
+    Vote(String),
+}
+
+impl Werewolf {
+    /// Deal roles to `players` and start the first night
+    pub fn new(players: &[String], settings: WerewolfSettings) -> Result<Werewolf, String> {
+        if players.len() < MIN_PLAYERS {
+            return Err(format!("Werewolf needs at least {} players", MIN_PLAYERS));
+        }
+        let special = settings
+            .werewolves
+            .checked_add(settings.seers)
+            .and_then(|n| n.checked_add(settings.doctors))
+            .filter(|n| *n <= players.len());
+        let Some(special) = special else {
+            return Err(format!(
+                "There are more roles to deal than the {} players",
+                players.len()
+            ));
+        };
+        if settings.werewolves == 0 || settings.werewolves * 2 >= players.len() {
+            return Err(format!(
+                "{} players can't have {} werewolves, the village must outnumber them",
+                players.len(),
+                settings.werewolves
+            ));
+        }
+        if settings.night_secs.max(settings.day_secs) > MAX_TIMER_SECS {
+            return Err(format!(
+                "Nights and days can last at most {} seconds",
+                MAX_TIMER_SECS
+            ));
+        }
+
+        let mut deck: Vec<Role> = [
+            (Role::Werewolf, settings.werewolves),
+            (Role::Seer, settings.seers),
+            (Role::Doctor, settings.doctors),
+            (Role::Villager, players.len() - special),
+        ]
+        .into_iter()
+        .flat_map(|(role, n)| std::iter::repeat_n(role, n))
+        .collect();
+        deck.shuffle(&mut thread_rng());
+
+        let mut game = Werewolf {
+            players: players.to_vec(),
+            roles: players.iter().cloned().zip(deck).collect(),
+            alive: players.to_vec(),
+            eliminated: Vec::new(),
+            phase: Phase::Night,
+            day: 0,
+            deadline: None,
+            wolf_votes: HashMap::new(),
+            wolf_chat: Vec::new(),
+            inspections: Vec::new(),
+            protections: HashMap::new(),
+            lynch_votes: HashMap::new(),
+            winner: None,
+            settings,
+        };
+        game.start_night();
+
+        Ok(game)
+    }
+
+    fn is_alive(&self, user: &str) -> bool {
+        self.alive.iter().any(|p| p == user)
+    }
+
+    /// Role of a player who is still in the game
+    fn living_role(&self, user: &str) -> Option<Role> {
+        self.roles
+            .get(user)
+            .copied()
+            .filter(|_| self.is_alive(user))
+    }
+
+    fn living(&self, role: Role) -> impl Iterator<Item = &String> {
+        self.alive.iter().filter(move |p| self.roles[*p] == role)
+    }
+
+    fn check_phase(&self, phase: Phase) -> Result<(), InvalidMove> {
+        if self.phase != phase {
+            return Err(InvalidMove::WrongState {
+                msg: format!("Can't do that during the {:?}", self.phase),
+            });
+        }
+        Ok(())
+    }
+
+    fn check_role(&self, user: &str, role: Role) -> Result<(), InvalidMove> {
+        if self.living_role(user) != Some(role) {
+            return Err(InvalidMove::NotYourTurn {
+                msg: format!("Only a living {:?} can do that", role),
+            });
+        }
+        Ok(())
+    }
+
+    fn check_target(&self, target: &str) -> Result<(), InvalidMove> {
+        if !self.is_alive(target) {
+            return Err(InvalidMove::InvalidUser {
+                msg: format!("{} is not a living player", target),
+            });
+        }
+        Ok(())
+    }
+
+    fn start_night(&mut self) {
+        self.day += 1;
+        self.phase = Phase::Night;
+        self.deadline = Some(Instant::now() + Duration::from_secs(self.settings.night_secs));
+        self.wolf_votes.clear();
+        self.protections.clear();
+    }
+
+    fn start_day(&mut self) {
+        self.phase = Phase::Day;
+        self.deadline = Some(Instant::now() + Duration::from_secs(self.settings.day_secs));
+        self.lynch_votes.clear();
+    }
+
+    fn night_move(&mut self, user: &str, m: WerewolfMove) -> Result<(), InvalidMove> {
+        self.check_phase(Phase::Night)?;
+
+        match m {
+            WerewolfMove::Kill(target) => {
+                self.check_role(user, Role::Werewolf)?;
+                self.check_target(&target)?;
+                if self.roles[&target] == Role::Werewolf {
+                    return Err(InvalidMove::NotAllowed {
+                        msg: "Werewolves can't attack each other".to_owned(),
+                    });
+                }
+                self.wolf_votes.insert(user.to_owned(), target);
+            }
+            WerewolfMove::Inspect(target) => {
+                self.check_role(user, Role::Seer)?;
+                self.check_target(&target)?;
+                if target == user {
+                    return Err(InvalidMove::NotAllowed {
+                        msg: "You already know your own role".to_owned(),
+                    });
+                }
+                if self.has_inspected(user) {
+                    return Err(InvalidMove::NotAllowed {
+                        msg: "You can only inspect one player a night".to_owned(),
+                    });
+                }
+                self.inspections.push(Inspection {
+                    seer: user.to_owned(),
+                    day: self.day,
+                    werewolf: self.roles[&target] == Role::Werewolf,
+                    target,
+                });
+            }
+            WerewolfMove::Protect(target) => {
+                self.check_role(user, Role::Doctor)?;
+                self.check_target(&target)?;
+                self.protections.insert(user.to_owned(), target);
+            }
+            m => {
+                return Err(InvalidMove::WrongState {
+                    msg: format!("{:?} isn't a night move", m),
+                })
+            }
+        }
+
+        if self.night_done() {
+            self.end_night();
+        }
+
+        Ok(())
+    }
+
+    fn has_inspected(&self, seer: &str) -> bool {
+        self.inspections
+            .iter()
+            .any(|i| i.seer == seer && i.day == self.day)
+    }
+
+    /// Whether every living player with a night role has used it
+    fn night_done(&self) -> bool {
+        self.living(Role::Werewolf)
+            .all(|w| self.wolf_votes.contains_key(w))
+            && self.living(Role::Seer).all(|s| self.has_inspected(s))
+            && self
+                .living(Role::Doctor)
+                .all(|d| self.protections.contains_key(d))
+    }
+
+    /// The werewolves' victim is whoever most of them chose, nobody dies on
+    /// a tie or when a doctor protected the victim
+    fn end_night(&mut self) {
+        let victim =
+            top_choice(&self.wolf_votes).filter(|v| !self.protections.values().any(|p| p == v));
+
+        if let Some(victim) = victim {
+            self.eliminate(&victim, Cause::Killed);
+        }
+        if !self.check_winner() {
+            self.start_day();
+        }
+    }
+
+    fn chat(&mut self, user: &str, msg: String) -> Result<(), InvalidMove> {
+        self.check_phase(Phase::Night)?;
+        self.check_role(user, Role::Werewolf)?;
+        let msg =
+            text::clean(&msg, MAX_CHAT_CHARS).map_err(|e| InvalidMove::from_text("Message", e))?;
+
+        self.wolf_chat.push(ChatMessage {
+            from: user.to_owned(),
+            msg,
+        });
+        Ok(())
+    }
+
+    fn vote(&mut self, user: &str, target: String) -> Result<(), InvalidMove> {
+        self.check_phase(Phase::Day)?;
+        if !self.is_alive(user) {
+            return Err(InvalidMove::NotYourTurn {
+                msg: "Only living players can vote".to_owned(),
+            });
+        }
+        self.check_target(&target)?;
+
+        self.lynch_votes.insert(user.to_owned(), target);
+        if self.majority().is_some() {
+            self.end_day();
+        }
+
+        Ok(())
+    }
+
+    /// Player more than half the living players voted to lynch
+    fn majority(&self) -> Option<String> {
+        let mut counts: HashMap<&str, usize> = HashMap::new();
+        for target in self.lynch_votes.values() {
+            *counts.entry(target).or_default() += 1;
+        }
+        counts
+            .into_iter()
+            .find(|(_, n)| *n > self.alive.len() / 2)
+            .map(|(p, _)| p.to_owned())
+    }
+
+    fn end_day(&mut self) {
+        if let Some(lynched) = self.majority() {
+            self.eliminate(&lynched, Cause::Lynched);
+        }
+        if !self.check_winner() {
+            self.start_night();
+        }
+    }
+
+    fn eliminate(&mut self, player: &str, cause: Cause) {
+        self.alive.retain(|p| p != player);
+        self.eliminated.push(Elimination {
+            player: player.to_owned(),
+            role: self.roles[player],
+            cause,
+            day: self.day,
+        });
+    }
+
+    /// Villagers win once every werewolf is dead, werewolves once they match
+    /// the rest of the village. Returns whether the game is over.
+    fn check_winner(&mut self) -> bool {
+        let wolves = self.living(Role::Werewolf).count();
+        let winner = if wolves == 0 {
+            Winner::Villagers
+        } else if wolves * 2 >= self.alive.len() {
+            Winner::Werewolves
+        } else {
+            return false;
+        };
+
+        self.winner = Some(winner);
+        self.phase = Phase::GameOver;
+        self.deadline = None;
+        true
+    }
+}
+
+/// The option chosen most, if one was chosen more than any other
+fn top_choice(votes: &HashMap<String, String>) -> Option<String> {
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for target in votes.values() {
+        *counts.entry(target).or_default() += 1;
+    }
+    let max = *counts.values().max()?;
+    let mut top = counts.into_iter().filter(|(_, n)| *n == max);
+
+    match (top.next(), top.next()) {
+        (Some((t, _)), None) => Some(t.to_owned()),
+        _ => None,
+    }
+}
+
+impl Game for Werewolf {
+    fn make_move(&mut self, user: &str, action: Value) -> Result<(), InvalidMove> {
+        match parse_move(action)? {
+            WerewolfMove::Chat(msg) => self.chat(user, msg),
+            WerewolfMove::Vote(target) => self.vote(user, target),
+            m @ (WerewolfMove::Kill(_) | WerewolfMove::Inspect(_) | WerewolfMove::Protect(_)) => {
+                self.night_move(user, m)
+            }
+        }
+    }
+
+    /// Players see their own role and night actions, werewolves also see
+    /// each other and their chat. Eliminated players and spectators only see
+    /// what is public until the game is over.
+    fn filter(&self, user: &str) -> Value {
+        if self.phase == Phase::GameOver {
+            return to_json(self);
+        }
+
+        let mut view = self.clone();
+        let wolf = self.living_role(user) == Some(Role::Werewolf);
+
+        view.roles
+            .retain(|p, r| p == user || (wolf && *r == Role::Werewolf));
+        if !wolf {
+            view.wolf_votes.clear();
+            view.wolf_chat.clear();
+        }
+        view.inspections.retain(|i| i.seer == user);
+        view.protections.retain(|d, _| d == user);
+        if !self.is_alive(user) {
+            view.inspections.clear();
+            view.protections.clear();
+        }
+
+        to_json(&view)
+    }
+
+    fn state(&self) -> Value {
+        to_json(self)
+    }
+
+    fn phase(&self) -> String {
+        format!("{:?}", self.phase)
+    }
+
+    fn is_over(&self) -> bool {
+        self.phase == Phase::GameOver
+    }
+
+    fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    /// Players who didn't act in time forfeit their night action or vote
+    fn tick(&mut self, now: Instant) -> bool {
+        match self.deadline {
+            Some(d) if now >= d => {
+                match self.phase {
+                    Phase::Night => self.end_night(),
+                    Phase::Day => self.end_day(),
+                    Phase::GameOver => return false,
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// alice and bob are werewolves, carol the seer, dave the doctor and
+    /// erin and frank villagers
+    fn game() -> Werewolf {
+        let players: Vec<String> = ["alice", "bob", "carol", "dave", "erin", "frank"]
+            .map(String::from)
+            .to_vec();
+        let settings = WerewolfSettings {
+            werewolves: 2,
+            ..WerewolfSettings::default()
+        };
+        let mut g = Werewolf::new(&players, settings).unwrap();
+
+        let roles = [
+            Role::Werewolf,
+            Role::Werewolf,
+            Role::Seer,
+            Role::Doctor,
+            Role::Villager,
+            Role::Villager,
+        ];
+        g.roles = players.into_iter().zip(roles).collect();
+        g
+    }
+
+    fn play(g: &mut Werewolf, user: &str, action: &str, data: &str) -> Result<(), InvalidMove> {
+        g.make_move(user, json!({ "actionType": action, "data": data }))
+    }
+
+    fn night(g: &mut Werewolf, victim: &str, inspect: &str, protect: &str) {
+        play(g, "alice", "kill", victim).unwrap();
+        play(g, "bob", "kill", victim).unwrap();
+        play(g, "carol", "inspect", inspect).unwrap();
+        play(g, "dave", "protect", protect).unwrap();
+    }
+
+    fn lynch(g: &mut Werewolf, target: &str) {
+        let voters: Vec<String> = g.alive.iter().filter(|p| *p != target).cloned().collect();
+        for v in voters {
+            if g.phase != Phase::Day {
+                break;
+            }
+            play(g, &v, "vote", target).unwrap();
+        }
+    }
+
+    fn roles(v: &Value) -> Vec<String> {
+        let mut r: Vec<String> = v["roles"].as_object().unwrap().keys().cloned().collect();
+        r.sort();
+        r
+    }
+
+    #[test]
+    fn roles_are_dealt_from_the_settings() {
+        let players: Vec<String> = (0..7).map(|i| format!("p{}", i)).collect();
+        let settings = WerewolfSettings {
+            werewolves: 2,
+            seers: 1,
+            doctors: 0,
+            ..WerewolfSettings::default()
+        };
+        let g = Werewolf::new(&players, settings).unwrap();
+        let count = |role| g.roles.values().filter(|r| **r == role).count();
+
+        assert_eq!(count(Role::Werewolf), 2);
+        assert_eq!(count(Role::Seer), 1);
+        assert_eq!(count(Role::Doctor), 0);
+        assert_eq!(count(Role::Villager), 4);
+        assert_eq!(g.phase, Phase::Night);
+        assert_eq!(g.day, 1);
+    }
+
+    #[test]
+    fn impossible_role_sets_are_refused() {
+        let players: Vec<String> = (0..5).map(|i| format!("p{}", i)).collect();
+        let with = |werewolves, seers| WerewolfSettings {
+            werewolves,
+            seers,
+            ..WerewolfSettings::default()
+        };
+
+        assert!(Werewolf::new(&players[..3], with(1, 1)).is_err());
+        assert!(Werewolf::new(&players, with(0, 1)).is_err());
+        assert!(Werewolf::new(&players, with(3, 1)).is_err());
+        assert!(Werewolf::new(&players, with(2, 3)).is_err());
+        assert!(Werewolf::new(&players, with(usize::MAX / 2 + 1, 0)).is_err());
+        assert!(Werewolf::new(&players, with(1, usize::MAX)).is_err());
+        assert!(Werewolf::new(&players, with(2, 1)).is_ok());
+    }
+
+    #[test]
+    fn timers_are_bounded() {
+        let players: Vec<String> = (0..5).map(|i| format!("p{}", i)).collect();
+        let night = WerewolfSettings {
+            night_secs: u64::MAX,
+            ..WerewolfSettings::default()
+        };
+        let day = WerewolfSettings {
+            day_secs: MAX_TIMER_SECS + 1,
+            ..WerewolfSettings::default()
+        };
+
+        assert!(Werewolf::new(&players, night).is_err());
+        assert!(Werewolf::new(&players, day).is_err());
+    }
+
+    #[test]
+    fn players_only_see_their_own_role() {
+        let g = game();
+        assert_eq!(roles(&g.filter("carol")), ["carol"]);
+        assert_eq!(roles(&g.filter("erin")), ["erin"]);
+        assert_eq!(roles(&g.filter("spectator")), Vec::<String>::new());
+    }
+
+    #[test]
+    fn werewolves_know_each_other_and_chat_privately() {
+        let mut g = game();
+        play(&mut g, "alice", "chat", "let's get erin").unwrap();
+        play(&mut g, "alice", "kill", "erin").unwrap();
+
+        let wolf = g.filter("bob");
+        assert_eq!(roles(&wolf), ["alice", "bob"]);
+        assert_eq!(wolf["wolfChat"][0]["msg"], json!("let's get erin"));
+        assert_eq!(wolf["wolfVotes"], json!({ "alice": "erin" }));
+
+        for user in ["carol", "erin", "spectator"] {
+            let view = g.filter(user);
+            assert_eq!(view["wolfChat"], json!([]));
+            assert_eq!(view["wolfVotes"], json!({}));
+        }
+
+        let res = play(&mut g, "erin", "chat", "hello?");
+        assert!(matches!(res, Err(InvalidMove::NotYourTurn { .. })));
+    }
+
+    #[test]
+    fn night_actions_are_checked() {
+        let mut g = game();
+        let err = |r: Result<(), InvalidMove>| r.unwrap_err().kind();
+
+        assert_eq!(err(play(&mut g, "erin", "kill", "frank")), "not_your_turn");
+        assert_eq!(err(play(&mut g, "alice", "kill", "bob")), "not_allowed");
+        assert_eq!(err(play(&mut g, "alice", "kill", "zed")), "invalid_user");
+        assert_eq!(
+            err(play(&mut g, "carol", "inspect", "carol")),
+            "not_allowed"
+        );
+        assert_eq!(
+            err(play(&mut g, "dave", "inspect", "alice")),
+            "not_your_turn"
+        );
+        assert_eq!(err(play(&mut g, "erin", "vote", "alice")), "wrong_state");
+
+        play(&mut g, "carol", "inspect", "alice").unwrap();
+        assert_eq!(err(play(&mut g, "carol", "inspect", "bob")), "not_allowed");
+
+        // Other moves are refused rather than trusted to never arrive
+        let vote = WerewolfMove::Vote("alice".to_owned());
+        assert_eq!(err(g.night_move("erin", vote)), "wrong_state");
+    }
+
+    #[test]
+    fn seer_learns_privately() {
+        let mut g = game();
+        play(&mut g, "carol", "inspect", "bob").unwrap();
+
+        let seer = g.filter("carol");
+        assert_eq!(seer["inspections"][0]["target"], json!("bob"));
+        assert_eq!(seer["inspections"][0]["werewolf"], json!(true));
+        assert_eq!(g.filter("alice")["inspections"], json!([]));
+    }
+
+    #[test]
+    fn victim_dies_when_everyone_has_acted() {
+        let mut g = game();
+        night(&mut g, "erin", "alice", "frank");
+
+        assert_eq!(g.phase, Phase::Day);
+        assert!(!g.is_alive("erin"));
+        assert_eq!(g.eliminated[0].role, Role::Villager);
+        assert_eq!(g.eliminated[0].cause, Cause::Killed);
+        // Roles of the dead are public
+        assert_eq!(
+            g.filter("frank")["eliminated"][0]["role"],
+            json!("Villager")
+        );
+    }
+
+    #[test]
+    fn doctor_saves_the_victim() {
+        let mut g = game();
+        night(&mut g, "erin", "alice", "erin");
+        assert_eq!(g.phase, Phase::Day);
+        assert!(g.eliminated.is_empty());
+    }
+
+    #[test]
+    fn split_werewolves_kill_nobody() {
+        let mut g = game();
+        play(&mut g, "alice", "kill", "erin").unwrap();
+        play(&mut g, "bob", "kill", "frank").unwrap();
+        play(&mut g, "carol", "inspect", "alice").unwrap();
+        play(&mut g, "dave", "protect", "dave").unwrap();
+        assert!(g.eliminated.is_empty());
+    }
+
+    #[test]
+    fn night_ends_when_time_runs_out() {
+        let mut g = game();
+        play(&mut g, "alice", "kill", "erin").unwrap();
+        play(&mut g, "bob", "kill", "erin").unwrap();
+        let deadline = g.deadline().unwrap();
+
+        assert!(!g.tick(deadline - Duration::from_secs(1)));
+        assert!(g.tick(deadline));
+        assert_eq!(g.phase, Phase::Day);
+        assert!(!g.is_alive("erin"));
+    }
+
+    #[test]
+    fn majority_lynches_at_once() {
+        let mut g = game();
+        night(&mut g, "erin", "alice", "frank");
+
+        // Five alive, three votes is a majority
+        play(&mut g, "carol", "vote", "alice").unwrap();
+        play(&mut g, "dave", "vote", "alice").unwrap();
+        play(&mut g, "bob", "vote", "carol").unwrap();
+        assert_eq!(g.filter("spectator")["lynchVotes"]["bob"], json!("carol"));
+        assert_eq!(g.phase, Phase::Day);
+
+        play(&mut g, "frank", "vote", "alice").unwrap();
+        assert_eq!(g.phase, Phase::Night);
+        assert_eq!(g.day, 2);
+        assert_eq!(g.eliminated[1].player, "alice");
+        assert_eq!(g.eliminated[1].cause, Cause::Lynched);
+    }
+
+    #[test]
+    fn day_without_majority_lynches_nobody() {
+        let mut g = game();
+        night(&mut g, "erin", "alice", "frank");
+        play(&mut g, "carol", "vote", "alice").unwrap();
+
+        let deadline = g.deadline().unwrap();
+        assert!(g.tick(deadline));
+        assert_eq!(g.phase, Phase::Night);
+        assert_eq!(g.alive.len(), 5);
+    }
+
+    #[test]
+    fn eliminated_players_become_spectators() {
+        let mut g = game();
+        night(&mut g, "erin", "alice", "frank");
+        lynch(&mut g, "bob");
+        assert_eq!(g.phase, Phase::Night);
+
+        let view = g.filter("bob");
+        assert_eq!(view["wolfChat"], json!([]));
+        assert_eq!(roles(&view), ["bob"]);
+
+        let res = play(&mut g, "bob", "kill", "erin");
+        assert!(matches!(res, Err(InvalidMove::NotYourTurn { .. })));
+    }
+
+    #[test]
+    fn villagers_win_once_the_werewolves_are_dead() {
+        let mut g = game();
+        night(&mut g, "erin", "alice", "frank");
+        lynch(&mut g, "alice");
+        night_without(&mut g, "alice", "frank");
+        lynch(&mut g, "bob");
+
+        assert_eq!(g.winner, Some(Winner::Villagers));
+        assert_eq!(g.phase, Phase::GameOver);
+        assert_eq!(g.deadline(), None);
+        // Everything is revealed at the end
+        assert_eq!(g.filter("spectator")["roles"].as_object().unwrap().len(), 6);
+    }
+
+    #[test]
+    fn werewolves_win_when_they_match_the_village() {
+        let mut g = game();
+        night(&mut g, "erin", "alice", "frank");
+        lynch(&mut g, "frank");
+        // Two wolves against carol and dave
+        assert_eq!(g.winner, Some(Winner::Werewolves));
+    }
+
+    /// A night where the living werewolves kill `victim`
+    fn night_without(g: &mut Werewolf, dead_wolf: &str, victim: &str) {
+        for w in ["alice", "bob"].into_iter().filter(|w| *w != dead_wolf) {
+            play(g, w, "kill", victim).unwrap();
+        }
+        play(g, "carol", "inspect", "bob").unwrap();
+        play(g, "dave", "protect", victim).unwrap();
+    }
+}
@@ -0,0 +1,35 @@
+use rand::{seq::SliceRandom, thread_rng};
+use serde::Deserialize;
+use std::path::Path;
+
+use super::{load_json, Asset};
+
+/// A list of words loaded from one of the json files in `assets/`
+#[derive(Deserialize, Debug, Clone)]
+pub struct WordList {
+    pub description: String,
+    pub words: Vec<String>,
+}
+
+impl WordList {
+    pub async fn load(path: &Path) -> eyre::Result<WordList> {
+        load_json(path, "word list").await
+    }
+
+    /// A copy of the list in a random order
+    pub fn shuffled(&self) -> WordList {
+        let mut list = self.clone();
+        list.words.shuffle(&mut thread_rng());
+        list
+    }
+}
+
+impl Asset for WordList {
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn entries(&self) -> usize {
+        self.words.len()
+    }
+}
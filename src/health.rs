@@ -0,0 +1,73 @@
+use axum::{extract::Extension, http::StatusCode, response::IntoResponse, Json};
+use serde::Serialize;
+use std::{path::Path, sync::Arc};
+
+use crate::{config::Config, games::GameAssets, shutdown::Shutdown};
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Readiness {
+    ready: bool,
+    shutting_down: bool,
+    word_lists_loaded: bool,
+    storage_reachable: bool,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct BuildInfo {
+    name: &'static str,
+    version: &'static str,
+    git_commit: &'static str,
+}
+
+/// Liveness: the process is up and serving requests
+pub async fn healthz() -> &'static str {
+    "ok"
+}
+
+/// Readiness: the server can take new games. Fails once shutdown starts so
+/// load balancers stop sending traffic before sockets are closed.
+pub async fn readyz(
+    Extension(shutdown): Extension<Shutdown>,
+    Extension(assets): Extension<Arc<GameAssets>>,
+    Extension(config): Extension<Arc<Config>>,
+) -> impl IntoResponse {
+    let storage_reachable = match &config.shutdown.state_dir {
+        Some(dir) => storage_writable(dir).await,
+        None => true,
+    };
+
+    let shutting_down = shutdown.is_triggered();
+    let word_lists_loaded = assets.is_loaded();
+
+    let status = Readiness {
+        ready: !shutting_down && word_lists_loaded && storage_reachable,
+        shutting_down,
+        word_lists_loaded,
+        storage_reachable,
+    };
+
+    let code = if status.ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (code, Json(status))
+}
+
+pub async fn version() -> impl IntoResponse {
+    Json(BuildInfo {
+        name: env!("CARGO_PKG_NAME"),
+        version: env!("CARGO_PKG_VERSION"),
+        git_commit: env!("GIT_COMMIT"),
+    })
+}
+
+/// Whether game states could be saved to `dir`
+async fn storage_writable(dir: &Path) -> bool {
+    let probe = dir.join(".readyz");
+    tokio::fs::create_dir_all(dir).await.is_ok()
+        && tokio::fs::write(&probe, b"ok").await.is_ok()
+        && tokio::fs::remove_file(&probe).await.is_ok()
+}
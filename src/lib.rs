@@ -0,0 +1,107 @@
+//! Backend for the game site. Players join lobbies over websockets and play
+//! party games together, see [`router`] for serving it from an axum app.
+
+pub mod config;
+pub mod games;
+pub mod lobby_manager;
+pub mod logging;
+pub mod models;
+pub mod shutdown;
+
+mod admin;
+mod health;
+mod limits;
+mod lobby;
+mod metrics;
+mod outbound;
+mod socket;
+mod text;
+mod user_manager;
+
+use axum::{
+    extract::{ws::WebSocket, Extension, Path, WebSocketUpgrade},
+    http::{header::CONTENT_TYPE, StatusCode},
+    response::{Html, IntoResponse, Response},
+    routing::get,
+    Router, TypedHeader,
+};
+use config::Config;
+use lobby_manager::LobbyManager;
+use shutdown::Shutdown;
+use std::sync::Arc;
+use tower_http::trace::TraceLayer;
+use tracing::{debug, info_span, Instrument};
+
+/// Every route the game server serves: lobby websockets, health checks,
+/// metrics and the admin API. Triggering `shutdown` stops new sockets from
+/// connecting and fails readiness checks.
+pub fn router(
+    config: Arc<Config>,
+    lm: Arc<LobbyManager>,
+    shutdown: Shutdown,
+) -> eyre::Result<Router> {
+    let cors = config.server.cors()?;
+
+    Ok(Router::new()
+        .route("/", get(handler))
+        .route("/lobby/:id/ws", get(ws_handler))
+        .route("/metrics", get(metrics_handler))
+        .route("/healthz", get(health::healthz))
+        .route("/readyz", get(health::readyz))
+        .route("/version", get(health::version))
+        .nest("/admin", admin::routes())
+        .layer(Extension(lm.assets()))
+        .layer(Extension(lm))
+        .layer(Extension(shutdown))
+        .layer(Extension(config))
+        .layer(TraceLayer::new_for_http())
+        .layer(cors))
+}
+
+async fn handler() -> Html<&'static str> {
+    Html("<h1>Hello, World!</h1>")
+}
+
+async fn metrics_handler() -> impl IntoResponse {
+    (
+        [(CONTENT_TYPE, prometheus::TEXT_FORMAT)],
+        metrics::METRICS.render(),
+    )
+}
+
+async fn ws_handler(
+    Path(lobby_id): Path<String>,
+    Extension(lm): Extension<Arc<LobbyManager>>,
+    Extension(shutdown): Extension<Shutdown>,
+    Extension(config): Extension<Arc<Config>>,
+    ws: WebSocketUpgrade,
+    user_agent: Option<TypedHeader<headers::UserAgent>>,
+) -> Response {
+    if shutdown.is_triggered() {
+        return (StatusCode::SERVICE_UNAVAILABLE, "Server is shutting down").into_response();
+    }
+
+    let span = info_span!(
+        parent: None,
+        "socket",
+        lobby_id = %lobby_id,
+        user_id = tracing::field::Empty
+    );
+    if let Some(TypedHeader(user_agent)) = user_agent {
+        span.in_scope(|| debug!(user_agent = user_agent.as_str(), "Upgrading socket"));
+    }
+
+    let limits = config.limits.clone();
+    // Messages well past the limit are refused by the protocol layer before
+    // they are buffered, smaller ones get a clear error from the socket task
+    ws.max_message_size(
+        limits
+            .max_message_bytes
+            .max(limits.max_binary_bytes)
+            .saturating_mul(4),
+    )
+    .on_upgrade(move |socket: WebSocket| {
+        socket::handle_socket(socket, lm, lobby_id, shutdown, limits).instrument(span)
+    })
+    .into_response()
+}
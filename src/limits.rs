@@ -0,0 +1,177 @@
+use serde_json::Value;
+use std::time::Instant;
+
+use crate::{config::LimitsConfig, models::lobby::LobbyInMsg};
+
+/// A client message refused for going over one of the socket limits
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Violation {
+    RateLimited,
+    BinaryRateLimited,
+    MessageTooLarge,
+    BinaryTooLarge,
+    FieldTooLong,
+}
+
+impl Violation {
+    /// Short name of the violation, used as a metric label
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Violation::RateLimited => "rate",
+            Violation::BinaryRateLimited => "binary_rate",
+            Violation::MessageTooLarge => "message_size",
+            Violation::BinaryTooLarge => "binary_size",
+            Violation::FieldTooLong => "field_size",
+        }
+    }
+}
+
+/// Limits how much and how fast a single socket can send. Messages are
+/// paid for from a token bucket that refills at a steady rate, and clients
+/// that keep going over a limit are disconnected. Binary frames, like
+/// drawing strokes, come far more often so have a bucket of their own.
+pub struct Limiter {
+    config: LimitsConfig,
+    messages: Bucket,
+    binary: Bucket,
+    violations: u32,
+    window_start: Instant,
+}
+
+struct Bucket {
+    rate: f64,
+    burst: f64,
+    tokens: f64,
+    refilled: Instant,
+}
+
+impl Bucket {
+    fn new(rate: f64, burst: u32, now: Instant) -> Bucket {
+        Bucket {
+            rate,
+            burst: burst as f64,
+            tokens: burst as f64,
+            refilled: now,
+        }
+    }
+
+    /// Take a token if there is one
+    fn take(&mut self, now: Instant) -> bool {
+        let elapsed = now.saturating_duration_since(self.refilled).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.burst);
+        self.refilled = now;
+
+        if self.tokens < 1.0 {
+            return false;
+        }
+        self.tokens -= 1.0;
+        true
+    }
+}
+
+impl Limiter {
+    pub fn new(config: LimitsConfig) -> Limiter {
+        let now = Instant::now();
+
+        Limiter {
+            messages: Bucket::new(config.messages_per_sec, config.burst, now),
+            binary: Bucket::new(config.binary_per_sec, config.binary_burst, now),
+            config,
+            violations: 0,
+            window_start: now,
+        }
+    }
+
+    /// Account for a text frame of `len` bytes arriving at `now`
+    pub fn check_frame(&mut self, len: usize, now: Instant) -> Result<(), Violation> {
+        if !self.messages.take(now) {
+            return Err(Violation::RateLimited);
+        }
+        self.check_size(len)
+    }
+
+    /// Account for a binary frame of `len` bytes arriving at `now`
+    pub fn check_binary(&mut self, len: usize, now: Instant) -> Result<(), Violation> {
+        if !self.binary.take(now) {
+            return Err(Violation::BinaryRateLimited);
+        }
+        if len > self.config.max_binary_bytes {
+            return Err(Violation::BinaryTooLarge);
+        }
+        Ok(())
+    }
+
+    fn check_size(&self, len: usize) -> Result<(), Violation> {
+        if len > self.config.max_message_bytes {
+            return Err(Violation::MessageTooLarge);
+        }
+        Ok(())
+    }
+
+    /// Check no string in the message, like a user id or hint, is too long
+    pub fn check_fields(&self, msg: &LobbyInMsg) -> Result<(), Violation> {
+        let longest = match msg {
+            LobbyInMsg::Join { user_id } => user_id.chars().count(),
+            LobbyInMsg::GameMove(action) => longest_string(action),
+            _ => 0,
+        };
+
+        if longest > self.config.max_field_chars {
+            return Err(Violation::FieldTooLong);
+        }
+
+        Ok(())
+    }
+
+    /// Record a violation at `now`. Returns whether the client has gone over
+    /// the limits too often and should be disconnected.
+    pub fn record(&mut self, now: Instant) -> bool {
+        if now.saturating_duration_since(self.window_start) > self.config.violation_window {
+            self.window_start = now;
+            self.violations = 0;
+        }
+        self.violations += 1;
+
+        self.violations >= self.config.max_violations
+    }
+
+    /// Explanation sent to the client
+    pub fn describe(&self, violation: Violation) -> String {
+        match violation {
+            Violation::RateLimited => format!(
+                "Rate limit exceeded: at most {} messages per second, slow down",
+                self.config.messages_per_sec
+            ),
+            Violation::BinaryRateLimited => format!(
+                "Rate limit exceeded: at most {} binary frames per second, slow down",
+                self.config.binary_per_sec
+            ),
+            Violation::MessageTooLarge => format!(
+                "Message too large: at most {} bytes",
+                self.config.max_message_bytes
+            ),
+            Violation::BinaryTooLarge => format!(
+                "Binary frame too large: at most {} bytes",
+                self.config.max_binary_bytes
+            ),
+            Violation::FieldTooLong => format!(
+                "Field too long: at most {} characters",
+                self.config.max_field_chars
+            ),
+        }
+    }
+}
+
+/// Length in characters of the longest string, key or value, in `value`
+fn longest_string(value: &Value) -> usize {
+    match value {
+        Value::String(s) => s.chars().count(),
+        Value::Array(items) => items.iter().map(longest_string).max().unwrap_or(0),
+        Value::Object(fields) => fields
+            .iter()
+            .map(|(k, v)| k.chars().count().max(longest_string(v)))
+            .max()
+            .unwrap_or(0),
+        _ => 0,
+    }
+}
@@ -1,173 +1,528 @@
-use futures::future::join_all;
-use std::{collections::HashMap, sync::Arc};
-use tokio::sync::{mpsc::Receiver, Mutex};
+use serde_json::Value;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::{
+    select,
+    sync::{mpsc::Receiver, oneshot, watch},
+    time,
+};
+use tracing::{debug, info, trace, warn};
 
 use crate::{
-    games::{just_one::GameData, GameType},
-    lobby_manager::User,
-    models::lobby::{InMsg, LobbyInMsg, LobbyOutMsg},
+    config::Config,
+    games::{just_one::HintChecker, Game, GameAssets, GameContext, GameSettings, GameType},
+    lobby_manager::{User, Users},
+    logging::REDACTED,
+    metrics::METRICS,
+    models::{
+        admin::{LobbyDump, LobbySummary, MemberStatus},
+        lobby::{InMsg, LobbyInMsg, LobbyOutMsg, LobbyState},
+    },
+    outbound::Delivery,
 };
 
+/// Out of band commands sent to a lobby by the server rather than a user
+#[derive(Debug)]
+pub enum LobbyCtrl {
+    /// Tell members the lobby is about to be closed
+    Warn {
+        closes_in: Duration,
+    },
+    /// Report a summary of the lobby for the admin listing
+    Inspect(oneshot::Sender<LobbySummary>),
+    /// Report the full, unfiltered lobby and game state
+    Dump(oneshot::Sender<LobbyDump>),
+    /// A user was removed by an admin, tell the remaining members
+    Kicked {
+        user_id: String,
+    },
+    Announce {
+        msg: String,
+    },
+}
+
+/// Sent on a lobby's close signal, which stays open until it holds one of
+/// these
+#[derive(Debug, Clone, Copy)]
+pub enum Close {
+    Now,
+    /// The server is going away, save the game and close
+    Shutdown {
+        reconnect_after: Duration,
+    },
+}
+
 pub struct Lobby {
     id: String,
-    users: Arc<Mutex<HashMap<String, User>>>,
+    config: Arc<Config>,
+    assets: Arc<GameAssets>,
+    users: Users,
     rx: Receiver<InMsg>,
+    ctrl: Receiver<LobbyCtrl>,
+    /// Set, or dropped, by the lobby manager once the lobby is to close. Kept
+    /// out of `ctrl` so a full channel can't lose it.
+    close: watch::Receiver<Option<Close>>,
+    closed: bool,
+    /// Whether the running game should be written to disk once the lobby closes
+    save_game: bool,
+    last_active: Arc<Mutex<Instant>>,
     game: GameType,
+    settings: GameSettings,
+    hint_checker: Arc<HintChecker>,
 }
 
 impl Lobby {
-    pub fn new(id: String, users: Arc<Mutex<HashMap<String, User>>>, rx: Receiver<InMsg>) -> Lobby {
-        return Lobby {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        id: String,
+        config: Arc<Config>,
+        assets: Arc<GameAssets>,
+        users: Users,
+        rx: Receiver<InMsg>,
+        ctrl: Receiver<LobbyCtrl>,
+        close: watch::Receiver<Option<Close>>,
+        last_active: Arc<Mutex<Instant>>,
+    ) -> Lobby {
+        let hint_checker = Arc::new(HintChecker::from_config(&config.games.just_one));
+        let settings = GameSettings::from_config(&config.games);
+
+        Lobby {
             id,
+            config,
+            assets,
             users,
             rx,
+            ctrl,
+            close,
+            closed: false,
+            save_game: false,
+            last_active,
             game: GameType::JustOne,
-        };
+            settings,
+            hint_checker,
+        }
     }
     pub async fn lobby_loop(&mut self) {
-        while let Some(msg) = self.rx.recv().await {
+        while let Some(msg) = self.recv(None).await {
             use LobbyInMsg::*;
             use LobbyOutMsg::*;
             let req_uid = msg.uid;
 
             match msg.cmd {
                 Join { user_id } => {
-                    println!("User {} joined lobby {}", &user_id, &self.id);
-                    let members = self.get_members().await;
+                    info!(%user_id, "User joined");
+                    let members = self.get_members();
 
-                    self.broadcast(|_| Members(members.clone())).await;
-                    self.send(user_id, SelectedGame(self.game)).await;
+                    self.broadcast(|_| Members(members.clone()));
+                    self.send(user_id.clone(), SelectedGame(self.game));
+                    self.send(user_id, Settings(self.settings.clone()));
                 }
-                Leave => println!("User {} left lobby {}", &req_uid, &self.id),
+                Leave => info!(user_id = %req_uid, "User left"),
                 Start => {
-                    println!("Start Game");
-                    let users: Vec<String> = self.get_members().await;
-                    self.game_loop(GameData::new(&users).await).await;
+                    let users: Vec<String> = self.get_members();
+                    let ctx = GameContext {
+                        assets: &self.assets,
+                        settings: &self.settings,
+                        hint_checker: self.hint_checker.clone(),
+                        host: &req_uid,
+                    };
+                    let game = match self.game.start(&users, ctx) {
+                        Ok(game) => game,
+                        Err(msg) => {
+                            debug!(user_id = %req_uid, game = ?self.game, %msg, "Game could not start");
+                            self.send(req_uid, Error { msg });
+                            continue;
+                        }
+                    };
+
+                    info!(user_id = %req_uid, game = ?self.game, "Starting game");
+                    METRICS
+                        .games_started
+                        .with_label_values(&[self.game.name()])
+                        .inc();
+                    self.game_loop(game).await;
                 }
                 GetUsers => {
-                    println!("Get Users {}", req_uid);
-                    self.send(req_uid, Members(self.get_members().await)).await;
+                    debug!(user_id = %req_uid, "Get users");
+                    self.send(req_uid, Members(self.get_members()));
                 }
                 GetGameType => {
-                    println!("Get Game Type {}", req_uid);
-                    self.send(req_uid, SelectedGame(self.game)).await;
+                    debug!(user_id = %req_uid, "Get game type");
+                    self.send(req_uid, SelectedGame(self.game));
+                }
+                GameMove(_) => self.send(
+                    req_uid,
+                    Error {
+                        msg: "Invalid Msg. Cannot make move during the lobby".to_string(),
+                    },
+                ),
+                SetSettings(settings) => {
+                    info!(user_id = %req_uid, ?settings, "Settings changed");
+                    self.settings = settings;
+                    self.broadcast(|_| Settings(self.settings.clone()));
                 }
-                GameMove(_) => {
-                    self.send(
-                        req_uid,
-                        Error {
-                            msg: "Invalid Msg. Cannot make move during the lobby".to_string(),
-                        },
-                    )
-                    .await
+                SelectGame(game) => {
+                    info!(user_id = %req_uid, ?game, "Game selected");
+                    self.game = game;
+                    self.broadcast(|_| SelectedGame(self.game));
                 }
+                GetCanvas => debug!(user_id = %req_uid, "No canvas outside a game"),
+                LobbyInMsg::Binary(_) => self.send(
+                    req_uid,
+                    Error {
+                        msg: "Invalid Msg. Cannot send binary data during the lobby".to_string(),
+                    },
+                ),
             }
         }
-        println!("Lobby {} closed", self.id);
+        for u in self.users.lock().expect("Users poisoned").values_mut() {
+            u.is_conn = false;
+            u.out.close();
+        }
+        info!("Lobby closed");
     }
 
-    async fn game_loop(&mut self, mut game: GameData<'_>) {
-        self.broadcast_state(&game).await;
-        while let Some(msg) = self.rx.recv().await {
+    /// Wait for the next user message. Returns `None` once the lobby has been
+    /// told to close, after which it never yields another message. `game` is
+    /// the game being played, if any, for answering admin requests.
+    async fn recv(&mut self, game: Option<&dyn Game>) -> Option<InMsg> {
+        while !self.closed {
+            select! {
+                msg = self.rx.recv() => {
+                    *self.last_active.lock().expect("Activity poisoned") = Instant::now();
+                    return msg;
+                },
+                _ = self.close.changed() => {
+                    let close = *self.close.borrow();
+                    if let Some(Close::Shutdown { reconnect_after }) = close {
+                        self.broadcast(|_| LobbyOutMsg::ServerShuttingDown {
+                            reconnect_after_secs: reconnect_after.as_secs(),
+                        });
+                        self.save_game = self.config.shutdown.state_dir.is_some();
+                    }
+                    self.closed = true;
+                }
+                ctrl = self.ctrl.recv() => match ctrl {
+                    Some(LobbyCtrl::Warn { closes_in }) => {
+                        info!(?closes_in, "Warning members the lobby is closing");
+                        self.broadcast(|_| LobbyOutMsg::LobbyClosing {
+                            closes_in_secs: closes_in.as_secs(),
+                        });
+                    }
+                    None => self.closed = true,
+                    Some(LobbyCtrl::Inspect(reply)) => {
+                        let _ = reply.send(self.summary(game));
+                    }
+                    Some(LobbyCtrl::Dump(reply)) => {
+                        let _ = reply.send(self.dump(game));
+                    }
+                    Some(LobbyCtrl::Kicked { user_id }) => {
+                        info!(%user_id, "User kicked");
+                        let members = self.get_members();
+                        self.broadcast(|_| LobbyOutMsg::Members(members.clone()));
+                    }
+                    Some(LobbyCtrl::Announce { msg }) => {
+                        info!(%msg, "Announcement");
+                        self.broadcast(|_| LobbyOutMsg::Announcement { msg: msg.clone() });
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    /// Play `game` until it's over or the lobby closes. The final state has
+    /// already been sent to everyone when a finished game returns.
+    async fn game_loop(&mut self, mut game: Box<dyn Game + '_>) {
+        self.broadcast_state(&*game);
+        loop {
+            let deadline = game.deadline();
+            let timer = time::sleep_until(deadline.unwrap_or_else(Instant::now).into());
+            let msg = select! {
+                msg = self.recv(Some(&*game)) => msg,
+                _ = timer, if deadline.is_some() => {
+                    if game.tick(Instant::now()) {
+                        debug!(phase = %game.phase(), "Game timer ran out");
+                        self.broadcast_state(&*game);
+                    }
+                    if game.is_over() {
+                        break;
+                    }
+                    continue;
+                }
+            };
+            let Some(msg) = msg else {
+                break;
+            };
             use LobbyInMsg::*;
             use LobbyOutMsg::*;
 
             let req_uid = msg.uid;
             match msg.cmd {
                 Join { user_id } => {
-                    println!("User {} joined lobby {}", &user_id, &self.id);
-                    let members = self.get_members().await;
-                    self.broadcast(|_| Members(members.clone())).await;
-                    self.broadcast_state(&game).await;
-                }
-                Leave => println!("User {} left lobby {}", &req_uid, &self.id),
-                Start => {
-                    self.send(
-                        req_uid,
-                        Error {
-                            msg: "Invalid Msg. Cannot start a game during an existing game"
-                                .to_string(),
-                        },
-                    )
-                    .await
+                    info!(%user_id, "User joined");
+                    let members = self.get_members();
+                    self.broadcast(|_| Members(members.clone()));
+                    self.broadcast_state(&*game);
+                    self.send_canvas(user_id, &*game);
                 }
+                Leave => info!(user_id = %req_uid, "User left"),
+                Start => self.send(
+                    req_uid,
+                    Error {
+                        msg: "Invalid Msg. Cannot start a game during an existing game".to_string(),
+                    },
+                ),
                 GetUsers => {
-                    println!("Get Users {}", req_uid);
-                    self.send(req_uid, Members(self.get_members().await)).await;
+                    debug!(user_id = %req_uid, "Get users");
+                    self.send(req_uid, Members(self.get_members()));
                 }
                 GetGameType => {
-                    println!("Get Game Type {}", req_uid);
-                    self.send(req_uid, SelectedGame(self.game)).await;
+                    debug!(user_id = %req_uid, "Get game type");
+                    self.send(req_uid, SelectedGame(self.game));
                 }
-                GameMove(action) => match game.make_move(&req_uid, action) {
-                    Ok(state) => {
-                        self.broadcast_state(&state).await;
-                    }
+                SetSettings(_) => self.send(
+                    req_uid,
+                    Error {
+                        msg: "Invalid Msg. Cannot change settings during a game".to_string(),
+                    },
+                ),
+                SelectGame(_) => self.send(
+                    req_uid,
+                    Error {
+                        msg: "Invalid Msg. Cannot change game during a game".to_string(),
+                    },
+                ),
+                GetCanvas => self.send_canvas(req_uid, &*game),
+                LobbyInMsg::Binary(data) => match game.binary(&req_uid, &data) {
+                    Ok(Some(frame)) => self.relay(&req_uid, frame),
+                    Ok(None) => self.broadcast_state(&*game),
                     Err(e) => {
+                        trace!(user_id = %req_uid, error = ?e, "Binary frame refused");
+                        METRICS.rejected_moves.with_label_values(&[e.kind()]).inc();
                         self.send(
                             req_uid,
                             Error {
                                 msg: format!("Invalid Move: {:?}", e),
                             },
-                        )
-                        .await
+                        );
                     }
                 },
+                GameMove(action) => {
+                    debug!(user_id = %req_uid, action = %self.redact(&action), "Game move");
+                    METRICS.moves.inc();
+                    match game.make_move(&req_uid, action) {
+                        Ok(()) => self.broadcast_state(&*game),
+                        Err(e) => {
+                            debug!(user_id = %req_uid, error = ?e, "Invalid move");
+                            METRICS.rejected_moves.with_label_values(&[e.kind()]).inc();
+                            self.send(
+                                req_uid,
+                                Error {
+                                    msg: format!("Invalid Move: {:?}", e),
+                                },
+                            );
+                        }
+                    }
+                }
+            }
+            if game.is_over() {
+                break;
             }
         }
+
+        if game.is_over() {
+            info!(game = ?self.game, "Game over");
+            METRICS
+                .games_finished
+                .with_label_values(&[self.game.name()])
+                .inc();
+            return;
+        }
+
+        METRICS
+            .games_abandoned
+            .with_label_values(&[self.game.name()])
+            .inc();
+        if self.save_game {
+            self.save(&*game).await;
+        }
     }
 
-    async fn broadcast_state(&self, state: &GameData<'_>) {
-        self.broadcast(|u| match serde_json::to_value(&state.filter(u)) {
-            Ok(s) => {
-                println!("Sending State {}", s);
-                LobbyOutMsg::GameState(s)
-            }
-            Err(e) => LobbyOutMsg::Error { msg: e.to_string() },
-        })
-        .await;
+    /// Write the unfiltered game state to the configured state directory
+    async fn save(&self, game: &dyn Game) {
+        let Some(dir) = &self.config.shutdown.state_dir else {
+            return;
+        };
+
+        // Lobby ids come from the url so keep them from escaping the directory
+        let name: String = self
+            .id
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || c == '-' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        let path = dir.join(format!("{}.json", name));
+
+        let res = match serde_json::to_vec(&game.state()) {
+            Ok(state) => match tokio::fs::create_dir_all(dir).await {
+                Ok(_) => tokio::fs::write(&path, state).await,
+                Err(e) => Err(e),
+            },
+            Err(e) => Err(e.into()),
+        };
+
+        match res {
+            Ok(_) => info!(path = %path.display(), "Saved game"),
+            Err(e) => warn!(error = %e, "Unable to save game"),
+        }
     }
 
-    async fn get_members(&self) -> Vec<String> {
-        self.users
+    fn broadcast_state(&self, game: &dyn Game) {
+        let mut views: HashMap<String, Value> = HashMap::new();
+        self.broadcast(|u| {
+            let s = match game.audience(u) {
+                Some(audience) => views
+                    .entry(audience)
+                    .or_insert_with(|| game.filter(u))
+                    .clone(),
+                None => game.filter(u),
+            };
+            trace!(user_id = u, state = %self.redact(&s), "Sending state");
+            LobbyOutMsg::GameState(s)
+        });
+    }
+
+    /// Pass a binary frame from `from` straight on to everyone else, without
+    /// building a game state for each user. Everyone is sent the same frame.
+    fn relay(&self, from: &str, data: Arc<Vec<u8>>) {
+        let _timer = METRICS.broadcast_seconds.start_timer();
+        let mut users = self.users.lock().expect("Users poisoned");
+
+        for (u_id, u) in users.iter_mut().filter(|(id, u)| u.is_conn && *id != from) {
+            self.deliver(u_id, u, LobbyOutMsg::Binary(data.clone()));
+        }
+    }
+
+    /// Send `user` every binary frame they can see packed into one frame,
+    /// so they can redraw the canvas
+    fn send_canvas(&self, user: String, game: &dyn Game) {
+        let history = game.binary_history(&user);
+        if history.is_empty() {
+            return;
+        }
+
+        let mut packed = Vec::with_capacity(history.iter().map(|f| f.len() + 4).sum());
+        for frame in &history {
+            packed.extend_from_slice(&(frame.len() as u32).to_be_bytes());
+            packed.extend_from_slice(frame);
+        }
+
+        self.send(
+            user.clone(),
+            LobbyOutMsg::Canvas {
+                strokes: history.len(),
+            },
+        );
+        self.send(user, LobbyOutMsg::Binary(Arc::new(packed)));
+    }
+
+    fn summary(&self, game: Option<&dyn Game>) -> LobbySummary {
+        let users = self.users.lock().expect("Users poisoned");
+
+        LobbySummary {
+            id: self.id.clone(),
+            members: users.len(),
+            connected: users.values().filter(|u| u.is_conn).count(),
+            game_type: self.game,
+            state: lobby_state(game),
+            phase: game.map(|g| g.phase()),
+        }
+    }
+
+    fn dump(&self, game: Option<&dyn Game>) -> LobbyDump {
+        let members = self
+            .users
             .lock()
-            .await
+            .expect("Users poisoned")
             .iter()
-            .map(|(id, _)| id.clone())
+            .map(|(id, u)| MemberStatus {
+                user_id: id.clone(),
+                connected: u.is_conn,
+            })
+            .collect();
+
+        LobbyDump {
+            id: self.id.clone(),
+            game_type: self.game,
+            state: lobby_state(game),
+            members,
+            settings: self.settings.clone(),
+            game: game.map(|g| g.state()),
+        }
+    }
+
+    fn get_members(&self) -> Vec<String> {
+        self.users
+            .lock()
+            .expect("Users poisoned")
+            .keys()
+            .cloned()
             .collect()
     }
 
-    async fn broadcast(&self, f: impl Fn(&str) -> LobbyOutMsg) {
-        let users = self.users.lock().await;
+    fn broadcast(&self, mut f: impl FnMut(&str) -> LobbyOutMsg) {
+        let _timer = METRICS.broadcast_seconds.start_timer();
+        let mut users = self.users.lock().expect("Users poisoned");
 
-        let sends = users.iter().map(|(u_id, u)| async {
-            u.out
-                .send(f(u_id))
-                .await
-                .map_err(|e| format!("Unable to send {}", e))
-        });
-        let errors: Vec<String> = join_all(sends)
-            .await
-            .into_iter()
-            .filter(|r| r.is_err())
-            .map(|r| r.expect_err("Expected list to only contain errors"))
-            .collect();
-        if errors.len() > 0 {
-            println!("Error in broadcast: {}", errors.concat());
+        for (u_id, u) in users.iter_mut().filter(|(_, u)| u.is_conn) {
+            self.deliver(u_id, u, f(u_id));
         }
     }
 
-    async fn send(&self, user: String, msg: LobbyOutMsg) {
-        let um = self.users.lock().await;
+    fn send(&self, user: String, msg: LobbyOutMsg) {
+        let mut um = self.users.lock().expect("Users poisoned");
 
-        if !um.contains_key(&user) {
-            println!("Tried to send message to {} who was not found", &user);
+        match um.get_mut(&user) {
+            Some(u) => self.deliver(&user, u, msg),
+            None => warn!(user_id = %user, "Tried to send message to user who was not found"),
         }
+    }
 
-        let u = um.get(&user).unwrap();
+    fn deliver(&self, u_id: &str, u: &mut User, msg: LobbyOutMsg) {
+        match u.out.push(msg) {
+            Delivery::Sent | Delivery::Coalesced => {}
+            Delivery::Dropped => debug!(user_id = u_id, "User is lagging, dropped message"),
+            Delivery::Disconnected => {
+                if u.is_conn {
+                    warn!(user_id = u_id, "Disconnecting slow user");
+                }
+                u.is_conn = false;
+            }
+        }
+    }
 
-        if let Err(e) = u.out.send(msg).await {
-            println!("Unable to send {}", e);
+    /// Game content is only logged when secret redaction is turned off
+    fn redact(&self, content: &serde_json::Value) -> String {
+        if self.config.log.redact_secrets {
+            REDACTED.to_owned()
+        } else {
+            content.to_string()
         }
     }
 }
+
+fn lobby_state(game: Option<&dyn Game>) -> LobbyState {
+    match game {
+        Some(_) => LobbyState::InGame,
+        None => LobbyState::InLobby,
+    }
+}
@@ -1,125 +1,538 @@
-use std::{collections::HashMap, sync::Arc};
-use tokio::sync::{
-    mpsc::{self, Receiver, Sender},
-    Mutex,
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, Weak,
+    },
+    time::{Duration, Instant},
+};
+use tokio::{
+    sync::{
+        broadcast,
+        mpsc::{self, Sender},
+        oneshot, watch,
+    },
+    time::{interval, timeout},
 };
+use tracing::{info, info_span, warn, Instrument};
 
 use crate::{
-    lobby::Lobby,
+    config::{Config, ReaperConfig},
+    games::GameAssets,
+    lobby::{Close, Lobby, LobbyCtrl},
+    metrics::METRICS,
     models::{
+        admin::{LobbyDump, LobbySummary},
         lobby::{InMsg, LobbyOutMsg},
-        user,
     },
+    outbound::{self, Inbound, Outbound},
 };
 
+static NEXT_SESSION: AtomicU64 = AtomicU64::new(1);
+
+/// How long an admin request waits for a lobby to answer
+const LOBBY_REPLY_TIMEOUT: Duration = Duration::from_secs(2);
+
 #[derive(Debug)]
 pub enum LobbyError {
     LobbyDoesNotExist,
     UserAlreadyConnected,
     UserDoesNotExist,
+    /// The lobby didn't answer in time, usually because it is closing
+    LobbyUnresponsive,
+}
+
+#[derive(Debug, Clone)]
+pub enum LobbyEvent {
+    Created {
+        lobby_id: String,
+    },
+    /// The last connected user left, the lobby will close after the grace period
+    Emptied {
+        lobby_id: String,
+    },
+    /// A user came back to an empty lobby before it was closed
+    Resumed {
+        lobby_id: String,
+    },
+    Closed {
+        lobby_id: String,
+    },
+}
+
+impl LobbyEvent {
+    pub fn lobby_id(&self) -> &str {
+        match self {
+            LobbyEvent::Created { lobby_id }
+            | LobbyEvent::Emptied { lobby_id }
+            | LobbyEvent::Resumed { lobby_id }
+            | LobbyEvent::Closed { lobby_id } => lobby_id,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            LobbyEvent::Created { .. } => "created",
+            LobbyEvent::Emptied { .. } => "emptied",
+            LobbyEvent::Resumed { .. } => "resumed",
+            LobbyEvent::Closed { .. } => "closed",
+        }
+    }
 }
 
+pub(crate) type Users = Arc<Mutex<HashMap<String, User>>>;
+
+/// Tracks every running lobby. Cheap to share behind an `Arc`: the map is
+/// only locked for short, non-async critical sections.
 pub struct LobbyManager {
-    lobbies: HashMap<String, LobbyData>,
+    lobbies: Arc<Mutex<HashMap<String, LobbyData>>>,
+    events: broadcast::Sender<LobbyEvent>,
+    config: Arc<Config>,
+    assets: Arc<GameAssets>,
 }
 
 impl LobbyManager {
-    pub fn new() -> LobbyManager {
+    pub fn new(config: Arc<Config>, assets: Arc<GameAssets>) -> LobbyManager {
+        let (events, _) = broadcast::channel(100);
+
         LobbyManager {
-            lobbies: HashMap::new(),
+            lobbies: Arc::new(Mutex::new(HashMap::new())),
+            events,
+            config,
+            assets,
         }
     }
 
-    pub fn create_lobby(&mut self, id: String) {
-        self.lobbies
-            .entry(id.clone())
-            .or_insert_with(|| LobbyData::new(id));
+    /// Word lists and other content shared by every lobby
+    pub fn assets(&self) -> Arc<GameAssets> {
+        self.assets.clone()
     }
 
-    pub async fn add_user(
-        &mut self,
-        l_id: &String,
-        u_id: &String,
-    ) -> Result<(Sender<InMsg>, Receiver<LobbyOutMsg>), LobbyError> {
-        let lobby = match self.lobbies.get(l_id) {
-            Some(l) => l,
-            None => return Err(LobbyError::LobbyDoesNotExist),
-        };
+    pub fn subscribe(&self) -> broadcast::Receiver<LobbyEvent> {
+        self.events.subscribe()
+    }
 
-        let mut users = lobby.users.lock().await;
+    /// Add a user to a lobby, creating the lobby if it doesn't exist yet.
+    /// Returns the lobby's message sender, the user's outbound messages and
+    /// the session id that must be used to disconnect this connection.
+    pub(crate) fn join(
+        &self,
+        l_id: &str,
+        u_id: &str,
+    ) -> Result<(Sender<InMsg>, Inbound, u64), LobbyError> {
+        let mut lobbies = self.lobbies.lock().expect("Lobby map poisoned");
 
-        if users.contains_key(u_id) {
-            if users.get(u_id).unwrap().is_conn {
-                return Err(LobbyError::UserAlreadyConnected);
-            }
+        let lobby = lobbies.entry(l_id.to_owned()).or_insert_with(|| {
+            emit(
+                &self.events,
+                LobbyEvent::Created {
+                    lobby_id: l_id.to_owned(),
+                },
+            );
+            LobbyData::new(l_id.to_owned(), self.config.clone(), self.assets.clone())
+        });
+
+        let mut users = lobby.users.lock().expect("Users poisoned");
+
+        if users.get(u_id).map(|u| u.is_conn).unwrap_or(false) {
+            return Err(LobbyError::UserAlreadyConnected);
         }
 
-        let (tx, rx) = mpsc::channel(100);
+        let session = NEXT_SESSION.fetch_add(1, Ordering::Relaxed);
+        let (tx, rx) = outbound::channel(
+            self.config.lobby.user_channel_capacity,
+            self.config.lobby.max_lag_strikes,
+        );
         users.insert(
-            u_id.clone(),
+            u_id.to_owned(),
             User {
                 out: tx,
                 is_conn: true,
+                session,
             },
         );
-        Ok((lobby.msg_sender.clone(), rx))
+        lobby.epoch += 1;
+        lobby.touch();
+
+        if std::mem::replace(&mut lobby.empty, false) {
+            emit(
+                &self.events,
+                LobbyEvent::Resumed {
+                    lobby_id: l_id.to_owned(),
+                },
+            );
+        }
+
+        Ok((lobby.msg_sender.clone(), rx, session))
     }
 
-    pub async fn disconnect_user(
-        &mut self,
-        l_id: &String,
-        u_id: &String,
+    /// Mark a user's connection as gone. If nobody is left connected the
+    /// lobby is closed once the grace period passes without anyone rejoining.
+    pub(crate) fn disconnect_user(
+        &self,
+        l_id: &str,
+        u_id: &str,
+        session: u64,
     ) -> Result<(), LobbyError> {
+        let mut lobbies = self.lobbies.lock().expect("Lobby map poisoned");
+
+        let lobby = match lobbies.get_mut(l_id) {
+            Some(l) => l,
+            None => return Err(LobbyError::LobbyDoesNotExist),
+        };
+
         let any_connected = {
-            let lobby = match self.lobbies.get(l_id) {
-                Some(l) => l,
-                None => return Err(LobbyError::LobbyDoesNotExist),
+            let mut users = lobby.users.lock().expect("Users poisoned");
+
+            let user = match users.get_mut(u_id) {
+                Some(u) => u,
+                None => return Err(LobbyError::UserDoesNotExist),
             };
+            if user.session != session {
+                // The user already reconnected on another socket
+                return Ok(());
+            }
+            user.is_conn = false;
+            user.out.close();
+
+            users.values().any(|u| u.is_conn)
+        };
+
+        if !any_connected {
+            self.mark_empty(l_id, lobby);
+        }
+
+        Ok(())
+    }
+
+    /// Schedule an empty lobby to close after the grace period
+    fn mark_empty(&self, l_id: &str, lobby: &mut LobbyData) {
+        if lobby.empty {
+            return;
+        }
+
+        lobby.empty = true;
+        lobby.epoch += 1;
+        self.schedule_close(l_id.to_owned(), lobby.epoch);
+        emit(
+            &self.events,
+            LobbyEvent::Emptied {
+                lobby_id: l_id.to_owned(),
+            },
+        );
+    }
 
-            let mut users = lobby.users.lock().await;
+    /// Summaries of every lobby, sorted by id. Lobbies that don't answer in
+    /// time are left out.
+    pub async fn summaries(&self) -> Vec<LobbySummary> {
+        let ctrls: Vec<(String, Sender<LobbyCtrl>)> = self
+            .lobbies
+            .lock()
+            .expect("Lobby map poisoned")
+            .iter()
+            .map(|(id, l)| (id.clone(), l.ctrl.clone()))
+            .collect();
 
-            if !users.contains_key(u_id) {
-                return Err(LobbyError::UserDoesNotExist);
+        let mut summaries = Vec::with_capacity(ctrls.len());
+        for (id, ctrl) in ctrls {
+            match ask(&ctrl, LobbyCtrl::Inspect).await {
+                Ok(s) => summaries.push(s),
+                Err(e) => warn!(lobby_id = %id, error = ?e, "Lobby did not report its state"),
             }
-            users.get_mut(u_id).expect("Expected user").is_conn = false;
+        }
+        summaries.sort_by(|a, b| a.id.cmp(&b.id));
+
+        summaries
+    }
+
+    /// Full lobby state, including the unfiltered game
+    pub async fn dump(&self, l_id: &str) -> Result<LobbyDump, LobbyError> {
+        let ctrl = self.ctrl_sender(l_id)?;
+        ask(&ctrl, LobbyCtrl::Dump).await
+    }
+
+    /// Close a lobby straight away, disconnecting everyone in it
+    pub fn close(&self, l_id: &str) -> Result<(), LobbyError> {
+        let lobby = self
+            .lobbies
+            .lock()
+            .expect("Lobby map poisoned")
+            .remove(l_id)
+            .ok_or(LobbyError::LobbyDoesNotExist)?;
+
+        lobby.close();
+        emit(
+            &self.events,
+            LobbyEvent::Closed {
+                lobby_id: l_id.to_owned(),
+            },
+        );
+
+        Ok(())
+    }
+
+    /// Remove a user from a lobby and close their socket
+    pub fn kick(&self, l_id: &str, u_id: &str) -> Result<(), LobbyError> {
+        let mut lobbies = self.lobbies.lock().expect("Lobby map poisoned");
+        let lobby = lobbies.get_mut(l_id).ok_or(LobbyError::LobbyDoesNotExist)?;
+
+        let any_connected = {
+            let mut users = lobby.users.lock().expect("Users poisoned");
+
+            let mut user = users.remove(u_id).ok_or(LobbyError::UserDoesNotExist)?;
+            user.out.push(LobbyOutMsg::Kicked);
+            user.out.close();
 
-            users.iter().find(|(_, u)| u.is_conn).is_some()
+            users.values().any(|u| u.is_conn)
         };
 
+        lobby.send_ctrl(LobbyCtrl::Kicked {
+            user_id: u_id.to_owned(),
+        });
         if !any_connected {
-            self.lobbies.remove(l_id);
+            self.mark_empty(l_id, lobby);
         }
 
         Ok(())
     }
+
+    /// Send a message to every member of every lobby. Returns the number of
+    /// lobbies it was sent to.
+    pub fn announce(&self, msg: &str) -> usize {
+        let lobbies = self.lobbies.lock().expect("Lobby map poisoned");
+
+        for lobby in lobbies.values() {
+            lobby.send_ctrl(LobbyCtrl::Announce {
+                msg: msg.to_owned(),
+            });
+        }
+
+        lobbies.len()
+    }
+
+    fn ctrl_sender(&self, l_id: &str) -> Result<Sender<LobbyCtrl>, LobbyError> {
+        self.lobbies
+            .lock()
+            .expect("Lobby map poisoned")
+            .get(l_id)
+            .map(|l| l.ctrl.clone())
+            .ok_or(LobbyError::LobbyDoesNotExist)
+    }
+
+    fn schedule_close(&self, l_id: String, epoch: u64) {
+        let lobbies = self.lobbies.clone();
+        let events = self.events.clone();
+        let grace_period = self.config.lobby.grace_period;
+
+        tokio::spawn(async move {
+            tokio::time::sleep(grace_period).await;
+
+            let mut lobbies = lobbies.lock().expect("Lobby map poisoned");
+            // Anyone joining or leaving since bumps the epoch, in which case
+            // a newer timer (if any) is responsible for the lobby
+            if lobbies.get(&l_id).map(|l| l.epoch) != Some(epoch) {
+                return;
+            }
+
+            if let Some(lobby) = lobbies.remove(&l_id) {
+                lobby.close();
+                emit(&events, LobbyEvent::Closed { lobby_id: l_id });
+            }
+        });
+    }
+
+    /// Periodically close lobbies that have been idle or alive for too long.
+    /// Only holds a weak reference, so runs until the manager is dropped.
+    pub async fn run_reaper(lm: Weak<LobbyManager>) {
+        let Some(period) = lm.upgrade().map(|lm| lm.config.lobby.reaper.interval) else {
+            return;
+        };
+        let mut ticks = interval(period);
+        loop {
+            ticks.tick().await;
+            let Some(lm) = lm.upgrade() else {
+                return;
+            };
+
+            let reclaimed = lm.reap(Instant::now());
+            METRICS.lobbies_reaped.inc_by(reclaimed as u64);
+            if reclaimed > 0 {
+                info!(reclaimed, "Reaper reclaimed lobbies");
+            }
+        }
+    }
+
+    /// Close every lobby past its idle or lifetime deadline and warn the
+    /// members of lobbies about to reach theirs. Returns the number closed.
+    fn reap(&self, now: Instant) -> usize {
+        let mut lobbies = self.lobbies.lock().expect("Lobby map poisoned");
+        let reaper = &self.config.lobby.reaper;
+
+        let mut expired = Vec::new();
+        for (id, lobby) in lobbies.iter_mut() {
+            let deadline = lobby.deadline(reaper);
+
+            if deadline <= now {
+                expired.push(id.clone());
+            } else if deadline <= now + reaper.warning {
+                if !lobby.warned {
+                    lobby.warned = true;
+                    lobby.send_ctrl(LobbyCtrl::Warn {
+                        closes_in: deadline - now,
+                    });
+                }
+            } else {
+                // There was activity since the last warning
+                lobby.warned = false;
+            }
+        }
+
+        for id in expired.iter() {
+            if let Some(lobby) = lobbies.remove(id) {
+                lobby.close();
+                emit(
+                    &self.events,
+                    LobbyEvent::Closed {
+                        lobby_id: id.clone(),
+                    },
+                );
+            }
+        }
+
+        expired.len()
+    }
+
+    /// Tell every lobby the server is going away. Each lobby notifies its
+    /// members, saves its game and then closes.
+    pub fn shutdown(&self) {
+        let lobbies = self.lobbies.lock().expect("Lobby map poisoned");
+        info!(lobbies = lobbies.len(), "Shutting down lobbies");
+
+        for lobby in lobbies.values() {
+            lobby.shut_down(self.config.shutdown.reconnect_after);
+        }
+    }
 }
 
-pub struct User {
-    pub out: Sender<LobbyOutMsg>,
+/// Send a lobby a request and wait for its reply
+async fn ask<T>(
+    ctrl: &Sender<LobbyCtrl>,
+    request: impl FnOnce(oneshot::Sender<T>) -> LobbyCtrl,
+) -> Result<T, LobbyError> {
+    let (tx, rx) = oneshot::channel();
+    // Either end going away means the lobby closed in the meantime
+    let reply = async {
+        ctrl.send(request(tx))
+            .await
+            .map_err(|_| LobbyError::LobbyDoesNotExist)?;
+        rx.await.map_err(|_| LobbyError::LobbyDoesNotExist)
+    };
+
+    timeout(LOBBY_REPLY_TIMEOUT, reply)
+        .await
+        .unwrap_or(Err(LobbyError::LobbyUnresponsive))
+}
+
+fn emit(events: &broadcast::Sender<LobbyEvent>, event: LobbyEvent) {
+    match event {
+        LobbyEvent::Created { .. } => METRICS.lobbies.inc(),
+        LobbyEvent::Closed { .. } => METRICS.lobbies.dec(),
+        _ => {}
+    }
+    METRICS
+        .lobby_events
+        .with_label_values(&[event.name()])
+        .inc();
+
+    // Sending only fails when there are no subscribers
+    let _ = events.send(event);
+}
+
+pub(crate) struct User {
+    pub out: Outbound,
     pub is_conn: bool,
+    session: u64,
 }
 
-pub struct LobbyData {
-    id: String,
-    users: Arc<Mutex<HashMap<String, User>>>,
+struct LobbyData {
+    users: Users,
     msg_sender: Sender<InMsg>,
+    ctrl: Sender<LobbyCtrl>,
+    close: watch::Sender<Option<Close>>,
+    /// Bumped on every join and on the lobby becoming empty
+    epoch: u64,
+    /// Whether everyone has disconnected and a close has been scheduled
+    empty: bool,
+    created_at: Instant,
+    last_active: Arc<Mutex<Instant>>,
+    /// Whether members were told the lobby is about to be reaped
+    warned: bool,
 }
 
 impl LobbyData {
-    fn new(id: String) -> LobbyData {
-        let (tx, rx) = mpsc::channel(100);
+    fn new(id: String, config: Arc<Config>, assets: Arc<GameAssets>) -> LobbyData {
+        let (tx, rx) = mpsc::channel(config.lobby.channel_capacity);
+        let (ctrl_tx, ctrl_rx) = mpsc::channel(10);
+        let (close_tx, close_rx) = watch::channel(None);
         let users = Arc::new(Mutex::new(HashMap::new()));
+        let last_active = Arc::new(Mutex::new(Instant::now()));
+        let span = info_span!(parent: None, "lobby", lobby_id = %id);
 
-        let mut lobby = Lobby::new(id.clone(), users.clone(), rx);
+        let mut lobby = Lobby::new(
+            id,
+            config,
+            assets,
+            users.clone(),
+            rx,
+            ctrl_rx,
+            close_rx,
+            last_active.clone(),
+        );
 
-        tokio::spawn(async move {
-            lobby.lobby_loop().await;
-        });
+        tokio::spawn(
+            async move {
+                lobby.lobby_loop().await;
+            }
+            .instrument(span),
+        );
 
-        return LobbyData {
-            id,
+        LobbyData {
             users,
             msg_sender: tx,
-        };
+            ctrl: ctrl_tx,
+            close: close_tx,
+            epoch: 0,
+            empty: false,
+            created_at: Instant::now(),
+            last_active,
+            warned: false,
+        }
+    }
+
+    fn touch(&self) {
+        *self.last_active.lock().expect("Activity poisoned") = Instant::now();
+    }
+
+    fn deadline(&self, cfg: &ReaperConfig) -> Instant {
+        let last_active = *self.last_active.lock().expect("Activity poisoned");
+        (last_active + cfg.idle_ttl).min(self.created_at + cfg.max_lifetime)
+    }
+
+    /// Tell the lobby task to close. Unlike control messages this is never
+    /// dropped, however busy the lobby is.
+    fn close(self) {
+        self.close.send_replace(Some(Close::Now));
+    }
+
+    /// Tell the lobby task to save its game and close, as for `close`
+    fn shut_down(&self, reconnect_after: Duration) {
+        self.close
+            .send_replace(Some(Close::Shutdown { reconnect_after }));
+    }
+
+    fn send_ctrl(&self, ctrl: LobbyCtrl) {
+        if let Err(e) = self.ctrl.try_send(ctrl) {
+            warn!(error = %e, "Unable to send lobby control message");
+        }
     }
 }
@@ -0,0 +1,31 @@
+use tracing_subscriber::{fmt, prelude::*, EnvFilter};
+
+use crate::config::{LogConfig, LogFormat};
+
+/// Install the global tracing subscriber. `RUST_LOG`, when set, takes
+/// precedence over the configured level.
+pub fn init(config: &LogConfig) -> eyre::Result<()> {
+    let filter = match std::env::var(EnvFilter::DEFAULT_ENV) {
+        Ok(directives) => EnvFilter::try_new(directives)?,
+        Err(_) => EnvFilter::try_new(&config.level)?,
+    };
+
+    let registry = tracing_subscriber::registry().with(filter);
+
+    match config.format {
+        LogFormat::Pretty => registry.with(fmt::layer()).try_init()?,
+        LogFormat::Json => registry
+            .with(
+                fmt::layer()
+                    .json()
+                    .with_current_span(true)
+                    .with_span_list(true),
+            )
+            .try_init()?,
+    }
+
+    Ok(())
+}
+
+/// Stand in for content that could give away a game's secrets
+pub const REDACTED: &str = "<redacted>";
@@ -1,61 +1,70 @@
-mod games;
-mod lobby;
-mod lobby_manager;
-mod models;
-mod socket;
-mod user_manager;
-use axum::{extract::Extension, http::Method};
-use lobby_manager::LobbyManager;
-use std::net::SocketAddr;
+use clap::Parser;
+use game_site_be::{
+    config::{Args, Config},
+    games::GameAssets,
+    lobby_manager::{LobbyEvent, LobbyManager},
+    logging, router,
+    shutdown::{self, Shutdown},
+};
 use std::sync::Arc;
-use tokio::sync::Mutex;
-use tower_http::cors::{Any, CorsLayer};
-
-use axum::{
-    extract::{ws::WebSocket, Path, WebSocketUpgrade},
-    response::{Html, IntoResponse},
-    routing::get,
-    Router, TypedHeader,
+use tokio::{
+    sync::broadcast::{error::RecvError, Receiver},
+    time::timeout,
 };
+use tracing::{info, warn};
 
 #[tokio::main]
-async fn main() {
-    let cors = CorsLayer::new()
-        .allow_methods([Method::GET])
-        .allow_origin(Any);
-
-    let lm = Arc::new(Mutex::new(LobbyManager::new()));
-    let app = Router::new()
-        .route("/", get(handler))
-        .route("/lobby/:id/ws", get(ws_handler))
-        .layer(Extension(lm))
-        .layer(cors);
-
-    let addr = SocketAddr::from(([0, 0, 0, 0], 9000));
-    println!("Listening on {}", addr);
+async fn main() -> eyre::Result<()> {
+    let config = Arc::new(Config::load(Args::parse())?);
+    logging::init(&config.log)?;
+
+    let assets = Arc::new(GameAssets::load(&config.games).await?);
+    let lm = Arc::new(LobbyManager::new(config.clone(), assets));
+    tokio::spawn(log_lobby_events(lm.subscribe()));
+    tokio::spawn(LobbyManager::run_reaper(Arc::downgrade(&lm)));
+
+    let shutdown = Shutdown::new();
+    let app = router(config.clone(), lm.clone(), shutdown.clone())?;
+
+    let addr = config.server.bind;
+    info!(%addr, "Listening");
     axum::Server::bind(&addr)
         .serve(app.into_make_service())
-        .await
-        .unwrap();
-}
+        .with_graceful_shutdown(drain(shutdown, lm, config.clone()))
+        .await?;
 
-async fn handler() -> Html<&'static str> {
-    Html("<h1>Hello, World!</h1>")
+    info!("Server stopped");
+    Ok(())
 }
 
-async fn ws_handler<'a>(
-    Path(lobby_id): Path<String>,
-    Extension(lm): Extension<Arc<Mutex<LobbyManager>>>,
-    ws: WebSocketUpgrade,
-    user_agent: Option<TypedHeader<headers::UserAgent>>,
-) -> impl IntoResponse {
-    if let Some(TypedHeader(user_agent)) = user_agent {
-        println!(
-            "`{}` trying to connect to lobby {}",
-            user_agent.as_str(),
-            lobby_id
+/// Waits for a shutdown signal, then stops new sockets from connecting and
+/// fails readiness checks. After a short delay it tells every lobby to wrap
+/// up. Resolves once all sockets have closed or the deadline passes, at
+/// which point the server stops.
+async fn drain(shutdown: Shutdown, lm: Arc<LobbyManager>, config: Arc<Config>) {
+    shutdown::wait_for_signal().await;
+
+    shutdown.trigger();
+    tokio::time::sleep(config.shutdown.readiness_delay).await;
+    lm.shutdown();
+
+    if timeout(config.shutdown.deadline, shutdown.sockets_drained())
+        .await
+        .is_err()
+    {
+        warn!(
+            open_sockets = shutdown.open_sockets(),
+            "Shutdown deadline reached with sockets still open"
         );
     }
+}
 
-    ws.on_upgrade(move |socket: WebSocket| socket::handle_socket(socket, lm, lobby_id))
+async fn log_lobby_events(mut events: Receiver<LobbyEvent>) {
+    loop {
+        match events.recv().await {
+            Ok(event) => info!(lobby_id = event.lobby_id(), ?event, "Lobby event"),
+            Err(RecvError::Lagged(n)) => warn!(missed = n, "Missed lobby events"),
+            Err(RecvError::Closed) => return,
+        }
+    }
 }
@@ -0,0 +1,155 @@
+use prometheus::{
+    core::Collector, Encoder, Histogram, HistogramOpts, IntCounter, IntCounterVec, IntGauge, Opts,
+    Registry, TextEncoder,
+};
+use std::sync::LazyLock;
+
+/// Every metric the server exports on `/metrics`
+pub struct Metrics {
+    registry: Registry,
+
+    pub lobbies: IntGauge,
+    pub lobby_events: IntCounterVec,
+    pub lobbies_reaped: IntCounter,
+
+    pub sockets_open: IntGauge,
+    pub messages_received: IntCounterVec,
+    pub messages_sent: IntCounter,
+    pub limit_violations: IntCounterVec,
+    pub abusive_disconnects: IntCounter,
+    /// Outcome of pushing a message onto a user's outbound channel
+    pub outbound: IntCounterVec,
+
+    pub broadcast_seconds: Histogram,
+    pub moves: IntCounter,
+    pub rejected_moves: IntCounterVec,
+    pub games_started: IntCounterVec,
+    pub games_finished: IntCounterVec,
+    pub games_abandoned: IntCounterVec,
+}
+
+pub static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::new);
+
+impl Metrics {
+    fn new() -> Metrics {
+        let registry =
+            Registry::new_custom(Some("gamesite".to_owned()), None).expect("Valid registry prefix");
+
+        let m = Metrics {
+            lobbies: IntGauge::new("lobbies", "Lobbies currently running").unwrap(),
+            lobby_events: IntCounterVec::new(
+                Opts::new("lobby_events_total", "Lobby lifecycle events by kind"),
+                &["event"],
+            )
+            .unwrap(),
+            lobbies_reaped: IntCounter::new(
+                "lobbies_reaped_total",
+                "Lobbies closed by the idle reaper",
+            )
+            .unwrap(),
+            sockets_open: IntGauge::new("sockets_open", "WebSockets currently connected").unwrap(),
+            messages_received: IntCounterVec::new(
+                Opts::new(
+                    "messages_received_total",
+                    "Messages received from clients by type",
+                ),
+                &["msg_type"],
+            )
+            .unwrap(),
+            messages_sent: IntCounter::new("messages_sent_total", "Messages sent to clients")
+                .unwrap(),
+            limit_violations: IntCounterVec::new(
+                Opts::new(
+                    "limit_violations_total",
+                    "Client messages refused for going over a socket limit",
+                ),
+                &["limit"],
+            )
+            .unwrap(),
+            abusive_disconnects: IntCounter::new(
+                "abusive_disconnects_total",
+                "Sockets closed for repeatedly going over limits",
+            )
+            .unwrap(),
+            outbound: IntCounterVec::new(
+                Opts::new(
+                    "outbound_messages_total",
+                    "Messages queued for users by delivery outcome",
+                ),
+                &["outcome"],
+            )
+            .unwrap(),
+            broadcast_seconds: Histogram::with_opts(
+                HistogramOpts::new(
+                    "broadcast_duration_seconds",
+                    "Time taken to build and queue a broadcast for every user in a lobby",
+                )
+                .buckets(vec![
+                    0.00001, 0.00005, 0.0001, 0.0005, 0.001, 0.005, 0.01, 0.05, 0.1,
+                ]),
+            )
+            .unwrap(),
+            moves: IntCounter::new("game_moves_total", "Game moves made").unwrap(),
+            rejected_moves: IntCounterVec::new(
+                Opts::new("rejected_moves_total", "Game moves rejected by reason"),
+                &["reason"],
+            )
+            .unwrap(),
+            games_started: IntCounterVec::new(
+                Opts::new("games_started_total", "Games started by game type"),
+                &["game"],
+            )
+            .unwrap(),
+            games_finished: IntCounterVec::new(
+                Opts::new(
+                    "games_finished_total",
+                    "Games played to the end by game type",
+                ),
+                &["game"],
+            )
+            .unwrap(),
+            games_abandoned: IntCounterVec::new(
+                Opts::new(
+                    "games_abandoned_total",
+                    "Games still running when their lobby closed by game type",
+                ),
+                &["game"],
+            )
+            .unwrap(),
+            registry,
+        };
+
+        let collectors: Vec<Box<dyn Collector>> = vec![
+            Box::new(m.lobbies.clone()),
+            Box::new(m.lobby_events.clone()),
+            Box::new(m.lobbies_reaped.clone()),
+            Box::new(m.sockets_open.clone()),
+            Box::new(m.messages_received.clone()),
+            Box::new(m.messages_sent.clone()),
+            Box::new(m.limit_violations.clone()),
+            Box::new(m.abusive_disconnects.clone()),
+            Box::new(m.outbound.clone()),
+            Box::new(m.broadcast_seconds.clone()),
+            Box::new(m.moves.clone()),
+            Box::new(m.rejected_moves.clone()),
+            Box::new(m.games_started.clone()),
+            Box::new(m.games_finished.clone()),
+            Box::new(m.games_abandoned.clone()),
+        ];
+        for c in collectors {
+            m.registry.register(c).expect("Metric names are unique");
+        }
+
+        m
+    }
+
+    /// Encode every metric in the Prometheus text format
+    pub fn render(&self) -> String {
+        let mut buf = Vec::new();
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buf)
+            .expect("Metrics encode to a Vec");
+
+        String::from_utf8(buf).expect("Metrics are utf8")
+    }
+}
@@ -0,0 +1,43 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::games::{GameSettings, GameType};
+
+use super::lobby::LobbyState;
+
+/// One row of the admin lobby listing
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct LobbySummary {
+    pub id: String,
+    pub members: usize,
+    pub connected: usize,
+    pub game_type: GameType,
+    pub state: LobbyState,
+    /// Stage the running game is in, e.g. "Guessing"
+    pub phase: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct MemberStatus {
+    pub user_id: String,
+    pub connected: bool,
+}
+
+/// Everything a lobby knows, including game secrets hidden from players
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct LobbyDump {
+    pub id: String,
+    pub game_type: GameType,
+    pub state: LobbyState,
+    pub members: Vec<MemberStatus>,
+    pub settings: GameSettings,
+    pub game: Option<Value>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Announcement {
+    pub msg: String,
+}
@@ -1,7 +1,8 @@
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use std::sync::Arc;
 
-use crate::games::GameType;
+use crate::games::{GameSettings, GameType};
 
 pub struct InMsg {
     pub uid: String,
@@ -24,6 +25,34 @@ pub enum LobbyInMsg {
     GetGameType,
     #[serde(rename_all = "camelCase")]
     GameMove(Value),
+    /// Change the settings used by the next game
+    SetSettings(GameSettings),
+    /// Choose which game is played next
+    SelectGame(GameType),
+    /// Ask for the current canvas, or the drawings the user can see, in games
+    /// with them
+    GetCanvas,
+    /// A binary frame from the client, e.g. drawing strokes
+    #[serde(skip)]
+    Binary(Arc<Vec<u8>>),
+}
+
+impl LobbyInMsg {
+    /// Short name of the message type, used as a metric label
+    pub fn kind(&self) -> &'static str {
+        match self {
+            LobbyInMsg::Join { .. } => "join",
+            LobbyInMsg::Leave => "leave",
+            LobbyInMsg::Start => "start",
+            LobbyInMsg::GetUsers => "get_users",
+            LobbyInMsg::GetGameType => "get_game_type",
+            LobbyInMsg::GameMove(_) => "game_move",
+            LobbyInMsg::SetSettings(_) => "set_settings",
+            LobbyInMsg::SelectGame(_) => "select_game",
+            LobbyInMsg::GetCanvas => "get_canvas",
+            LobbyInMsg::Binary(_) => "binary",
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, Copy)]
@@ -36,8 +65,33 @@ pub enum LobbyState {
 #[serde(rename_all = "camelCase")]
 #[serde(tag = "msgType", content = "data")]
 pub enum LobbyOutMsg {
-    Error { msg: String },
+    Error {
+        msg: String,
+    },
     Members(Vec<String>),
     SelectedGame(GameType),
+    Settings(GameSettings),
     GameState(Value),
+    #[serde(rename_all = "camelCase")]
+    LobbyClosing {
+        closes_in_secs: u64,
+    },
+    #[serde(rename_all = "camelCase")]
+    ServerShuttingDown {
+        reconnect_after_secs: u64,
+    },
+    /// Message from the server operators
+    Announcement {
+        msg: String,
+    },
+    /// The user was removed from the lobby by an admin
+    Kicked,
+    /// The next binary frame holds the current canvas: `strokes` binary
+    /// frames, each prefixed with its length as a big endian u32
+    Canvas {
+        strokes: usize,
+    },
+    /// Sent to the client as a binary frame rather than json
+    #[serde(skip)]
+    Binary(Arc<Vec<u8>>),
 }
@@ -0,0 +1,184 @@
+use serde_json::Value;
+use std::sync::{Arc, Mutex};
+use tokio::{
+    select,
+    sync::{
+        mpsc::{self, error::TrySendError, Receiver, Sender},
+        Notify,
+    },
+};
+
+use crate::{metrics::METRICS, models::lobby::LobbyOutMsg};
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Delivery {
+    Sent,
+    /// A game state the user had not read yet was replaced by this one
+    Coalesced,
+    /// The user's queue was full and the message was discarded
+    Dropped,
+    /// The user exceeded the lag budget, or their receiver is gone
+    Disconnected,
+}
+
+#[derive(Default)]
+struct StateSlot {
+    latest: Mutex<Option<Value>>,
+    notify: Notify,
+}
+
+/// Lobby side of a user's outbound channel. Pushing never waits on the
+/// user's socket so one slow client can't stall the whole lobby.
+pub struct Outbound {
+    msgs: Option<Sender<LobbyOutMsg>>,
+    state: Arc<StateSlot>,
+    strikes: u32,
+    /// Number of consecutive messages a user may miss, either dropped or
+    /// replacing a game state they never read, before the lobby gives up on
+    /// them and closes their outbound channel
+    max_strikes: u32,
+}
+
+/// Socket side of a user's outbound channel
+pub struct Inbound {
+    msgs: Receiver<LobbyOutMsg>,
+    state: Arc<StateSlot>,
+}
+
+pub fn channel(capacity: usize, max_strikes: u32) -> (Outbound, Inbound) {
+    let (tx, rx) = mpsc::channel(capacity);
+    let state = Arc::new(StateSlot::default());
+
+    (
+        Outbound {
+            msgs: Some(tx),
+            state: state.clone(),
+            strikes: 0,
+            max_strikes,
+        },
+        Inbound { msgs: rx, state },
+    )
+}
+
+impl Outbound {
+    /// Queue a message for the user without blocking.
+    ///
+    /// Game states are coalesced: only the most recent unread state is kept
+    /// since each one supersedes the last. Any other message is queued and
+    /// dropped if the queue is full.
+    pub fn push(&mut self, msg: LobbyOutMsg) -> Delivery {
+        let Some(msgs) = &self.msgs else {
+            return Delivery::Disconnected;
+        };
+
+        let res = match msg {
+            LobbyOutMsg::GameState(s) => {
+                let replaced = self
+                    .state
+                    .latest
+                    .lock()
+                    .expect("State slot poisoned")
+                    .replace(s)
+                    .is_some();
+                self.state.notify.notify_one();
+
+                if replaced {
+                    Delivery::Coalesced
+                } else {
+                    Delivery::Sent
+                }
+            }
+            msg => match msgs.try_send(msg) {
+                Ok(_) => Delivery::Sent,
+                Err(TrySendError::Full(_)) => Delivery::Dropped,
+                Err(TrySendError::Closed(_)) => Delivery::Disconnected,
+            },
+        };
+
+        let res = match res {
+            Delivery::Sent => {
+                self.strikes = 0;
+                Delivery::Sent
+            }
+            Delivery::Disconnected => Delivery::Disconnected,
+            missed => {
+                self.strikes += 1;
+                if self.strikes > self.max_strikes {
+                    Delivery::Disconnected
+                } else {
+                    missed
+                }
+            }
+        };
+
+        let outcome = match res {
+            Delivery::Sent => "sent",
+            Delivery::Coalesced => "coalesced",
+            Delivery::Dropped => "dropped",
+            Delivery::Disconnected => {
+                self.close();
+                "disconnected"
+            }
+        };
+        METRICS.outbound.with_label_values(&[outcome]).inc();
+
+        res
+    }
+
+    /// Drop the sending half so the user's socket task sees the channel close
+    pub fn close(&mut self) {
+        self.msgs = None;
+    }
+}
+
+impl Inbound {
+    /// Wait for the next message for this user. Queued messages are
+    /// delivered before the latest game state.
+    pub async fn recv(&mut self) -> Option<LobbyOutMsg> {
+        loop {
+            select! {
+                biased;
+                msg = self.msgs.recv() => return msg,
+                _ = self.state.notify.notified() => {
+                    let latest = self.state.latest.lock().expect("State slot poisoned").take();
+                    if let Some(s) = latest {
+                        return Some(LobbyOutMsg::GameState(s));
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn state() -> LobbyOutMsg {
+        LobbyOutMsg::GameState(json!({}))
+    }
+
+    #[test]
+    fn unread_states_count_against_the_lag_budget() {
+        let (mut out, _inbound) = channel(10, 2);
+        assert_eq!(out.push(state()), Delivery::Sent);
+        assert_eq!(out.push(state()), Delivery::Coalesced);
+        assert_eq!(out.push(state()), Delivery::Coalesced);
+        assert_eq!(out.push(state()), Delivery::Disconnected);
+        assert_eq!(out.push(state()), Delivery::Disconnected);
+    }
+
+    #[tokio::test]
+    async fn reading_resets_the_lag_budget() {
+        let (mut out, mut inbound) = channel(10, 1);
+        for _ in 0..5 {
+            assert_eq!(out.push(state()), Delivery::Sent);
+            assert_eq!(out.push(state()), Delivery::Coalesced);
+            assert!(matches!(
+                inbound.recv().await,
+                Some(LobbyOutMsg::GameState(_))
+            ));
+        }
+    }
+}
@@ -0,0 +1,96 @@
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+use tokio::{
+    signal::unix::{signal, SignalKind},
+    sync::{watch, Notify},
+};
+use tracing::info;
+
+/// Shared view of whether the server is shutting down, plus a count of the
+/// sockets still open so shutdown can wait for them to drain.
+#[derive(Clone)]
+pub struct Shutdown {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    triggered: watch::Sender<bool>,
+    sockets: AtomicUsize,
+    sockets_closed: Notify,
+}
+
+/// Held by each socket task for as long as its socket is open
+pub struct SocketGuard {
+    shutdown: Shutdown,
+}
+
+impl Shutdown {
+    pub fn new() -> Shutdown {
+        let (triggered, _) = watch::channel(false);
+
+        Shutdown {
+            inner: Arc::new(Inner {
+                triggered,
+                sockets: AtomicUsize::new(0),
+                sockets_closed: Notify::new(),
+            }),
+        }
+    }
+
+    pub fn trigger(&self) {
+        self.inner.triggered.send_replace(true);
+    }
+
+    pub fn is_triggered(&self) -> bool {
+        *self.inner.triggered.borrow()
+    }
+
+    pub fn track_socket(&self) -> SocketGuard {
+        self.inner.sockets.fetch_add(1, Ordering::SeqCst);
+        SocketGuard {
+            shutdown: self.clone(),
+        }
+    }
+
+    pub fn open_sockets(&self) -> usize {
+        self.inner.sockets.load(Ordering::SeqCst)
+    }
+
+    /// Resolves once every tracked socket has closed
+    pub async fn sockets_drained(&self) {
+        loop {
+            let closed = self.inner.sockets_closed.notified();
+            if self.open_sockets() == 0 {
+                return;
+            }
+            closed.await;
+        }
+    }
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Shutdown::new()
+    }
+}
+
+impl Drop for SocketGuard {
+    fn drop(&mut self) {
+        let inner = &self.shutdown.inner;
+        if inner.sockets.fetch_sub(1, Ordering::SeqCst) == 1 {
+            inner.sockets_closed.notify_waiters();
+        }
+    }
+}
+
+/// Resolves on SIGTERM or Ctrl-C
+pub async fn wait_for_signal() {
+    let mut term = signal(SignalKind::terminate()).expect("Unable to listen for SIGTERM");
+
+    tokio::select! {
+        _ = term.recv() => info!("Received SIGTERM"),
+        _ = tokio::signal::ctrl_c() => info!("Received Ctrl-C"),
+    }
+}
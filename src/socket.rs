@@ -1,30 +1,47 @@
-use std::sync::Arc;
+use std::{sync::Arc, time::Instant};
 
+use crate::config::LimitsConfig;
+use crate::limits::{Limiter, Violation};
 use crate::lobby_manager::LobbyManager;
+use crate::metrics::METRICS;
 use crate::models::lobby::InMsg;
 use crate::models::lobby::{LobbyInMsg, LobbyOutMsg};
-use axum::extract::ws::{Message, WebSocket};
+use crate::outbound::Inbound;
+use crate::shutdown::{Shutdown, SocketGuard};
+use crate::text;
+use axum::extract::ws::{close_code, CloseFrame, Message, WebSocket};
 use eyre::{eyre, WrapErr};
-use serde_json;
 use tokio::select;
-use tokio::sync::mpsc::{Receiver, Sender};
-use tokio::sync::Mutex;
+use tokio::sync::mpsc::Sender;
+use tracing::{debug, info, trace, warn, Instrument, Span};
+
+const MAX_USER_ID_CHARS: usize = 32;
 
 struct UserManager {
     user_id: String,
     lobby_id: String,
-    lm: Arc<Mutex<LobbyManager>>,
+    session: u64,
+    lm: Arc<LobbyManager>,
 
     socket: WebSocket,
     c_out: Sender<InMsg>,
-    c_in: Receiver<LobbyOutMsg>,
+    c_in: Inbound,
+    limiter: Limiter,
+
+    shutdown: Shutdown,
+    _guard: SocketGuard,
 }
 
 async fn handle_join(
     mut socket: WebSocket,
-    lm_mutex: Arc<Mutex<LobbyManager>>,
+    lm: Arc<LobbyManager>,
     lobby_id: String,
+    shutdown: Shutdown,
+    limits: LimitsConfig,
 ) -> Result<UserManager, String> {
+    let guard = shutdown.track_socket();
+    let mut limiter = Limiter::new(limits);
+
     let Some(res) = socket.recv().await else {
         return Err("Socked closed before join message".to_owned());
     };
@@ -32,19 +49,55 @@ async fn handle_join(
         return Err("Socked closed before join message".to_owned());
     };
     let Message::Text(txt) = ws_msg else {
-        let _ = socket.send(Message::Text("Error: Expected join message with user id".to_string())).await;
-        return Err(format!("Initial message from socket was not a text message. Msg: {:?}", &ws_msg));
+        let _ = socket
+            .send(Message::Text(
+                "Error: Expected join message with user id".to_string(),
+            ))
+            .await;
+        return Err(format!(
+            "Initial message from socket was not a text message. Msg: {:?}",
+            &ws_msg
+        ));
     };
     let Ok(LobbyInMsg::Join { user_id }) = serde_json::from_str(&txt) else {
-        let _ = socket.send(Message::Text("Error: Expected join message with user id".to_string())).await;
-        return Err(format!("Initial message from socket was not join message. Msg {}", txt));
+        let _ = socket
+            .send(Message::Text(
+                "Error: Expected join message with user id".to_string(),
+            ))
+            .await;
+        return Err(format!(
+            "Initial message from socket was not join message. Msg {}",
+            txt
+        ));
     };
 
-    let mut lm = lm_mutex.lock().await;
+    let checked = limiter
+        .check_frame(txt.len(), Instant::now())
+        .and_then(|_| {
+            limiter.check_fields(&LobbyInMsg::Join {
+                user_id: user_id.clone(),
+            })
+        });
+    if let Err(v) = checked {
+        let _ = socket
+            .send(Message::Text(format!("Error: {}", limiter.describe(v))))
+            .await;
+        return Err(format!("Join message went over limits: {:?}", v));
+    }
 
-    lm.create_lobby(lobby_id.clone());
+    let user_id = match text::clean(&user_id, MAX_USER_ID_CHARS) {
+        Ok(id) => id,
+        Err(e) => {
+            let _ = socket
+                .send(Message::Text(format!("Error: User id {}", e)))
+                .await;
+            return Err(format!("Invalid user id: {}", e));
+        }
+    };
 
-    let (lobby_in, lobby_out) = match lm.add_user(&lobby_id, &user_id).await {
+    Span::current().record("user_id", user_id.as_str());
+
+    let (lobby_in, lobby_out, session) = match lm.join(&lobby_id, &user_id) {
         Ok(c) => c,
         Err(e) => return Err(format!("Error joining lobby {}: {:?}", lobby_id, e)),
     };
@@ -58,21 +111,21 @@ async fn handle_join(
         })
         .await;
 
-    if res.is_err() {
-        println!(
-            "Could not join lobby {}, error: {}",
-            &lobby_id,
-            res.unwrap_err()
-        );
+    if let Err(e) = res {
+        warn!(error = %e, "Could not join lobby");
     }
 
     Ok(UserManager {
         user_id,
         lobby_id,
-        lm: lm_mutex.clone(),
+        session,
+        lm,
         socket,
         c_out: lobby_in,
         c_in: lobby_out,
+        limiter,
+        shutdown,
+        _guard: guard,
     })
 }
 
@@ -87,48 +140,33 @@ async fn communicate(mut um: UserManager) {
                     })
                     .unwrap_or_else(|| Err(eyre!("Socket Closed"))) {
                     Ok(msg)=> {
-                        match msg {
-                            Message::Text(t) => {
-                                if !t.eq("ping") {
-                                    let msg: Result<LobbyInMsg, _> = serde_json::from_str(&t);
-                                    match msg {
-                                        Ok(msg) => {
-                                            um.c_out.send(InMsg { uid: um.user_id.clone(), cmd: msg }).await.unwrap_or_else(|e| println!("{}", e));
-                                        },
-                                        Err(e) => {
-                                            let msg = serde_json::to_string(
-                                                &LobbyOutMsg::Error { msg: format!("{}", e)}
-                                            ).unwrap_or_else(|_| "Internal Server Error...".to_owned());
-
-                                            um.socket.send(Message::Text(msg)).await.unwrap_or_else(|err| {
-                                                println!("Error message failed to send {}", err);
-                                            });
-                                        }
-                                    }
-                                }
-                            }
-                            Message::Binary(_) => {
-                                println!("client sent binary data... ignoring");
-                            }
+                        let res = match msg {
+                            Message::Text(t) => handle_text(&mut um, t).await,
+                            Message::Binary(b) => handle_binary(&mut um, b).await,
                             Message::Ping(_) => {
-                                println!("socket ping");
+                                trace!("Socket ping");
+                                Ok(())
                             }
                             Message::Pong(_) => {
-                                println!("socket pong");
+                                trace!("Socket pong");
+                                Ok(())
                             }
                             Message::Close(_) => {
                                 panic!("Close should have been mapped to err");
                             }
-                        }
+                        };
 
+                        if let Err(v) = res {
+                            if over_limit(&mut um, v).await {
+                                leave(&mut um).await;
+                                return
+                            }
+                        }
                     },
                     Err(e) => {
-                        println!("{:?}", e);
+                        info!(reason = %e, "Socket closed");
 
-                        let mut lm = um.lm.lock().await;
-                        lm.disconnect_user(&um.lobby_id, &um.user_id).await;
-
-                        um.c_out.send(InMsg {uid: um.user_id.clone(), cmd: LobbyInMsg::Leave }).await;
+                        leave(&mut um).await;
                         return
                     }
                 }
@@ -136,22 +174,43 @@ async fn communicate(mut um: UserManager) {
             },
             lobby_res = um.c_in.recv() => {
                 match lobby_res {
+                    Some(LobbyOutMsg::Binary(data)) => {
+                        // Relayed frames are shared by every recipient, the
+                        // socket only needs its own copy while others hold one
+                        let data = Arc::try_unwrap(data).unwrap_or_else(|d| d.to_vec());
+                        match um.socket.send(Message::Binary(data)).await {
+                            Ok(_) => METRICS.messages_sent.inc(),
+                            Err(e) => warn!(error = %e, "Error sending binary message"),
+                        }
+                    },
                     Some(msg) => {
                         let res = match serde_json::to_string(&msg) {
                             Ok(txt) => um.socket.send(Message::Text(txt)).await,
-                            Err(_) => {
-                                println!("Unable to encode {:?}", msg);
+                            Err(e) => {
+                                warn!(error = %e, "Unable to encode message");
                                 Ok(())
                             }
                         };
-                        if let Err(e) = res {
-                            println!("Error Sending msg {:?} {}", msg, e)
+                        match res {
+                            Ok(_) => METRICS.messages_sent.inc(),
+                            Err(e) => warn!(error = %e, "Error sending message"),
                         }
                     },
                     None => {
-                        um.socket.send(Message::Text("Error: Lobby Closed".to_string())).await.unwrap_or_else(|err| {
-                            println!("Lobby Closed message failed to send {}", err);
-                        });
+                        // The lobby closed our channel, either because it shut down or
+                        // because we fell too far behind on messages
+                        let frame = if um.shutdown.is_triggered() {
+                            CloseFrame { code: close_code::AWAY, reason: "Server shutting down".into() }
+                        } else {
+                            um.socket.send(Message::Text("Error: Lobby Closed".to_string())).await.unwrap_or_else(|err| {
+                                warn!(error = %err, "Lobby Closed message failed to send");
+                            });
+                            CloseFrame { code: close_code::NORMAL, reason: "Lobby closed".into() }
+                        };
+                        let _ = um.socket.send(Message::Close(Some(frame))).await;
+
+                        leave(&mut um).await;
+                        return
                     }
                 }
             }
@@ -159,16 +218,134 @@ async fn communicate(mut um: UserManager) {
     }
 }
 
-pub async fn handle_socket(socket: WebSocket, lm: Arc<Mutex<LobbyManager>>, lobby_id: String) {
-    let um = match handle_join(socket, lm, lobby_id).await {
+/// Forward a text message from the client to the lobby
+async fn handle_text(um: &mut UserManager, t: String) -> Result<(), Violation> {
+    um.limiter.check_frame(t.len(), Instant::now())?;
+
+    if t.eq("ping") {
+        METRICS.messages_received.with_label_values(&["ping"]).inc();
+        return Ok(());
+    }
+
+    match serde_json::from_str::<LobbyInMsg>(&t) {
+        Ok(msg) => {
+            um.limiter.check_fields(&msg)?;
+            METRICS
+                .messages_received
+                .with_label_values(&[msg.kind()])
+                .inc();
+            um.c_out
+                .send(InMsg {
+                    uid: um.user_id.clone(),
+                    cmd: msg,
+                })
+                .await
+                .unwrap_or_else(|e| warn!(error = %e, "Unable to forward message to lobby"));
+        }
+        Err(e) => {
+            METRICS
+                .messages_received
+                .with_label_values(&["invalid"])
+                .inc();
+            send_error(um, format!("{}", e)).await;
+        }
+    }
+
+    Ok(())
+}
+
+/// Forward a binary frame from the client, e.g. drawing strokes, to the lobby
+async fn handle_binary(um: &mut UserManager, b: Vec<u8>) -> Result<(), Violation> {
+    um.limiter.check_binary(b.len(), Instant::now())?;
+
+    let msg = LobbyInMsg::Binary(Arc::new(b));
+    METRICS
+        .messages_received
+        .with_label_values(&[msg.kind()])
+        .inc();
+    um.c_out
+        .send(InMsg {
+            uid: um.user_id.clone(),
+            cmd: msg,
+        })
+        .await
+        .unwrap_or_else(|e| warn!(error = %e, "Unable to forward message to lobby"));
+
+    Ok(())
+}
+
+/// Tell the client its message was refused. Returns whether the client
+/// has gone over the limits too often and its socket was closed.
+async fn over_limit(um: &mut UserManager, violation: Violation) -> bool {
+    METRICS
+        .limit_violations
+        .with_label_values(&[violation.kind()])
+        .inc();
+    debug!(violation = violation.kind(), "Client went over a limit");
+    send_error(um, um.limiter.describe(violation)).await;
+
+    if !um.limiter.record(Instant::now()) {
+        return false;
+    }
+
+    warn!("Disconnecting client for repeatedly going over limits");
+    METRICS.abusive_disconnects.inc();
+    let frame = CloseFrame {
+        code: close_code::POLICY,
+        reason: "Too many limit violations".into(),
+    };
+    let _ = um.socket.send(Message::Close(Some(frame))).await;
+
+    true
+}
+
+async fn send_error(um: &mut UserManager, msg: String) {
+    let msg = serde_json::to_string(&LobbyOutMsg::Error { msg })
+        .unwrap_or_else(|_| "Internal Server Error...".to_owned());
+
+    um.socket
+        .send(Message::Text(msg))
+        .await
+        .unwrap_or_else(|err| {
+            warn!(error = %err, "Error message failed to send");
+        });
+}
+
+async fn leave(um: &mut UserManager) {
+    if let Err(e) = um.lm.disconnect_user(&um.lobby_id, &um.user_id, um.session) {
+        warn!(error = ?e, "Error disconnecting user");
+    }
+
+    let _ = um
+        .c_out
+        .send(InMsg {
+            uid: um.user_id.clone(),
+            cmd: LobbyInMsg::Leave,
+        })
+        .await;
+}
+
+pub async fn handle_socket(
+    socket: WebSocket,
+    lm: Arc<LobbyManager>,
+    lobby_id: String,
+    shutdown: Shutdown,
+    limits: LimitsConfig,
+) {
+    let um = match handle_join(socket, lm, lobby_id, shutdown, limits).await {
         Ok(c) => c,
         Err(e) => {
-            println!("{}", e);
+            info!(reason = %e, "Socket did not join a lobby");
             return;
         }
     };
 
-    tokio::spawn(async move {
-        communicate(um).await;
-    });
+    tokio::spawn(
+        async move {
+            METRICS.sockets_open.inc();
+            communicate(um).await;
+            METRICS.sockets_open.dec();
+        }
+        .in_current_span(),
+    );
 }
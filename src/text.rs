@@ -0,0 +1,77 @@
+use std::fmt;
+use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
+
+/// Why a piece of player supplied text was refused
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextError {
+    Empty,
+    TooLong { max_chars: usize },
+    InvalidCharacters,
+}
+
+impl fmt::Display for TextError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TextError::Empty => write!(f, "must not be empty"),
+            TextError::TooLong { max_chars } => {
+                write!(f, "must be at most {} characters", max_chars)
+            }
+            TextError::InvalidCharacters => {
+                write!(f, "must not contain control or invisible characters")
+            }
+        }
+    }
+}
+
+/// NFKC normalise `s`, trim it and collapse every run of whitespace,
+/// including newlines, into a single space
+pub fn normalize(s: &str) -> String {
+    s.nfkc()
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Normalise `s` and check it is visible, non-empty text of at most
+/// `max_chars` characters
+pub fn clean(s: &str, max_chars: usize) -> Result<String, TextError> {
+    let s = normalize(s);
+
+    if s.is_empty() {
+        return Err(TextError::Empty);
+    }
+    if s.chars().any(is_invisible) {
+        return Err(TextError::InvalidCharacters);
+    }
+    if s.chars().count() > max_chars {
+        return Err(TextError::TooLong { max_chars });
+    }
+
+    Ok(s)
+}
+
+/// Case and accent insensitive form of `s`, for comparing words
+pub fn fold(s: &str) -> String {
+    strip_accents(&normalize(s)).to_lowercase()
+}
+
+/// Drop diacritics, so "café" becomes "cafe"
+pub fn strip_accents(s: &str) -> String {
+    s.nfd().filter(|c| !is_combining_mark(*c)).nfc().collect()
+}
+
+/// Control characters plus the zero width and direction changing characters
+/// that can make two strings look the same while comparing differently
+fn is_invisible(c: char) -> bool {
+    c.is_control()
+        || matches!(
+            c,
+            '\u{00AD}'
+                | '\u{200B}'..='\u{200F}'
+                | '\u{202A}'..='\u{202E}'
+                | '\u{2060}'..='\u{2064}'
+                | '\u{2066}'..='\u{2069}'
+                | '\u{FEFF}'
+        )
+}
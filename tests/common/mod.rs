@@ -0,0 +1,168 @@
+//! Helpers shared by the black box tests, which run the server in process
+//! and play through websockets the way the frontend does
+
+#![allow(dead_code)]
+
+use futures::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use std::{net::SocketAddr, sync::Arc, time::Duration};
+use tokio::{net::TcpStream, time::timeout};
+use tokio_tungstenite::{connect_async, tungstenite::Message, MaybeTlsStream, WebSocketStream};
+
+use game_site_be::{
+    config::Config,
+    games::{
+        location_list::{Location, LocationList},
+        question_pack::{Question, QuestionPack},
+        spectrum_list::{Spectrum, SpectrumList},
+        word_list::WordList,
+        GameAssets,
+    },
+    lobby_manager::LobbyManager,
+    router,
+    shutdown::Shutdown,
+};
+
+pub const WORD: &str = "Apple";
+
+pub async fn start_server() -> SocketAddr {
+    serve(manager()).await
+}
+
+/// A lobby manager with a small set of test assets
+pub fn manager() -> Arc<LobbyManager> {
+    let config = Arc::new(Config::default());
+    let assets = Arc::new(GameAssets {
+        nouns: WordList {
+            description: "Test".to_owned(),
+            words: vec![WORD.to_owned()],
+        },
+        categories: WordList {
+            description: "Test".to_owned(),
+            words: vec!["Fruits".to_owned()],
+        },
+        locations: LocationList {
+            description: "Test".to_owned(),
+            locations: vec![Location {
+                name: "Beach".to_owned(),
+                roles: vec!["Lifeguard".to_owned()],
+            }],
+        },
+        spectrums: SpectrumList {
+            description: "Test".to_owned(),
+            cards: vec![Spectrum {
+                left: "Hot".to_owned(),
+                right: "Cold".to_owned(),
+            }],
+        },
+        questions: QuestionPack {
+            description: "Test".to_owned(),
+            questions: vec![Question {
+                category: "Food".to_owned(),
+                question: "What is guacamole made from?".to_owned(),
+                choices: Vec::new(),
+                answer: "Avocado".to_owned(),
+                alternatives: Vec::new(),
+            }],
+        },
+    });
+    Arc::new(LobbyManager::new(config, assets))
+}
+
+/// Serve `lm` on a free local port
+pub async fn serve(lm: Arc<LobbyManager>) -> SocketAddr {
+    let app = router(Arc::new(Config::default()), lm, Shutdown::new()).unwrap();
+
+    let server =
+        axum::Server::bind(&SocketAddr::from(([127, 0, 0, 1], 0))).serve(app.into_make_service());
+    let addr = server.local_addr();
+    tokio::spawn(server);
+
+    addr
+}
+
+pub struct Client {
+    pub user_id: String,
+    ws: WebSocketStream<MaybeTlsStream<TcpStream>>,
+    /// Every text message received, as sent
+    pub received: Vec<String>,
+}
+
+impl Client {
+    pub async fn join(addr: SocketAddr, lobby: &str, user_id: &str) -> Client {
+        let (ws, _) = connect_async(format!("ws://{}/lobby/{}/ws", addr, lobby))
+            .await
+            .unwrap();
+        let mut client = Client {
+            user_id: user_id.to_owned(),
+            ws,
+            received: Vec::new(),
+        };
+        client
+            .send(json!({ "msgType": "join", "data": { "userId": user_id } }))
+            .await;
+        client
+    }
+
+    pub async fn send(&mut self, msg: Value) {
+        self.ws.send(Message::Text(msg.to_string())).await.unwrap();
+    }
+
+    pub async fn game_move(&mut self, action: &str, data: Value) {
+        self.send(json!({
+            "msgType": "gameMove",
+            "data": { "actionType": action, "data": data },
+        }))
+        .await;
+    }
+
+    pub async fn recv(&mut self) -> Value {
+        loop {
+            let msg = timeout(Duration::from_secs(5), self.ws.next())
+                .await
+                .unwrap_or_else(|_| panic!("{} timed out waiting for a message", self.user_id))
+                .unwrap()
+                .unwrap();
+
+            if let Message::Text(txt) = msg {
+                self.received.push(txt.clone());
+                return serde_json::from_str(&txt).unwrap_or(Value::String(txt));
+            }
+        }
+    }
+
+    /// Skip messages until the server closes the socket
+    pub async fn wait_closed(&mut self) {
+        loop {
+            let msg = timeout(Duration::from_secs(5), self.ws.next())
+                .await
+                .unwrap_or_else(|_| panic!("{} timed out waiting to be closed", self.user_id));
+
+            match msg {
+                None | Some(Ok(Message::Close(_))) | Some(Err(_)) => return,
+                Some(Ok(_)) => {}
+            }
+        }
+    }
+
+    /// Skip messages until one of `msg_type` matching `pred` arrives
+    pub async fn wait_for(&mut self, msg_type: &str, pred: impl Fn(&Value) -> bool) -> Value {
+        loop {
+            let msg = self.recv().await;
+            if msg["msgType"] == msg_type && pred(&msg["data"]) {
+                return msg["data"].clone();
+            }
+        }
+    }
+}
+
+pub async fn lobby(addr: SocketAddr, lobby: &str, users: &[&str]) -> Vec<Client> {
+    let mut clients = Vec::new();
+    for user in users {
+        let mut c = Client::join(addr, lobby, user).await;
+        c.wait_for("members", |m| m.as_array().unwrap().contains(&json!(user)))
+            .await;
+        clients.push(c);
+    }
+    clients
+}
@@ -0,0 +1,139 @@
+//! Black box tests of Just One, played through websockets
+
+mod common;
+
+use serde_json::{json, Value};
+
+use common::{lobby, start_server, Client, WORD};
+
+/// Wait for the current round to reach `state`
+async fn wait_for_round(c: &mut Client, state: &str) -> Value {
+    c.wait_for("gameState", |g| current_round(g)["curState"] == state)
+        .await
+}
+
+fn current_round(game: &Value) -> &Value {
+    let rounds = game["rounds"].as_array().unwrap();
+    &rounds[rounds.len() - 1]
+}
+
+/// Start a game and split the players into the guesser and hint givers
+async fn start_game(mut clients: Vec<Client>) -> (Client, Vec<Client>) {
+    clients[0].send(json!({ "msgType": "start" })).await;
+
+    let mut guesser = None;
+    for c in clients.iter_mut() {
+        let game = wait_for_round(c, "GivingHints").await;
+        guesser = current_round(&game)["guesser"].as_str().map(String::from);
+    }
+
+    let guesser = guesser.unwrap();
+    let (guessers, givers): (Vec<_>, Vec<_>) =
+        clients.into_iter().partition(|c| c.user_id == guesser);
+    (guessers.into_iter().next().unwrap(), givers)
+}
+
+#[tokio::test]
+async fn joining_lists_members_game_and_settings() {
+    let addr = start_server().await;
+    let mut alice = Client::join(addr, "join", "alice").await;
+
+    assert_eq!(alice.recv().await["msgType"], "members");
+    assert_eq!(alice.recv().await["msgType"], "selectedGame");
+    assert_eq!(alice.recv().await["msgType"], "settings");
+
+    let mut bob = Client::join(addr, "join", "bob").await;
+    bob.wait_for("members", |_| true).await;
+    let members = alice.wait_for("members", |_| true).await;
+    let mut members: Vec<&str> = members
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|m| m.as_str().unwrap())
+        .collect();
+    members.sort();
+    assert_eq!(members, ["alice", "bob"]);
+}
+
+#[tokio::test]
+async fn moves_are_refused_before_the_game_starts() {
+    let addr = start_server().await;
+    let mut alice = Client::join(addr, "early", "alice").await;
+
+    alice.game_move("hint", json!("fruit")).await;
+    let err = alice.wait_for("error", |_| true).await;
+    assert!(err["msg"].as_str().unwrap().contains("lobby"));
+}
+
+#[tokio::test]
+async fn invalid_moves_are_reported_to_the_sender() {
+    let addr = start_server().await;
+    let clients = lobby(addr, "invalid", &["alice", "bob", "carol"]).await;
+    let (mut guesser, _) = start_game(clients).await;
+
+    guesser.game_move("hint", json!("fruit")).await;
+    let err = guesser.wait_for("error", |_| true).await;
+    assert!(err["msg"].as_str().unwrap().contains("NotYourTurn"));
+}
+
+#[tokio::test]
+async fn full_game_keeps_secrets_from_the_guesser() {
+    let addr = start_server().await;
+    let clients = lobby(addr, "full", &["alice", "bob", "carol", "dave"]).await;
+    let (mut guesser, mut givers) = start_game(clients).await;
+
+    // Two hints are duplicates of each other, one is unique
+    givers[0].game_move("hint", json!("Fruit")).await;
+    givers[1].game_move("hint", json!("fruits")).await;
+    givers[2].game_move("hint", json!("Orchard")).await;
+
+    let duplicate = givers[0].user_id.clone();
+    for g in givers.iter_mut() {
+        let game = wait_for_round(g, "RemovingDuplicates").await;
+        let round = current_round(&game);
+        assert_eq!(round["word"], WORD);
+        assert_eq!(round["hints"][&duplicate]["duplicate"], true);
+    }
+    wait_for_round(&mut guesser, "RemovingDuplicates").await;
+
+    givers[0].game_move("revealHints", Value::Null).await;
+    let game = wait_for_round(&mut guesser, "Guessing").await;
+    let hints = &current_round(&game)["hints"];
+    let mut revealed: Vec<&str> = hints
+        .as_object()
+        .unwrap()
+        .values()
+        .filter_map(|h| h["val"].as_str())
+        .filter(|v| !v.is_empty())
+        .collect();
+    revealed.sort();
+    assert_eq!(revealed, ["Orchard"]);
+
+    guesser.game_move("guess", json!("pear")).await;
+    let game = guesser
+        .wait_for("gameState", |g| {
+            current_round(g)["guesses"].as_array().unwrap().len() == 1
+        })
+        .await;
+    assert_eq!(current_round(&game)["word"], "");
+
+    guesser.game_move("guess", json!("apple")).await;
+    let game = wait_for_round(&mut guesser, "RoundFinished").await;
+    assert_eq!(current_round(&game)["word"], WORD);
+
+    // Nothing the guesser received before the round ended gave away the
+    // word or the hints that were removed as duplicates
+    let (_, before) = guesser.received.split_last().unwrap();
+    for msg in before {
+        let msg = msg.to_lowercase();
+        assert!(!msg.contains("apple"), "{}", msg);
+        assert!(!msg.contains("fruit"), "{}", msg);
+    }
+
+    for g in givers.iter_mut() {
+        wait_for_round(g, "RoundFinished").await;
+    }
+    givers[0].game_move("nextRound", Value::Null).await;
+    let game = guesser.wait_for("gameState", |g| g["round"] == 2).await;
+    assert_eq!(current_round(&game)["curState"], "GivingHints");
+}
@@ -0,0 +1,154 @@
+//! Black box tests of lobbies moving between games and being closed, played
+//! through websockets
+
+mod common;
+
+use serde_json::{json, Value};
+use std::net::SocketAddr;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+
+use common::{lobby, manager, serve, start_server, Client};
+
+/// Read a counter from the metrics endpoint, e.g.
+/// `gamesite_games_finished_total{game="tic_tac_toe"}`
+async fn metric(addr: SocketAddr, name: &str) -> u64 {
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream
+        .write_all(b"GET /metrics HTTP/1.0\r\n\r\n")
+        .await
+        .unwrap();
+    let mut res = String::new();
+    stream.read_to_string(&mut res).await.unwrap();
+
+    res.lines()
+        .find_map(|l| l.strip_prefix(name)?.trim().parse().ok())
+        .unwrap_or(0)
+}
+
+/// Choose the next game and wait for everyone to hear about it
+async fn select_game(clients: &mut [Client], game: &str) {
+    clients[0]
+        .send(json!({ "msgType": "selectGame", "data": game }))
+        .await;
+    for c in clients.iter_mut() {
+        c.wait_for("selectedGame", |g| g == game).await;
+    }
+}
+
+/// Number of cells claimed on a grid game's board
+fn claimed(game: &Value) -> usize {
+    game["cells"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter(|c| !c.is_null())
+        .count()
+}
+
+/// Take turns making `moves` on a grid game that has just started, sending
+/// each one from whoever's turn it is. Returns the last game state.
+async fn play_grid(clients: &mut [Client], action: &str, moves: &[Value]) -> Value {
+    let mut game = clients[0].wait_for("gameState", |g| claimed(g) == 0).await;
+    for (n, m) in moves.iter().enumerate() {
+        let turn = game["turn"].as_u64().unwrap() as usize;
+        let player = game["players"][turn].as_str().unwrap().to_owned();
+        let c = clients.iter_mut().find(|c| c.user_id == player).unwrap();
+        c.game_move(action, m.clone()).await;
+        game = c.wait_for("gameState", |g| claimed(g) == n + 1).await;
+    }
+    game
+}
+
+/// Play Tic-Tac-Toe until whoever went first wins along the top row
+async fn play_tic_tac_toe(clients: &mut [Client]) -> Value {
+    let moves = [(0, 0), (1, 0), (0, 1), (1, 1), (0, 2)]
+        .map(|(row, col)| json!({ "row": row, "col": col }));
+    play_grid(clients, "mark", &moves).await
+}
+
+#[tokio::test]
+async fn a_finished_game_can_be_played_again() {
+    let addr = start_server().await;
+    let mut clients = lobby(addr, "rematch", &["alice", "bob"]).await;
+    select_game(&mut clients, "TicTacToe").await;
+
+    for _ in 0..2 {
+        clients[0].send(json!({ "msgType": "start" })).await;
+        let game = play_tic_tac_toe(&mut clients).await;
+        assert_eq!(game["phase"], "GameOver");
+        assert_eq!(game["winner"], game["turn"]);
+    }
+
+    // Counted as soon as they end, while the lobby is still open
+    let finished = metric(addr, r#"gamesite_games_finished_total{game="tic_tac_toe"}"#).await;
+    assert!(finished >= 2, "{}", finished);
+
+    // Back in the lobby, so settings can be changed again
+    select_game(&mut clients, "JustOne").await;
+    assert!(!clients[0].received.iter().any(|m| m.contains("error")));
+}
+
+#[tokio::test]
+async fn the_next_game_can_be_chosen_once_one_is_over() {
+    let addr = start_server().await;
+    let mut clients = lobby(addr, "switch", &["alice", "bob", "carol"]).await;
+
+    // Whoever goes first fills the left column
+    select_game(&mut clients, "ConnectFour").await;
+    clients[0].send(json!({ "msgType": "start" })).await;
+    let moves = [0, 1, 0, 1, 0, 1, 0].map(|col| json!(col));
+    let game = play_grid(&mut clients, "drop", &moves).await;
+    assert_eq!(game["phase"], "GameOver");
+    assert_eq!(game["winner"], game["turn"]);
+    assert_eq!(game["rows"], 6);
+
+    select_game(&mut clients, "TicTacToe").await;
+    clients[0].send(json!({ "msgType": "start" })).await;
+    let game = play_tic_tac_toe(&mut clients).await;
+    assert_eq!(game["phase"], "GameOver");
+    assert_eq!(game["rows"], 3);
+
+    // The spectator watched both games to the end
+    let spectator = game["spectators"][0].as_str().unwrap();
+    let c = clients.iter_mut().find(|c| c.user_id == spectator).unwrap();
+    c.wait_for("gameState", |g| g["rows"] == 3 && g["phase"] == "GameOver")
+        .await;
+    assert!(!c.received.iter().any(|m| m.contains("error")));
+}
+
+#[tokio::test]
+async fn closing_a_busy_lobby_disconnects_everyone() {
+    let lm = manager();
+    let addr = serve(lm.clone()).await;
+    let mut clients = lobby(addr, "busy", &["alice", "bob"]).await;
+
+    // More announcements than the lobby's control channel holds
+    for _ in 0..20 {
+        lm.announce("Hello");
+    }
+    lm.close("busy").unwrap();
+
+    for c in clients.iter_mut() {
+        c.wait_closed().await;
+    }
+}
+
+#[tokio::test]
+async fn a_busy_lobby_still_hears_about_shutdown() {
+    let lm = manager();
+    let addr = serve(lm.clone()).await;
+    let mut clients = lobby(addr, "shutdown", &["alice", "bob"]).await;
+
+    for _ in 0..20 {
+        lm.announce("Hello");
+    }
+    lm.shutdown();
+
+    for c in clients.iter_mut() {
+        c.wait_for("serverShuttingDown", |_| true).await;
+        c.wait_closed().await;
+    }
+}